@@ -0,0 +1,7 @@
+fn main() {
+    let args = std::env::args().skip(1).collect();
+    if let Err(error) = velo::cli::run(args) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
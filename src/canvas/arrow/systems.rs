@@ -5,8 +5,9 @@ use super::components::{ArrowConnect, ArrowMeta};
 // use crate::states::{AppState, MainCamera, RedrawArrow};
 use super::events::{CreateArrow, RedrawArrow};
 use super::utils::{build_arrow, create_arrow, get_pos};
-use crate::chart_plugin::UiState;
+use crate::chart_plugin::{Operation, UiState, UndoHistory};
 use crate::components::MainCamera;
+use crate::resources::AppState;
 use bevy_prototype_lyon::prelude::Path;
 
 pub fn create_arrow_start(
@@ -17,6 +18,8 @@ pub fn create_arrow_start(
     mut state: ResMut<UiState>,
     mut create_arrow: EventWriter<CreateArrow>,
     mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    app_state: Res<AppState>,
+    mut history: ResMut<UndoHistory>,
 ) {
     let mut primary_window = windows.single_mut();
     for (interaction, arrow_connect) in interaction_query.iter_mut() {
@@ -27,10 +30,16 @@ pub fn create_arrow_start(
                         continue;
                     }
                     state.arrow_to_draw_start = None;
-                    create_arrow.send(CreateArrow {
+                    let arrow = ArrowMeta {
                         start: start_arrow,
                         end: *arrow_connect,
                         arrow_type: state.arrow_type,
+                    };
+                    history.record(&app_state, Operation::AddArrow { arrow });
+                    create_arrow.send(CreateArrow {
+                        start: arrow.start,
+                        end: arrow.end,
+                        arrow_type: arrow.arrow_type,
                     });
                 }
                 None => {
@@ -35,7 +35,7 @@ fn parallel_arrow_mid(start: Vec2, end: Vec2, arrow_meta: ArrowMeta) -> (Vec2, V
         (_, _) => (mid, mid),
     }
 }
-fn arrow_head(point: Vec2, pos: ArrowConnectPos) -> shapes::Polygon {
+fn arrow_head(point: Vec2, pos: ArrowConnectPos) -> Vec<Vec2> {
     let headlen: f32 = 10.0;
     use ArrowConnectPos::*;
     let angle = match pos {
@@ -44,97 +44,78 @@ fn arrow_head(point: Vec2, pos: ArrowConnectPos) -> shapes::Polygon {
         Right => 0.,
         Left => PI,
     };
-    let points = vec![
+    vec![
         point + Vec2::from_angle(angle - PI / 6.) * headlen,
         point,
         point + Vec2::from_angle(angle + PI / 6.) * headlen,
-    ];
-    shapes::Polygon {
-        points,
-        closed: false,
-    }
+    ]
 }
-pub fn build_arrow(start: Vec2, end: Vec2, arrow_meta: ArrowMeta) -> Path {
+
+/// Open polylines an arrow is drawn with, shared by the canvas and exports.
+pub fn arrow_lines(start: Vec2, end: Vec2, arrow_meta: ArrowMeta) -> Vec<Vec<Vec2>> {
+    let headlen = 10.0;
+    let angle = (end.y - start.y).atan2(end.x - start.x);
     match arrow_meta.arrow_type {
-        ArrowType::Line => {
-            let main = shapes::Line(start, end);
-            GeometryBuilder::build_as(&main)
-        }
-        ArrowType::Arrow => {
-            let dt = end.x - start.x;
-            let dy = end.y - start.y;
-            let angle = dy.atan2(dt);
-            let headlen = 10.0;
-            GeometryBuilder::new()
-                .add(&shapes::Line(start, end))
-                .add(&shapes::Line(
-                    end,
-                    end - headlen * Vec2::from_angle(angle + PI / 6.),
-                ))
-                .add(&shapes::Line(
-                    end,
-                    end - headlen * Vec2::from_angle(angle - PI / 6.),
-                ))
-                .build()
-        }
-        ArrowType::DoubleArrow => {
-            let headlen = 10.0;
-            let dt = end.x - start.x;
-            let dy = end.y - start.y;
-            let angle = dy.atan2(dt);
-            GeometryBuilder::new()
-                .add(&shapes::Line(
-                    start,
-                    start + headlen * Vec2::from_angle(angle + PI / 6.),
-                ))
-                .add(&shapes::Line(
-                    start,
-                    start + headlen * Vec2::from_angle(angle - PI / 6.),
-                ))
-                .add(&shapes::Line(start, end))
-                .add(&shapes::Line(
-                    end,
-                    end - headlen * Vec2::from_angle(angle + PI / 6.),
-                ))
-                .add(&shapes::Line(
-                    end,
-                    end - headlen * Vec2::from_angle(angle - PI / 6.),
-                ))
-                .build()
-        }
+        ArrowType::Line => vec![vec![start, end]],
+        ArrowType::Arrow => vec![
+            vec![start, end],
+            vec![end, end - headlen * Vec2::from_angle(angle + PI / 6.)],
+            vec![end, end - headlen * Vec2::from_angle(angle - PI / 6.)],
+        ],
+        ArrowType::DoubleArrow => vec![
+            vec![start, start + headlen * Vec2::from_angle(angle + PI / 6.)],
+            vec![start, start + headlen * Vec2::from_angle(angle - PI / 6.)],
+            vec![start, end],
+            vec![end, end - headlen * Vec2::from_angle(angle + PI / 6.)],
+            vec![end, end - headlen * Vec2::from_angle(angle - PI / 6.)],
+        ],
         ArrowType::ParallelLine => {
             let mid_point = parallel_arrow_mid(start, end, arrow_meta);
-            GeometryBuilder::new()
-                .add(&shapes::Line(start, mid_point.0))
-                .add(&shapes::Line(mid_point.0, mid_point.1))
-                .add(&shapes::Line(mid_point.1, end))
-                .build()
+            vec![
+                vec![start, mid_point.0],
+                vec![mid_point.0, mid_point.1],
+                vec![mid_point.1, end],
+            ]
         }
         ArrowType::ParallelArrow => {
             let head_pos = arrow_meta.end.pos;
             let mid_point = parallel_arrow_mid(start, end, arrow_meta);
-            GeometryBuilder::new()
-                .add(&shapes::Line(start, mid_point.0))
-                .add(&shapes::Line(mid_point.0, mid_point.1))
-                .add(&shapes::Line(mid_point.1, end))
-                .add(&arrow_head(end, head_pos))
-                .build()
+            vec![
+                vec![start, mid_point.0],
+                vec![mid_point.0, mid_point.1],
+                vec![mid_point.1, end],
+                arrow_head(end, head_pos),
+            ]
         }
         ArrowType::ParallelDoubleArrow => {
             let head_pos = arrow_meta.end.pos;
             let tail_pos = arrow_meta.start.pos;
             let mid_point = parallel_arrow_mid(start, end, arrow_meta);
-            GeometryBuilder::new()
-                .add(&arrow_head(start, tail_pos))
-                .add(&shapes::Line(start, mid_point.0))
-                .add(&shapes::Line(mid_point.0, mid_point.1))
-                .add(&shapes::Line(mid_point.1, end))
-                .add(&arrow_head(end, head_pos))
-                .build()
+            vec![
+                arrow_head(start, tail_pos),
+                vec![start, mid_point.0],
+                vec![mid_point.0, mid_point.1],
+                vec![mid_point.1, end],
+                arrow_head(end, head_pos),
+            ]
         }
     }
 }
 
+pub fn build_arrow(start: Vec2, end: Vec2, arrow_meta: ArrowMeta) -> Path {
+    let mut builder = GeometryBuilder::new();
+    for points in arrow_lines(start, end, arrow_meta) {
+        builder = match points[..] {
+            [from, to] => builder.add(&shapes::Line(from, to)),
+            _ => builder.add(&shapes::Polygon {
+                points,
+                closed: false,
+            }),
+        };
+    }
+    builder.build()
+}
+
 pub fn get_pos(
     global_transform: &GlobalTransform,
     primary_window: &Window,
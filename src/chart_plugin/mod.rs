@@ -1,15 +1,15 @@
-use bevy::{
-    prelude::*,
-    text::BreakLineOn,
-    window::{PrimaryWindow, WindowResized},
-};
+use bevy::{prelude::*, text::BreakLineOn, ui::UiSystem, window::PrimaryWindow};
 use serde::{Deserialize, Serialize};
 
 use crate::canvas::arrow::components::{ArrowConnect, ArrowConnectPos, ArrowType};
 use crate::canvas::arrow::events::{CreateArrow, RedrawArrow};
+use crate::errors::VeloError;
 use crate::resources::AppState;
-use crate::resources::LoadRequest;
+use crate::resources::CanvasView;
+use crate::resources::PopulateRequest;
+use crate::resources::Settings;
 use crate::resources::StaticState;
+use crate::storage::migrate_store;
 use crate::utils::ReflectableUuid;
 use std::time::Duration;
 use uuid::Uuid;
@@ -43,6 +43,27 @@ use tabs::*;
 #[path = "systems/doc_list.rs"]
 mod doc_list;
 use doc_list::*;
+#[path = "systems/import_export.rs"]
+mod import_export;
+use import_export::*;
+#[path = "systems/undo.rs"]
+mod undo;
+pub use undo::*;
+#[path = "systems/autosave.rs"]
+mod autosave;
+pub use autosave::*;
+#[path = "systems/error_notice.rs"]
+mod error_notice;
+use error_notice::*;
+#[path = "systems/watch.rs"]
+mod watch;
+use watch::*;
+#[path = "systems/canvas.rs"]
+mod canvas;
+pub use canvas::*;
+#[path = "systems/minimap.rs"]
+mod minimap;
+pub use minimap::*;
 
 pub struct ChartPlugin;
 
@@ -53,6 +74,11 @@ pub struct AddRect {
 
 pub struct HighlightEvent;
 
+pub struct ErrorEvent(pub VeloError);
+
+/// Shown like errors, for things the user should know about that didn't fail.
+pub struct InfoEvent(pub String);
+
 #[derive(Serialize, Deserialize)]
 pub enum NodeType {
     Rect,
@@ -77,8 +103,8 @@ pub struct JsonNodeText {
 pub struct JsonNode {
     pub id: Uuid,
     pub node_type: NodeType,
-    pub left: Val,
-    pub bottom: Val,
+    pub x: f32, // World position of the left side
+    pub y: f32, // World position of the bottom side, grows upwards
     pub width: Val,
     pub height: Val,
     pub text: JsonNodeText,
@@ -111,6 +137,10 @@ impl Plugin for ChartPlugin {
         app.init_resource::<UiState>();
         app.init_resource::<StaticState>();
         app.init_resource::<AppState>();
+        app.init_resource::<UndoHistory>();
+        app.init_resource::<Settings>();
+        app.init_resource::<ChangeTracker>();
+        app.init_resource::<CanvasView>();
 
         app.register_type::<VeloNode>();
         app.register_type::<EditableText>();
@@ -127,7 +157,11 @@ impl Plugin for ChartPlugin {
         app.add_event::<CreateArrow>();
         app.add_event::<RedrawArrow>();
         app.add_event::<HighlightEvent>();
+        app.add_event::<HistoryEvent>();
+        app.add_event::<ErrorEvent>();
+        app.add_event::<InfoEvent>();
 
+        app.add_startup_system(migrate_store.pipe(report_error).before(init_layout));
         app.add_startup_system(init_layout);
 
         app.add_systems((
@@ -140,21 +174,51 @@ impl Plugin for ChartPlugin {
             cancel_modal,
             modal_keyboard_input_system,
             confirm_modal,
-            resize_notificator,
+            pan_canvas
+                .after(set_focused_entity)
+                .before(update_rectangle_position),
+            zoom_canvas.before(apply_canvas_view),
+            apply_canvas_view.after(pan_canvas),
+            remember_tab_view.after(apply_canvas_view),
+        ));
+
+        app.add_systems((
+            navigate_minimap.before(apply_canvas_view),
+            update_minimap.after(apply_canvas_view),
+            draw_grid.after(apply_canvas_view),
         ));
 
+        app.add_system(
+            scale_nodes
+                .in_base_set(CoreSet::PostUpdate)
+                .before(UiSystem::Flex),
+        );
+
         app.add_systems(
-            (save_json, remove_save_request)
+            (save_json.pipe(report_error), remove_save_request)
                 .chain()
                 .distributive_run_if(should_save),
         );
 
         app.add_systems(
-            (load_json, remove_load_request)
+            (export_file.pipe(report_error), remove_export_request)
+                .chain()
+                .after(remove_save_request)
+                .distributive_run_if(should_export),
+        );
+
+        app.add_systems(
+            (load_json.pipe(report_error), remove_load_request)
                 .chain()
                 .distributive_run_if(should_load),
         );
 
+        app.add_system(
+            populate_tab
+                .before(create_new_rectangle)
+                .run_if(should_populate),
+        );
+
         app.add_systems((
             change_color_pallete,
             change_arrow_type,
@@ -169,6 +233,7 @@ impl Plugin for ChartPlugin {
             rename_doc_handler,
             delete_doc_handler,
             save_doc_handler,
+            backup_all_handler,
             keyboard_input_system,
         ));
 
@@ -176,7 +241,20 @@ impl Plugin for ChartPlugin {
             button_generic_handler,
             selected_tab_handler,
             higlight_event_handler,
+            import_file,
+            undo_keyboard_input,
+            record_edit_operations.before(undo_redo),
+            undo_redo,
+            detect_changes.before(autosave),
+            autosave,
+            update_unsaved_indicator,
+            evict_documents,
+            show_error_notice,
+            hide_error_notice,
+            watch_store,
         ));
+
+        app.add_system(autosave_on_exit.in_base_set(CoreSet::Last));
     }
 }
 
@@ -246,23 +324,34 @@ fn set_focused_entity(
 
 fn update_rectangle_position(
     mut cursor_moved_events: EventReader<CursorMoved>,
-    mut node_position: Query<(&mut Style, &VeloNodeContainer), With<VeloNodeContainer>>,
+    mut node_position: Query<(&mut NodeRect, &VeloNodeContainer), With<VeloNodeContainer>>,
     state: Res<UiState>,
-    mut query: Query<(&Style, &LeftPanel), Without<VeloNodeContainer>>,
+    main_panel: Query<(&Node, &GlobalTransform), With<MainPanel>>,
+    view: Res<CanvasView>,
+    settings: Res<Settings>,
+    keys: Res<Input<KeyCode>>,
     mut events: EventWriter<RedrawArrow>,
     windows: Query<&Window, With<PrimaryWindow>>,
 ) {
     let primary_window = windows.single();
+    let (panel, panel_transform) = main_panel.single();
+    let origin = panel_origin(panel, panel_transform, primary_window);
+    let snap = |value: f32| {
+        if keys.any_pressed([KeyCode::LAlt, KeyCode::RAlt]) {
+            value
+        } else {
+            settings.snap(value)
+        }
+    };
     for event in cursor_moved_events.iter() {
-        for (mut style, top) in &mut node_position.iter_mut() {
+        let position = view.to_world(event.position - origin);
+        for (mut rect, top) in &mut node_position.iter_mut() {
             if Some(top.id) == state.hold_entity {
-                let size = query.single_mut().0.size;
-                if let (Val::Percent(x), Val::Px(element_width)) = (size.width, style.size.width) {
-                    let width = (primary_window.width() * x) / 100.;
-                    style.position.left = Val::Px(event.position.x - width - element_width / 2.);
+                if let Val::Px(element_width) = rect.width {
+                    rect.left = Val::Px(snap(position.x - element_width / 2.));
                 }
-                if let Val::Px(element_height) = style.size.height {
-                    style.position.bottom = Val::Px(event.position.y - element_height / 2.);
+                if let Val::Px(element_height) = rect.height {
+                    rect.bottom = Val::Px(snap(position.y - element_height / 2.));
                 }
                 events.send(RedrawArrow { id: top.id });
             }
@@ -275,37 +364,38 @@ fn create_new_rectangle(
     mut events: EventReader<AddRect>,
     state: ResMut<StaticState>,
     mut ui_state: ResMut<UiState>,
+    app_state: Res<AppState>,
+    mut history: ResMut<UndoHistory>,
+    populate: Option<Res<PopulateRequest>>,
 ) {
     for event in events.iter() {
         let font = state.font.as_ref().unwrap().clone();
         *ui_state = UiState::default();
         ui_state.entity_to_edit = Some(ReflectableUuid(event.node.id));
-        let entity = spawn_node(
-            &mut commands,
-            NodeMeta {
-                font,
-                size: (event.node.width, event.node.height),
-                id: ReflectableUuid(event.node.id),
-                image: event.image.clone(),
-                text: event.node.text.text.clone(),
-                bg_color: event.node.bg_color,
-                position: (event.node.left, event.node.bottom),
-                text_pos: event.node.text.pos.clone(),
-                tags: event.node.tags.clone(),
-                z_index: event.node.z_index,
-            },
-        );
-        commands.entity(state.main_panel.unwrap()).add_child(entity);
-    }
-}
-
-fn resize_notificator(mut commands: Commands, resize_event: Res<Events<WindowResized>>) {
-    let mut reader = resize_event.get_reader();
-    for _ in reader.iter(&resize_event) {
-        commands.insert_resource(LoadRequest {
-            doc_id: None,
-            drop_last_checkpoint: false,
-        });
+        let node = NodeMeta {
+            font,
+            size: (event.node.width, event.node.height),
+            id: ReflectableUuid(event.node.id),
+            image: event.image.clone(),
+            text: event.node.text.text.clone(),
+            bg_color: event.node.bg_color,
+            position: (Val::Px(event.node.x), Val::Px(event.node.y)),
+            text_pos: event.node.text.pos.clone(),
+            tags: event.node.tags.clone(),
+            z_index: event.node.z_index,
+        };
+        // An import fills a new tab, one undo step per node would only empty it
+        if populate.is_none() {
+            history.record(
+                &app_state,
+                Operation::AddNode {
+                    node: node.clone(),
+                    arrows: vec![],
+                },
+            );
+        }
+        let entity = spawn_node(&mut commands, node);
+        commands.entity(state.canvas.unwrap()).add_child(entity);
     }
 }
 
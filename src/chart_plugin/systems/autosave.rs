@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use bevy::{app::AppExit, prelude::*};
+
+use super::ui_helpers::{DocListItemDirty, EditableText, NodeRect, VeloNode};
+use super::{node_text, save_json, ErrorEvent, VeloNodeContainer};
+use crate::canvas::arrow::components::ArrowMeta;
+use crate::get_timestamp;
+use crate::resources::{AppState, LoadRequest, SaveRequest, Settings};
+use crate::storage::Store;
+use crate::utils::ReflectableUuid;
+
+#[derive(Resource, Default)]
+pub struct ChangeTracker {
+    /// Frames to ignore after a load, while its nodes and arrows are spawned.
+    pub settle_frames: u8,
+    last_change: f64,
+    texts: HashMap<ReflectableUuid, String>,
+}
+
+pub fn detect_changes(
+    mut tracker: ResMut<ChangeTracker>,
+    mut app_state: ResMut<AppState>,
+    containers: Query<
+        (),
+        (
+            With<VeloNodeContainer>,
+            Or<(Changed<NodeRect>, Changed<ZIndex>)>,
+        ),
+    >,
+    nodes: Query<(), (With<VeloNode>, Changed<BackgroundColor>)>,
+    texts: Query<(&EditableText, &Text), Changed<Text>>,
+    arrows: Query<(), Changed<ArrowMeta>>,
+    mut removed_nodes: RemovedComponents<VeloNodeContainer>,
+    mut removed_arrows: RemovedComponents<ArrowMeta>,
+) {
+    // Removals are read every frame, unread ones would show up after a save
+    let removed = removed_nodes.iter().count() + removed_arrows.iter().count();
+    let mut changed =
+        !containers.is_empty() || !nodes.is_empty() || !arrows.is_empty() || removed > 0;
+    // Text is touched every frame by the cursor blink, compare the content
+    for (editable_text, text) in texts.iter() {
+        let text = node_text(text);
+        if tracker.texts.get(&editable_text.id) != Some(&text) {
+            tracker.texts.insert(editable_text.id, text);
+            changed = true;
+        }
+    }
+    if tracker.settle_frames > 0 {
+        tracker.settle_frames -= 1;
+        return;
+    }
+    if changed {
+        if let Some(doc_id) = app_state.current_document {
+            app_state.docs.mark_dirty(doc_id);
+            tracker.last_change = get_timestamp();
+        }
+    }
+}
+
+pub fn autosave(
+    mut commands: Commands,
+    tracker: Res<ChangeTracker>,
+    app_state: Res<AppState>,
+    settings: Res<Settings>,
+    save_request: Option<Res<SaveRequest>>,
+) {
+    if settings.autosave_interval_secs <= 0. || save_request.is_some() {
+        return;
+    }
+    let Some(doc_id) = app_state.current_document else {
+        return;
+    };
+    let idle_ms = get_timestamp() - tracker.last_change;
+    if app_state.docs.is_dirty(&doc_id)
+        && idle_ms >= (settings.autosave_interval_secs * 1000.) as f64
+    {
+        commands.insert_resource(SaveRequest {
+            doc_id: Some(doc_id),
+            tab_id: None,
+        });
+    }
+}
+
+/// Keeps the number of docs in memory bounded. The current doc is never
+/// evicted and evicted docs with unsaved changes are written to the store first.
+pub fn evict_documents(
+    mut app_state: ResMut<AppState>,
+    mut store: ResMut<Store>,
+    save_request: Option<Res<SaveRequest>>,
+    load_request: Option<Res<LoadRequest>>,
+    mut errors: EventWriter<ErrorEvent>,
+) {
+    // Pending requests may still point at a doc that is about to be evicted
+    if !app_state.is_changed() || save_request.is_some() || load_request.is_some() {
+        return;
+    }
+    let keep = app_state.current_document;
+    // Docs that failed to flush stay in memory, retry on the next change only
+    let app_state = app_state.bypass_change_detection();
+    for error in app_state.docs.evict(keep, |doc| store.save_doc(doc)) {
+        errors.send(ErrorEvent(error));
+    }
+}
+
+/// Writes every dirty doc to the store before the app exits. The regular save runs a
+/// frame after it's requested, which never comes once `AppExit` is sent.
+pub fn autosave_on_exit(world: &mut World) {
+    if world.resource::<Events<AppExit>>().is_empty() {
+        return;
+    }
+    let app_state = world.resource::<AppState>();
+    let current_document = app_state.current_document;
+    if let Some(doc_id) = current_document.filter(|x| app_state.docs.is_dirty(x)) {
+        world.insert_resource(SaveRequest {
+            doc_id: Some(doc_id),
+            tab_id: None,
+        });
+        let mut system = IntoSystem::into_system(save_json);
+        system.initialize(world);
+        // The window is gone, errors can only be logged
+        if let Err(error) = system.run((), world) {
+            error!("Failed to save document on exit: {}", error);
+        }
+        system.apply_buffers(world);
+        world.remove_resource::<SaveRequest>();
+    }
+    world.resource_scope(|world, mut store: Mut<Store>| {
+        let mut app_state = world.resource_mut::<AppState>();
+        let dirty_docs: Vec<_> = app_state.docs.dirty_docs().map(|doc| doc.id).collect();
+        for doc_id in dirty_docs {
+            match store.save_doc(app_state.docs.get(&doc_id).unwrap()) {
+                Ok(()) => app_state.docs.mark_clean(&doc_id),
+                Err(error) => error!("Failed to save document on exit: {}", error),
+            }
+        }
+    });
+}
+
+pub fn update_unsaved_indicator(
+    app_state: Res<AppState>,
+    mut indicators: Query<(&DocListItemDirty, &mut Visibility)>,
+) {
+    if !app_state.is_changed() {
+        return;
+    }
+    for (indicator, mut visibility) in indicators.iter_mut() {
+        let dirty = app_state.docs.is_dirty(&indicator.id);
+        *visibility = if dirty {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
@@ -5,17 +5,24 @@ use bevy::{prelude::*, window::PrimaryWindow};
 use serde_json::json;
 use uuid::Uuid;
 
-use crate::{get_timestamp, AddRect, JsonNode, JsonNodeText, NodeType, UiState};
+use crate::{
+    get_timestamp, node_text, AddRect, JsonNode, JsonNodeText, NodeType, Operation, UiState,
+    UndoHistory,
+};
 
 use super::ui_helpers::{
-    add_list_item, get_sections, pos_to_style, spawn_modal, ButtonAction, ChangeColor, DeleteDoc,
-    DocList, DocListItemButton, EditableText, GenericButton, ModalEntity, NewDoc, SaveDoc,
-    TextManipulation, TextManipulationAction, TextPosMode, Tooltip, VeloNode,
+    add_list_item, get_sections, pos_to_style, spawn_modal, style_to_pos, BackupAll, ButtonAction,
+    ChangeColor, DeleteDoc, DocList, DocListItemButton, EditableText, GenericButton, ModalEntity,
+    NewDoc, NodeMeta, NodeRect, SaveDoc, TextManipulation, TextManipulationAction, TextPosMode,
+    Tooltip, VeloNode,
 };
 use super::VeloNodeContainer;
 use crate::canvas::arrow::components::{ArrowMeta, ArrowMode};
 use crate::components::{Doc, Tab};
-use crate::resources::{AppState, LoadRequest, SaveRequest, StaticState};
+use crate::formats::velo::SCHEMA_VERSION;
+use crate::resources::{
+    AppState, CanvasView, ExportFormat, ExportRequest, LoadRequest, SaveRequest, StaticState,
+};
 use crate::utils::ReflectableUuid;
 
 pub fn rec_button_handlers(
@@ -25,22 +32,33 @@ pub fn rec_button_handlers(
         (&Interaction, &ButtonAction),
         (Changed<Interaction>, With<ButtonAction>),
     >,
-    mut nodes: Query<(Entity, &VeloNodeContainer, &mut ZIndex), With<VeloNodeContainer>>,
+    mut nodes: Query<(Entity, &VeloNodeContainer, &mut ZIndex, &NodeRect), With<VeloNodeContainer>>,
+    velo_nodes: Query<
+        (&VeloNode, &BackgroundColor, &UiImage, &Style, &ZIndex),
+        Without<VeloNodeContainer>,
+    >,
+    texts: Query<(&EditableText, &Text)>,
     mut arrows: Query<(Entity, &ArrowMeta, &mut Visibility), (With<ArrowMeta>, Without<Tooltip>)>,
     mut state: ResMut<UiState>,
     windows: Query<&Window, With<PrimaryWindow>>,
+    static_state: Res<StaticState>,
+    app_state: Res<AppState>,
+    mut history: ResMut<UndoHistory>,
+    view: Res<CanvasView>,
 ) {
     let window = windows.single();
     for (interaction, button_action) in &mut interaction_query {
         match *interaction {
             Interaction::Clicked => match button_action.button_type {
                 super::ui_helpers::ButtonTypes::Add => {
+                    let position =
+                        view.to_world(Vec2::new(window.width() / 2. - 200., window.height() / 2.));
                     events.send(AddRect {
                         node: JsonNode {
                             id: Uuid::new_v4(),
                             node_type: NodeType::Rect,
-                            left: Val::Px(window.width() / 2. - 200.),
-                            bottom: Val::Px(window.height() / 2.),
+                            x: position.x,
+                            y: position.y,
                             width: Val::Px(100.0),
                             height: Val::Px(100.0),
                             text: JsonNodeText {
@@ -57,8 +75,22 @@ pub fn rec_button_handlers(
                 super::ui_helpers::ButtonTypes::Del => {
                     if let Some(id) = state.entity_to_edit {
                         *state = UiState::default();
-                        for (entity, node, _) in nodes.iter() {
+                        for (entity, node, _, rect) in nodes.iter() {
                             if node.id == id {
+                                if let Some(node) =
+                                    node_snapshot(id, rect, &velo_nodes, &texts, &static_state)
+                                {
+                                    let arrows = arrows
+                                        .iter()
+                                        .filter(|(_, arrow, visibility)| {
+                                            (arrow.start.id == id || arrow.end.id == id)
+                                                && **visibility != Visibility::Hidden
+                                        })
+                                        .map(|(_, arrow, _)| *arrow)
+                                        .collect();
+                                    history
+                                        .record(&app_state, Operation::RemoveNode { node, arrows });
+                                }
                                 commands.entity(entity).despawn_recursive();
                             }
                         }
@@ -79,10 +111,18 @@ pub fn rec_button_handlers(
                 }
                 super::ui_helpers::ButtonTypes::Front => {
                     if let Some(id) = state.entity_to_edit {
-                        for (_, node, mut z_index) in nodes.iter_mut() {
+                        for (_, node, mut z_index, _) in nodes.iter_mut() {
                             if node.id == id {
                                 if let ZIndex::Local(i) = *z_index {
                                     *z_index = ZIndex::Local(i + 1);
+                                    history.record(
+                                        &app_state,
+                                        Operation::ZIndex {
+                                            id,
+                                            from: i,
+                                            to: i + 1,
+                                        },
+                                    );
                                 }
                             }
                         }
@@ -90,10 +130,18 @@ pub fn rec_button_handlers(
                 }
                 super::ui_helpers::ButtonTypes::Back => {
                     if let Some(id) = state.entity_to_edit {
-                        for (_, node, mut z_index) in nodes.iter_mut() {
+                        for (_, node, mut z_index, _) in nodes.iter_mut() {
                             if node.id == id {
                                 if let ZIndex::Local(i) = *z_index {
                                     *z_index = ZIndex::Local(i - 1);
+                                    history.record(
+                                        &app_state,
+                                        Operation::ZIndex {
+                                            id,
+                                            from: i,
+                                            to: i - 1,
+                                        },
+                                    );
                                 }
                             }
                         }
@@ -113,6 +161,8 @@ pub fn change_color_pallete(
     >,
     mut nodes: Query<(&mut BackgroundColor, &VeloNode), With<VeloNode>>,
     state: Res<UiState>,
+    app_state: Res<AppState>,
+    mut history: ResMut<UndoHistory>,
 ) {
     for (interaction, change_color) in &mut interaction_query {
         match *interaction {
@@ -121,6 +171,16 @@ pub fn change_color_pallete(
                 if state.entity_to_edit.is_some() {
                     for (mut bg_color, node) in nodes.iter_mut() {
                         if node.id == state.entity_to_edit.unwrap() {
+                            if bg_color.0 != color {
+                                history.record(
+                                    &app_state,
+                                    Operation::Recolor {
+                                        id: node.id,
+                                        from: bg_color.0,
+                                        to: color,
+                                    },
+                                );
+                            }
                             bg_color.0 = color;
                         }
                     }
@@ -309,6 +369,7 @@ pub fn new_doc_handler(
                 let mut checkpoints = VecDeque::new();
                 checkpoints.push_back(
                     json!({
+                        "version": SCHEMA_VERSION,
                         "nodes": [],
                         "arrows": [],
                         "images": {},
@@ -320,6 +381,7 @@ pub fn new_doc_handler(
                     name: "Tab 1".to_string(),
                     checkpoints,
                     is_active: true,
+                    view: Default::default(),
                 }];
                 app_state.docs.insert(
                     doc_id,
@@ -330,15 +392,14 @@ pub fn new_doc_handler(
                         tags: vec![],
                     },
                 );
+                // Not in the store yet, it must be flushed if evicted
+                app_state.docs.mark_dirty(doc_id);
                 commands.insert_resource(SaveRequest {
                     doc_id: Some(app_state.current_document.unwrap()),
                     tab_id: None,
                 });
                 app_state.current_document = Some(doc_id);
-                commands.insert_resource(LoadRequest {
-                    doc_id: None,
-                    drop_last_checkpoint: false,
-                });
+                commands.insert_resource(LoadRequest { doc_id: None });
                 let button = add_list_item(&mut commands, font.clone(), doc_id, name);
                 let doc_list = doc_list_query.single_mut();
                 commands.entity(doc_list).add_child(button);
@@ -425,6 +486,24 @@ pub fn save_doc_handler(
     }
 }
 
+pub fn backup_all_handler(
+    mut commands: Commands,
+    mut backup_all_query: Query<&Interaction, (Changed<Interaction>, With<BackupAll>)>,
+) {
+    for interaction in &mut backup_all_query.iter_mut() {
+        if let Interaction::Clicked = *interaction {
+            commands.insert_resource(SaveRequest {
+                doc_id: None,
+                tab_id: None,
+            });
+            commands.insert_resource(ExportRequest {
+                doc_id: None,
+                format: ExportFormat::Backup,
+            });
+        }
+    }
+}
+
 pub fn button_generic_handler(
     _commands: Commands,
     mut generic_button_query: Query<
@@ -459,3 +538,32 @@ pub fn button_generic_handler(
         }
     }
 }
+
+fn node_snapshot(
+    id: ReflectableUuid,
+    node_rect: &NodeRect,
+    velo_nodes: &Query<
+        (&VeloNode, &BackgroundColor, &UiImage, &Style, &ZIndex),
+        Without<VeloNodeContainer>,
+    >,
+    texts: &Query<(&EditableText, &Text)>,
+    static_state: &StaticState,
+) -> Option<NodeMeta> {
+    let (_, bg_color, image, style, z_index) = velo_nodes.iter().find(|x| x.0.id == id)?;
+    let (_, text) = texts.iter().find(|x| x.0.id == id)?;
+    Some(NodeMeta {
+        id,
+        size: (node_rect.width, node_rect.height),
+        position: (node_rect.left, node_rect.bottom),
+        text: node_text(text),
+        bg_color: bg_color.0,
+        font: static_state.font.as_ref().unwrap().clone(),
+        image: Some(image.clone()),
+        tags: vec![],
+        text_pos: style_to_pos((style.justify_content, style.align_items)),
+        z_index: match *z_index {
+            ZIndex::Local(v) => v,
+            _ => 0,
+        },
+    })
+}
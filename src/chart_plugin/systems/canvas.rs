@@ -0,0 +1,344 @@
+//! Panning and zooming of the canvas. Nodes are children of a canvas node
+//! inside `MainPanel` and keep their world rectangle in `NodeRect`, their
+//! styles are scaled from it. Arrows are drawn in world coordinates, the
+//! camera follows the view instead.
+use std::collections::HashMap;
+
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    window::PrimaryWindow,
+};
+use bevy_prototype_lyon::{
+    prelude::{Fill, GeometryBuilder, Path, Stroke},
+    shapes::{self, RectangleOrigin},
+};
+
+use super::ui_helpers::{
+    EditableText, MainPanel, NodeRect, ResizeMarker, VeloNodeContainer, ARROW_MARKER_SIZE,
+    RESIZE_MARKER_SIZE,
+};
+use crate::canvas::arrow::components::ArrowConnect;
+use crate::components::{Grid, MainCamera};
+use crate::formats::{FONT_SIZE, MARGIN, TEXT_PADDING};
+use crate::resources::{AppState, CanvasView, GridStyle, Settings, StaticState};
+use crate::systems::GRID_Z;
+use crate::UiState;
+
+/// Zoom change of one keyboard shortcut.
+const ZOOM_STEP: f32 = 1.25;
+const GRID_COLOR: Color = Color::rgb(0.8, 0.8, 0.8);
+/// Grid lines closer than this on screen are skipped, so zooming out doesn't
+/// fill the panel with them.
+const MIN_GRID_STEP: f32 = 8.;
+
+/// Bottom left corner of a UI node, like the main panel, in window
+/// coordinates, y grows upwards like cursor positions.
+pub fn panel_origin(panel: &Node, transform: &GlobalTransform, window: &Window) -> Vec2 {
+    let top_left = transform.translation().truncate() - panel.size() / 2.;
+    Vec2::new(top_left.x, window.height() - top_left.y - panel.size().y)
+}
+
+/// Pans while the middle button, or space and the left button, are held.
+pub fn pan_canvas(
+    mut cursor_moved_events: EventReader<CursorMoved>,
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    mut ui_state: ResMut<UiState>,
+    mut view: ResMut<CanvasView>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut space_held: Local<bool>,
+    mut last_cursor: Local<Option<Vec2>>,
+) {
+    // Space types into the node being edited, so it only pans when none is
+    if keys.just_pressed(KeyCode::Space)
+        && ui_state.entity_to_edit.is_none()
+        && ui_state.modal_id.is_none()
+    {
+        *space_held = true;
+    }
+    if !keys.pressed(KeyCode::Space) {
+        *space_held = false;
+    }
+    let panning =
+        buttons.pressed(MouseButton::Middle) || (*space_held && buttons.pressed(MouseButton::Left));
+    if *space_held {
+        // The left button drags the canvas, not the node under the cursor
+        if ui_state.entity_to_edit.is_some()
+            || ui_state.hold_entity.is_some()
+            || ui_state.entity_to_resize.is_some()
+        {
+            ui_state.entity_to_edit = None;
+            ui_state.hold_entity = None;
+            ui_state.entity_to_resize = None;
+        }
+    }
+    if *space_held || panning {
+        let mut window = windows.single_mut();
+        let icon = if panning {
+            CursorIcon::Grabbing
+        } else {
+            CursorIcon::Grab
+        };
+        if window.cursor.icon != icon {
+            window.cursor.icon = icon;
+        }
+    }
+
+    for event in cursor_moved_events.iter() {
+        if let (true, Some(last)) = (panning, *last_cursor) {
+            let zoom = view.zoom;
+            view.offset -= (event.position - last) / zoom;
+        }
+        *last_cursor = Some(event.position);
+    }
+}
+
+/// Moves the canvas node and the camera to the view. The camera follows the
+/// panel, so arrows are drawn in world coordinates as well and stay in place
+/// when the window is resized.
+pub fn apply_canvas_view(
+    view: Res<CanvasView>,
+    static_state: Res<StaticState>,
+    main_panel: Query<(Ref<Node>, Ref<GlobalTransform>), With<MainPanel>>,
+    mut styles: Query<&mut Style>,
+    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    let (panel, panel_transform) = main_panel.single();
+    if !view.is_changed() && !panel.is_changed() && !panel_transform.is_changed() {
+        return;
+    }
+    let mut style = styles.get_mut(static_state.canvas.unwrap()).unwrap();
+    style.position.left = Val::Px(-view.offset.x * view.zoom);
+    style.position.bottom = Val::Px(-view.offset.y * view.zoom);
+
+    let window = windows.single();
+    let center = Vec2::new(window.width(), window.height()) / 2.;
+    let origin = panel_origin(&panel, &panel_transform, window);
+    let (mut transform, mut projection) = camera.single_mut();
+    let z = transform.translation.z;
+    transform.translation = view.to_world(center - origin).extend(z);
+    projection.scale = 1. / view.zoom;
+}
+
+/// Zooms around the cursor with the mouse wheel over the canvas and with
+/// command + = and command + -. Command + 1 fits all nodes of the tab,
+/// command + 2 the node being edited.
+pub fn zoom_canvas(
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    input: Res<Input<KeyCode>>,
+    ui_state: Res<UiState>,
+    mut view: ResMut<CanvasView>,
+    main_panel: Query<(&Node, &GlobalTransform), With<MainPanel>>,
+    nodes: Query<(&VeloNodeContainer, &NodeRect)>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    let window = windows.single();
+    let (panel, panel_transform) = main_panel.single();
+    let origin = panel_origin(panel, panel_transform, window);
+    let cursor = window
+        .cursor_position()
+        .map(|cursor| cursor - origin)
+        .filter(|cursor| Rect::from_corners(Vec2::ZERO, panel.size()).contains(*cursor));
+    let mut zoom = view.zoom;
+    for event in mouse_wheel_events.iter() {
+        // The wheel scrolls the doc list everywhere else
+        if cursor.is_none() {
+            continue;
+        }
+        let lines = match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 20.,
+        };
+        zoom *= 1.1_f32.powf(lines);
+    }
+    let command = input.any_pressed([KeyCode::RWin, KeyCode::LWin]);
+    if command && input.any_just_pressed([KeyCode::Equals, KeyCode::Plus, KeyCode::NumpadAdd]) {
+        zoom *= ZOOM_STEP;
+    }
+    if command && input.any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]) {
+        zoom /= ZOOM_STEP;
+    }
+    if zoom != view.zoom {
+        view.zoom_around(zoom, cursor.unwrap_or(panel.size() / 2.));
+    }
+
+    let fit = if command && input.just_pressed(KeyCode::Key1) {
+        nodes
+            .iter()
+            .map(|(_, node)| node.rect())
+            .reduce(|a, b| a.union(b))
+    } else if command && input.just_pressed(KeyCode::Key2) {
+        nodes
+            .iter()
+            .find(|(container, _)| Some(container.id) == ui_state.entity_to_edit)
+            .map(|(_, node)| node.rect())
+    } else {
+        None
+    };
+    if let Some(fit) = fit {
+        view.fit(fit, panel.size(), MARGIN);
+    }
+}
+
+/// Scales the styles of nodes, their markers and text to the zoom.
+pub fn scale_nodes(
+    view: Res<CanvasView>,
+    mut containers: Query<(&VeloNodeContainer, &NodeRect, &mut Style)>,
+    mut markers: Query<
+        (&mut Style, Option<&ResizeMarker>),
+        (
+            Or<(With<ArrowConnect>, With<ResizeMarker>)>,
+            Without<VeloNodeContainer>,
+            Without<EditableText>,
+        ),
+    >,
+    mut texts: Query<
+        (&EditableText, &mut Text, &mut Style),
+        (
+            Without<VeloNodeContainer>,
+            Without<ArrowConnect>,
+            Without<ResizeMarker>,
+        ),
+    >,
+) {
+    let zoom = view.zoom;
+    let scale = |val: Val| match val {
+        Val::Px(x) => Val::Px(x * zoom),
+        val => val,
+    };
+    let mut sizes = HashMap::new();
+    for (container, rect, mut style) in containers.iter_mut() {
+        let position = UiRect {
+            left: scale(rect.left),
+            bottom: scale(rect.bottom),
+            ..default()
+        };
+        let size = Size::new(scale(rect.width), scale(rect.height));
+        if style.position != position || style.size != size {
+            style.position = position;
+            style.size = size;
+        }
+        sizes.insert(container.id, size);
+    }
+    for (mut style, resize_marker) in markers.iter_mut() {
+        let side = match resize_marker {
+            Some(_) => RESIZE_MARKER_SIZE,
+            None => ARROW_MARKER_SIZE,
+        };
+        let size = Size::all(Val::Px(side * zoom));
+        if style.size != size {
+            style.size = size;
+        }
+    }
+    for (editable_text, mut text, mut style) in texts.iter_mut() {
+        let font_size = FONT_SIZE * zoom;
+        if text.sections.iter().any(|x| x.style.font_size != font_size) {
+            for section in text.sections.iter_mut() {
+                section.style.font_size = font_size;
+            }
+        }
+        let padding = UiRect::all(Val::Px(TEXT_PADDING * zoom));
+        let max_size = sizes
+            .get(&editable_text.id)
+            .copied()
+            .unwrap_or(style.max_size);
+        if style.padding != padding || style.max_size != max_size {
+            style.padding = padding;
+            style.max_size = max_size;
+        }
+    }
+}
+
+/// Draws the grid over the visible part of the canvas. The path covers the
+/// panel and moves in whole steps while panning, it's only rebuilt when the
+/// zoom, the panel size or the settings change.
+pub fn draw_grid(
+    settings: Res<Settings>,
+    view: Res<CanvasView>,
+    main_panel: Query<&Node, With<MainPanel>>,
+    mut grid: Query<
+        (
+            &mut Path,
+            &mut Fill,
+            &mut Stroke,
+            &mut Transform,
+            &mut Visibility,
+        ),
+        With<Grid>,
+    >,
+    mut drawn: Local<Option<(GridStyle, f32, f32, UVec2)>>,
+) {
+    let (mut path, mut fill, mut stroke, mut transform, mut visibility) = grid.single_mut();
+    if settings.grid == GridStyle::Off || settings.grid_spacing <= 0. {
+        if *visibility != Visibility::Hidden {
+            *visibility = Visibility::Hidden;
+        }
+        *drawn = None;
+        return;
+    }
+    if *visibility != Visibility::Inherited {
+        *visibility = Visibility::Inherited;
+    }
+    let zoom = view.zoom;
+    let mut step = settings.grid_spacing;
+    while step * zoom < MIN_GRID_STEP {
+        step *= 2.;
+    }
+    let cells = (main_panel.single().size() / zoom / step).ceil().as_uvec2() + UVec2::ONE;
+    let key = (settings.grid, step, zoom, cells);
+    if *drawn != Some(key) {
+        *drawn = Some(key);
+        let extent = cells.as_vec2() * step;
+        let mut builder = GeometryBuilder::new();
+        if settings.grid == GridStyle::Lines {
+            for x in 0..=cells.x {
+                let x = x as f32 * step;
+                builder = builder.add(&shapes::Line(Vec2::new(x, 0.), Vec2::new(x, extent.y)));
+            }
+            for y in 0..=cells.y {
+                let y = y as f32 * step;
+                builder = builder.add(&shapes::Line(Vec2::new(0., y), Vec2::new(extent.x, y)));
+            }
+        } else {
+            // Squares are cheaper to tessellate than circles and look the same
+            for x in 0..=cells.x {
+                for y in 0..=cells.y {
+                    builder = builder.add(&shapes::Rectangle {
+                        extents: Vec2::splat(2. / zoom),
+                        origin: RectangleOrigin::CustomCenter(UVec2::new(x, y).as_vec2() * step),
+                    });
+                }
+            }
+        }
+        *path = builder.build();
+        let lines = settings.grid == GridStyle::Lines;
+        stroke.color = if lines { GRID_COLOR } else { Color::NONE };
+        // One pixel on screen at any zoom
+        stroke.options.line_width = 1. / zoom;
+        fill.color = if lines { Color::NONE } else { GRID_COLOR };
+    }
+    let translation = ((view.offset / step).floor() * step).extend(GRID_Z);
+    if transform.translation != translation {
+        transform.translation = translation;
+    }
+}
+
+/// Keeps the view of the active tab, so it's shown the same way when the
+/// tab is opened again.
+pub fn remember_tab_view(view: Res<CanvasView>, mut app_state: ResMut<AppState>) {
+    if !view.is_changed() {
+        return;
+    }
+    // Looking around is not an edit, it's saved with the next change
+    let app_state = app_state.bypass_change_detection();
+    let Some(doc_id) = app_state.current_document else {
+        return;
+    };
+    if let Some(doc) = app_state.docs.get_mut(&doc_id) {
+        if let Some(tab) = doc.tabs.iter_mut().find(|tab| tab.is_active) {
+            tab.view = *view;
+        }
+    }
+}
@@ -13,7 +13,7 @@ pub fn list_item_click(
         (&Interaction, &DocListItemButton),
         (Changed<Interaction>, With<DocListItemButton>),
     >,
-    mut state: ResMut<AppState>,
+    state: Res<AppState>,
     mut commands: Commands,
 ) {
     for (interaction, doc_list_item) in &mut interaction_query.iter_mut() {
@@ -24,10 +24,8 @@ pub fn list_item_click(
                         doc_id: Some(state.current_document.unwrap()),
                         tab_id: None,
                     });
-                    state.current_document = Some(doc_list_item.id);
                     commands.insert_resource(LoadRequest {
                         doc_id: Some(doc_list_item.id),
-                        drop_last_checkpoint: false,
                     });
                 }
             }
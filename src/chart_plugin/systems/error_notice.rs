@@ -0,0 +1,79 @@
+use bevy::prelude::*;
+
+use super::ui_helpers::ErrorNotice;
+use super::{ErrorEvent, InfoEvent};
+use crate::errors::VeloError;
+use crate::resources::StaticState;
+
+const NOTICE_SECONDS: f32 = 5.;
+
+pub fn report_error(In(result): In<Result<(), VeloError>>, mut errors: EventWriter<ErrorEvent>) {
+    if let Err(error) = result {
+        errors.send(ErrorEvent(error));
+    }
+}
+
+pub fn show_error_notice(
+    mut commands: Commands,
+    mut errors: EventReader<ErrorEvent>,
+    mut infos: EventReader<InfoEvent>,
+    static_state: Res<StaticState>,
+    notices: Query<&ErrorNotice>,
+) {
+    let mut count = notices.iter().count();
+    let errors = errors.iter().map(|ErrorEvent(error)| {
+        error!("{}", error);
+        (error.to_string(), Color::rgba(0.7, 0.1, 0.1, 0.9))
+    });
+    let infos = infos.iter().map(|InfoEvent(info)| {
+        info!("{}", info);
+        (info.clone(), Color::rgba(0.2, 0.3, 0.6, 0.9))
+    });
+    for (message, background) in errors.chain(infos) {
+        let Some(font) = static_state.font.clone() else {
+            continue;
+        };
+        commands.spawn((
+            TextBundle {
+                text: Text::from_section(
+                    message,
+                    TextStyle {
+                        font,
+                        font_size: 18.,
+                        color: Color::WHITE,
+                    },
+                ),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(10.),
+                        top: Val::Px(10. + 40. * count as f32),
+                        ..default()
+                    },
+                    max_size: Size::new(Val::Percent(40.), Val::Undefined),
+                    padding: UiRect::all(Val::Px(8.)),
+                    ..default()
+                },
+                background_color: background.into(),
+                z_index: ZIndex::Global(100),
+                ..default()
+            },
+            ErrorNotice {
+                timer: Timer::from_seconds(NOTICE_SECONDS, TimerMode::Once),
+            },
+        ));
+        count += 1;
+    }
+}
+
+pub fn hide_error_notice(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut notices: Query<(Entity, &mut ErrorNotice)>,
+) {
+    for (entity, mut notice) in notices.iter_mut() {
+        if notice.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
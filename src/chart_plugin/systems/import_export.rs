@@ -0,0 +1,369 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use super::reload_doc;
+use super::ui_helpers::{add_list_item, DocList, DocListItemText};
+use super::{get_timestamp, AddRect, ErrorEvent, InfoEvent, UiState, UndoHistory};
+use crate::canvas::arrow::events::CreateArrow;
+use crate::components::Tab;
+use crate::errors::VeloError;
+use crate::formats::csv::{is_edges_csv, parse_csv, CSV_FILE_EXTENSION};
+use crate::formats::dot::{checkpoint_to_dot, DOT_FILE_EXTENSIONS};
+use crate::formats::html::{doc_to_html, HTML_FILE_EXTENSION};
+use crate::formats::json_canvas::{checkpoint_to_canvas, CANVAS_FILE_EXTENSION};
+use crate::formats::markdown::{checkpoint_to_markdown, MARKDOWN_FILE_EXTENSION};
+use crate::formats::mermaid::{checkpoint_to_mermaid, MERMAID_FILE_EXTENSIONS};
+use crate::formats::opml::{checkpoint_to_opml, OPML_FILE_EXTENSION};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::formats::png::{checkpoint_to_png, PNG_FILE_EXTENSION};
+use crate::formats::svg::{checkpoint_to_svg, SVG_FILE_EXTENSION};
+use crate::formats::velo::{
+    checkpoint_to_string, latest_checkpoint, parse_velo_file, velo_file_to_string, Checkpoint,
+    VeloFile, VELO_FILE_EXTENSION,
+};
+use crate::formats::{file_name, tab_parser};
+use crate::resources::{
+    AppState, ExportFormat, ExportRequest, LoadRequest, PopulateRequest, SaveRequest, Settings,
+    StaticState,
+};
+use crate::storage::backup::{backup_store, restore_store, BACKUP_FILE_EXTENSION};
+use crate::storage::Store;
+use crate::utils::ReflectableUuid;
+use uuid::Uuid;
+
+pub fn should_export(request: Option<Res<ExportRequest>>) -> bool {
+    request.is_some()
+}
+
+pub fn remove_export_request(world: &mut World) {
+    world.remove_resource::<ExportRequest>().unwrap();
+}
+
+pub fn export_file(
+    request: Res<ExportRequest>,
+    app_state: Res<AppState>,
+    static_state: Res<StaticState>,
+    settings: Res<Settings>,
+    fonts: Res<Assets<Font>>,
+    store: Res<Store>,
+) -> Result<(), VeloError> {
+    let doc_id = request
+        .doc_id
+        .or(app_state.current_document)
+        .ok_or(VeloError::Export("no document is open".to_string()))?;
+    let doc = app_state
+        .docs
+        .get(&doc_id)
+        .ok_or(VeloError::DocNotFound(doc_id))?;
+    let get_image = |hash: &str| store.get_image(hash);
+    // Only formats of a single tab need it
+    let active_tab = || {
+        doc.tabs
+            .iter()
+            .find(|tab| tab.is_active)
+            .ok_or(VeloError::Export(
+                "the document has no active tab".to_string(),
+            ))
+    };
+    let (content, extension) = match request.format {
+        ExportFormat::Velo => {
+            let file = VeloFile::from_doc(doc, get_image)?;
+            (
+                velo_file_to_string(&file)?.into_bytes(),
+                VELO_FILE_EXTENSION,
+            )
+        }
+        ExportFormat::Backup => {
+            let backup = backup_store(&**store, app_state.docs.values())?;
+            (backup, BACKUP_FILE_EXTENSION)
+        }
+        ExportFormat::Html => {
+            let html = doc_to_html(doc, get_image)?;
+            (html.into_bytes(), HTML_FILE_EXTENSION)
+        }
+        ExportFormat::Mermaid => {
+            let checkpoint = latest_checkpoint(active_tab()?)?;
+            let mermaid = checkpoint_to_mermaid(&checkpoint);
+            (mermaid.into_bytes(), MERMAID_FILE_EXTENSIONS[0])
+        }
+        ExportFormat::Dot => {
+            let checkpoint = latest_checkpoint(active_tab()?)?;
+            let dot = checkpoint_to_dot(&checkpoint);
+            (dot.into_bytes(), DOT_FILE_EXTENSIONS[0])
+        }
+        ExportFormat::Canvas => {
+            let checkpoint = latest_checkpoint(active_tab()?)?;
+            let canvas = checkpoint_to_canvas(&checkpoint, get_image)?;
+            (canvas.into_bytes(), CANVAS_FILE_EXTENSION)
+        }
+        ExportFormat::Markdown => {
+            let checkpoint = latest_checkpoint(active_tab()?)?;
+            let markdown = checkpoint_to_markdown(&checkpoint);
+            // Ready to paste into tickets and notes
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Ok(mut clipboard) = arboard::Clipboard::new() {
+                let _ = clipboard.set_text(markdown.clone());
+            }
+            (markdown.into_bytes(), MARKDOWN_FILE_EXTENSION)
+        }
+        ExportFormat::Opml => {
+            let checkpoint = latest_checkpoint(active_tab()?)?;
+            let opml = checkpoint_to_opml(&checkpoint, &doc.name);
+            (opml.into_bytes(), OPML_FILE_EXTENSION)
+        }
+        ExportFormat::Svg => {
+            let checkpoint = latest_checkpoint(active_tab()?)?;
+            let svg = checkpoint_to_svg(&checkpoint, get_image);
+            (svg.into_bytes(), SVG_FILE_EXTENSION)
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        ExportFormat::Png => {
+            let checkpoint = latest_checkpoint(active_tab()?)?;
+            let font = static_state
+                .font
+                .as_ref()
+                .and_then(|font| fonts.get(font))
+                .ok_or(VeloError::Export("the font is not loaded yet".to_string()))?;
+            let png = checkpoint_to_png(&checkpoint, get_image, &font.font, settings.png_scale)?;
+            (png, PNG_FILE_EXTENSION)
+        }
+        #[cfg(target_arch = "wasm32")]
+        ExportFormat::Png => {
+            let _ = (static_state, settings, fonts);
+            return Ok(());
+        }
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let name = match request.format {
+            // Older backups are kept, the name sorts by time
+            ExportFormat::Backup => format!("velo-backup-{}", get_timestamp() as u64 / 1000),
+            _ => file_name(&doc.name),
+        };
+        let path = format!("{}.{}", name, extension);
+        std::fs::write(path, content).map_err(|error| VeloError::Export(error.to_string()))?;
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = (content, extension);
+    Ok(())
+}
+
+pub fn import_file(
+    mut commands: Commands,
+    mut events: EventReader<FileDragAndDrop>,
+    mut app_state: ResMut<AppState>,
+    static_state: Res<StaticState>,
+    mut doc_list_query: Query<Entity, With<DocList>>,
+    mut list_texts: Query<(&DocListItemText, &mut Text)>,
+    mut store: ResMut<Store>,
+    mut settings: ResMut<Settings>,
+    mut errors: EventWriter<ErrorEvent>,
+    mut infos: EventWriter<InfoEvent>,
+    mut history: ResMut<UndoHistory>,
+) {
+    // Nodes and edges tables dropped together become one tab
+    let mut tables = vec![];
+    for event in events.iter() {
+        let FileDragAndDrop::DroppedFile { path_buf, .. } = event else {
+            continue;
+        };
+        let Some(extension) = path_buf.extension().and_then(|x| x.to_str()) else {
+            continue;
+        };
+        let name = path_buf
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .unwrap_or_default()
+            .to_string();
+        if extension == VELO_FILE_EXTENSION {
+            let imported = std::fs::read_to_string(path_buf)
+                .map_err(|error| error.to_string())
+                .and_then(|json| {
+                    parse_velo_file(&json)
+                        .and_then(|file| file.into_doc())
+                        .map_err(|error| error.to_string())
+                });
+            let mut doc = match imported {
+                Ok(doc) => doc,
+                Err(error) => {
+                    errors.send(ErrorEvent(VeloError::Import(error)));
+                    continue;
+                }
+            };
+            let names = store.doc_names();
+            if app_state.docs.contains_key(&doc.id) || names.contains_key(&doc.id) {
+                doc.id = ReflectableUuid(Uuid::new_v4());
+            }
+            let font = static_state.font.as_ref().unwrap().clone();
+            let button = add_list_item(&mut commands, font, doc.id, doc.name.clone());
+            commands
+                .entity(doc_list_query.single_mut())
+                .add_child(button);
+            commands.insert_resource(SaveRequest {
+                doc_id: Some(app_state.current_document.unwrap()),
+                tab_id: None,
+            });
+            app_state.current_document = Some(doc.id);
+            // Tab ids are kept, so a doc imported again starts over
+            history.forget_doc(&doc);
+            let doc_id = doc.id;
+            app_state.docs.insert(doc_id, doc);
+            app_state.docs.mark_dirty(doc_id);
+            commands.insert_resource(LoadRequest { doc_id: None });
+        } else if extension == BACKUP_FILE_EXTENSION {
+            let strategy = settings.restore_strategy;
+            let restored = std::fs::read(path_buf)
+                .map_err(|error| VeloError::Import(error.to_string()))
+                .and_then(|archive| restore_store(&mut **store, &archive, strategy));
+            let summary = match restored {
+                Ok(summary) => summary,
+                Err(error) => {
+                    errors.send(ErrorEvent(error));
+                    continue;
+                }
+            };
+            let font = static_state.font.as_ref().unwrap().clone();
+            for (id, name) in summary.added.iter() {
+                let button = add_list_item(&mut commands, font.clone(), *id, name.clone());
+                commands
+                    .entity(doc_list_query.single_mut())
+                    .add_child(button);
+            }
+            for (id, name) in summary.replaced.iter() {
+                for (item, mut text) in list_texts.iter_mut() {
+                    if item.id == *id {
+                        text.sections[0].value = name.clone();
+                    }
+                }
+                reload_doc(&mut commands, &mut app_state, &mut history, &**store, *id);
+            }
+            if let Some(restored) = store.load_settings() {
+                *settings = restored;
+            }
+            infos.send(InfoEvent(format!("Restored {}, {}", name, summary)));
+        } else if extension.eq_ignore_ascii_case(CSV_FILE_EXTENSION) {
+            match std::fs::read_to_string(path_buf) {
+                Ok(source) => tables.push((name, source)),
+                Err(error) => errors.send(ErrorEvent(VeloError::Import(error.to_string()))),
+            }
+        } else if let Some(parse) = tab_parser(extension) {
+            let checkpoint = std::fs::read_to_string(path_buf)
+                .map_err(|error| VeloError::Import(error.to_string()))
+                .and_then(|source| parse(&source));
+            match checkpoint {
+                Ok((checkpoint, summary)) => {
+                    if !summary.is_empty() {
+                        infos.send(InfoEvent(format!("Imported {}, {}", name, summary)));
+                    }
+                    import_tab(&mut commands, &mut app_state, name, &checkpoint);
+                }
+                Err(error) => errors.send(ErrorEvent(error)),
+            }
+        }
+    }
+    if tables.is_empty() {
+        return;
+    }
+    let columns = &settings.csv_columns;
+    let (edges, nodes): (Vec<_>, Vec<_>) = tables
+        .iter()
+        .partition(|(_, source)| is_edges_csv(source, columns));
+    let (name, _) = nodes.first().or(edges.first()).unwrap();
+    let nodes = nodes.first().map(|(_, source)| source.as_str());
+    let edges = edges.first().map(|(_, source)| source.as_str());
+    match parse_csv(nodes, edges, columns) {
+        Ok((checkpoint, summary)) => {
+            if !summary.is_empty() {
+                infos.send(InfoEvent(format!("Imported {}, {}", name, summary)));
+            }
+            import_tab(
+                &mut commands,
+                &mut app_state,
+                name.clone(),
+                &Checkpoint::default(),
+            );
+            commands.insert_resource(PopulateRequest {
+                checkpoint,
+                frame: 0,
+            });
+        }
+        Err(error) => errors.send(ErrorEvent(error)),
+    }
+}
+
+pub fn should_populate(
+    request: Option<Res<PopulateRequest>>,
+    load_request: Option<Res<LoadRequest>>,
+) -> bool {
+    request.is_some() && load_request.is_none()
+}
+
+/// Fills the active tab with nodes first and arrows once the nodes have a
+/// layout to attach to, then saves it.
+pub fn populate_tab(
+    mut commands: Commands,
+    mut request: ResMut<PopulateRequest>,
+    mut ui_state: ResMut<UiState>,
+    mut add_rect: EventWriter<AddRect>,
+    mut create_arrow: EventWriter<CreateArrow>,
+) {
+    match request.frame {
+        0 => {
+            for node in std::mem::take(&mut request.checkpoint.nodes) {
+                add_rect.send(AddRect { node, image: None });
+            }
+        }
+        2 => {
+            for arrow in request.checkpoint.arrows.iter() {
+                create_arrow.send(CreateArrow {
+                    arrow_type: arrow.arrow_type,
+                    start: arrow.start,
+                    end: arrow.end,
+                });
+            }
+        }
+        4 => {
+            // Every AddRect opens its node for editing
+            *ui_state = UiState::default();
+            commands.insert_resource(SaveRequest {
+                doc_id: None,
+                tab_id: None,
+            });
+            commands.remove_resource::<PopulateRequest>();
+        }
+        _ => {}
+    }
+    request.frame += 1;
+}
+
+/// Opens a checkpoint from another format as a new tab of the current doc.
+fn import_tab(
+    commands: &mut Commands,
+    app_state: &mut ResMut<AppState>,
+    name: String,
+    checkpoint: &Checkpoint,
+) {
+    let doc_id = app_state.current_document.unwrap();
+    let doc = app_state.docs.get_mut(&doc_id).unwrap();
+    for tab in doc.tabs.iter_mut() {
+        if tab.is_active {
+            commands.insert_resource(SaveRequest {
+                doc_id: None,
+                tab_id: Some(tab.id),
+            });
+        }
+        tab.is_active = false;
+    }
+    let mut checkpoints = VecDeque::new();
+    checkpoints.push_back(checkpoint_to_string(checkpoint).unwrap());
+    doc.tabs.push(Tab {
+        id: ReflectableUuid(Uuid::new_v4()),
+        name,
+        checkpoints,
+        is_active: true,
+        view: Default::default(),
+    });
+    app_state.docs.mark_dirty(doc_id);
+    commands.insert_resource(LoadRequest { doc_id: None });
+}
@@ -1,4 +1,4 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::VecDeque;
 
 use bevy::{
     a11y::{
@@ -7,27 +7,35 @@ use bevy::{
     },
     prelude::*,
 };
-use bevy_pkv::PkvStore;
 use uuid::Uuid;
 
 use super::ui_helpers::ScrollingList;
 use crate::chart_plugin::ui_helpers::{add_list_item, add_tab, DocList};
 use crate::components::{Doc, Tab};
 use crate::resources::{AppState, LoadRequest};
+use crate::storage::images::collect_image_garbage;
+use crate::storage::Store;
 use crate::utils::ReflectableUuid;
 
 pub fn add_list(
     bottom_panel: Entity,
     commands: &mut Commands,
     state: &mut ResMut<AppState>,
-    pkv: &mut ResMut<PkvStore>,
+    store: &mut ResMut<Store>,
     font: Handle<Font>,
 ) -> Entity {
-    if let Ok(last_saved) = pkv.get::<ReflectableUuid>("last_saved") {
+    collect_image_garbage(&mut ***store, state.docs.values());
+
+    let names = store.doc_names();
+    // A stale "last_saved" falls back to any stored doc instead of a missing one
+    let last_saved = store
+        .last_saved()
+        .filter(|id| names.contains_key(id))
+        .or_else(|| names.keys().next().copied());
+    if let Some(last_saved) = last_saved {
         state.current_document = Some(last_saved);
         commands.insert_resource(LoadRequest {
             doc_id: Some(last_saved),
-            drop_last_checkpoint: false,
         });
     }
 
@@ -61,7 +69,7 @@ pub fn add_list(
         ))
         .id();
 
-    if let Ok(names) = pkv.get::<HashMap<ReflectableUuid, String>>("names") {
+    if !names.is_empty() {
         let mut keys: Vec<_> = names.keys().collect();
         keys.sort_by_key(|k| names.get(k).unwrap().to_lowercase());
 
@@ -78,6 +86,7 @@ pub fn add_list(
             name: tab_name.clone(),
             checkpoints: VecDeque::new(),
             is_active: true,
+            view: Default::default(),
         }];
         let doc_id = ReflectableUuid(Uuid::new_v4());
         let name = "Untitled".to_string();
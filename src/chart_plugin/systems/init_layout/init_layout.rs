@@ -1,14 +1,16 @@
 use bevy::prelude::*;
+use bevy_ui_borders::Outline;
 use std::time::Duration;
 
-use bevy_pkv::PkvStore;
-
 use super::ui_helpers::{
-    self, AddTab, BottomPanel, ButtonAction, LeftPanel, LeftPanelControls, LeftPanelExplorer,
-    MainPanel, Menu, NewDoc, Root, SaveDoc, TextManipulation, TextManipulationAction, TextPosMode,
+    self, AddTab, BackupAll, BottomPanel, ButtonAction, LeftPanel, LeftPanelControls,
+    LeftPanelExplorer, MainPanel, Menu, Minimap, MinimapViewport, NewDoc, Root, SaveDoc,
+    TextManipulation, TextManipulationAction, TextPosMode,
 };
+use super::{ErrorEvent, MINIMAP_SIZE};
 use crate::canvas::arrow::components::{ArrowMode, ArrowType};
-use crate::resources::{AppState, StaticState};
+use crate::resources::{AppState, Settings, StaticState};
+use crate::storage::Store;
 use crate::{BlinkTimer, TextPos};
 
 #[path = "add_arrow.rs"]
@@ -48,13 +50,23 @@ pub fn init_layout(
     mut static_state: ResMut<StaticState>,
     mut app_state: ResMut<AppState>,
     asset_server: Res<AssetServer>,
-    mut pkv: ResMut<PkvStore>,
+    mut store: ResMut<Store>,
+    mut errors: EventWriter<ErrorEvent>,
 ) {
     let font = asset_server.load("fonts/iosevka-regular.ttf");
     commands.insert_resource(BlinkTimer {
         timer: Timer::new(Duration::from_millis(500), TimerMode::Repeating),
     });
     static_state.font = Some(font.clone());
+    match store.load_settings() {
+        Some(settings) => commands.insert_resource(settings),
+        // Every key shows up in `velo-cli settings` and in backups
+        None => {
+            if let Err(error) = store.save_settings(&Settings::default()) {
+                errors.send(ErrorEvent(error));
+            }
+        }
+    }
     let bottom_panel = commands
         .spawn((
             NodeBundle {
@@ -91,7 +103,7 @@ pub fn init_layout(
         bottom_panel,
         &mut commands,
         &mut app_state,
-        &mut pkv,
+        &mut store,
         font.clone(),
     );
 
@@ -146,8 +158,16 @@ pub fn init_layout(
         "Save".to_string(),
         SaveDoc,
     );
+    let backup_all = add_menu_button(
+        &mut commands,
+        &asset_server,
+        font.clone(),
+        "Backup All".to_string(),
+        BackupAll,
+    );
     commands.entity(menu).add_child(save_doc);
     commands.entity(menu).add_child(new_doc);
+    commands.entity(menu).add_child(backup_all);
 
     let main_bottom = commands
         .spawn(NodeBundle {
@@ -208,7 +228,62 @@ pub fn init_layout(
             MainPanel,
         ))
         .id();
+    let canvas = commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(0.),
+                    bottom: Val::Px(0.),
+                    ..default()
+                },
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+
+    let minimap = commands
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(10.),
+                        bottom: Val::Px(10.),
+                        ..default()
+                    },
+                    size: Size::new(Val::Px(MINIMAP_SIZE.x), Val::Px(MINIMAP_SIZE.y)),
+                    overflow: Overflow::Hidden,
+                    display: Display::None,
+                    ..default()
+                },
+                background_color: Color::rgba(1., 1., 1., 0.8).into(),
+                ..default()
+            },
+            Outline::all(Color::GRAY, Val::Px(1.)),
+            Minimap::default(),
+        ))
+        .id();
+    let minimap_viewport = commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                ..default()
+            },
+            Outline::all(Color::BLUE, Val::Px(1.)),
+            MinimapViewport,
+        ))
+        .id();
 
+    commands.entity(minimap).add_child(minimap_viewport);
+    commands.entity(main_panel).add_child(canvas);
+    // After the canvas, so it's drawn on top of the nodes
+    commands.entity(main_panel).add_child(minimap);
     commands.entity(right_panel).add_child(main_panel);
     commands.entity(right_panel).add_child(bottom_panel);
 
@@ -511,4 +586,5 @@ pub fn init_layout(
     commands.entity(root_ui).add_child(main_bottom);
 
     static_state.main_panel = Some(main_panel);
+    static_state.canvas = Some(canvas);
 }
@@ -13,7 +13,20 @@ use uuid::Uuid;
 use crate::{AddRect, BlinkTimer, UiState};
 
 use super::ui_helpers::{get_sections, DocListItemText, EditableText, SelectedTabTextInput};
-use crate::resources::{AppState, LoadRequest, SaveRequest, StaticState};
+use crate::resources::{
+    AppState, CanvasView, ExportFormat, ExportRequest, SaveRequest, StaticState,
+};
+
+// Command + key saves the doc and exports it, keys of a single format
+// export it with and without shift
+const EXPORT_SHORTCUTS: [(KeyCode, ExportFormat, ExportFormat); 6] = [
+    (KeyCode::E, ExportFormat::Velo, ExportFormat::Svg),
+    (KeyCode::M, ExportFormat::Mermaid, ExportFormat::Mermaid),
+    (KeyCode::P, ExportFormat::Png, ExportFormat::Html),
+    (KeyCode::D, ExportFormat::Dot, ExportFormat::Dot),
+    (KeyCode::J, ExportFormat::Canvas, ExportFormat::Canvas),
+    (KeyCode::O, ExportFormat::Markdown, ExportFormat::Opml),
+];
 
 pub fn keyboard_input_system(
     mut commands: Commands,
@@ -52,12 +65,17 @@ pub fn keyboard_input_system(
     >,
     mut blink_timer: ResMut<BlinkTimer>,
     time: Res<Time>,
+    view: Res<CanvasView>,
 ) {
     let primary_window = windows.single();
     let scale_factor = primary_window.scale_factor();
     let font = static_state.font.as_ref().unwrap().clone();
     let command = input.any_pressed([KeyCode::RWin, KeyCode::LWin]);
     let shift = input.any_pressed([KeyCode::RShift, KeyCode::LShift]);
+    let export_format = EXPORT_SHORTCUTS
+        .iter()
+        .find(|(key, _, _)| input.just_pressed(*key))
+        .map(|(_, format, shift_format)| if shift { *shift_format } else { *format });
     blink_timer.timer.tick(time.delta());
     if command && input.just_pressed(KeyCode::V) {
         #[cfg(not(target_arch = "wasm32"))]
@@ -68,6 +86,7 @@ pub fn keyboard_input_system(
             &mut events,
             font,
             scale_factor,
+            view.offset,
         );
     } else if command && shift && input.just_pressed(KeyCode::S) {
         commands.insert_resource(SaveRequest {
@@ -79,10 +98,14 @@ pub fn keyboard_input_system(
             doc_id: None,
             tab_id: None,
         });
-    } else if command && input.just_pressed(KeyCode::L) {
-        commands.insert_resource(LoadRequest {
+    } else if let (true, Some(format)) = (command, export_format) {
+        commands.insert_resource(SaveRequest {
+            doc_id: None,
+            tab_id: None,
+        });
+        commands.insert_resource(ExportRequest {
             doc_id: None,
-            drop_last_checkpoint: true,
+            format,
         });
     } else {
         if ui_state.entity_to_edit.is_some()
@@ -229,6 +252,7 @@ pub fn insert_from_clipboard(
     events: &mut EventWriter<AddRect>,
     font: Handle<Font>,
     scale_factor: f64,
+    position: Vec2,
 ) {
     use crate::JsonNode;
 
@@ -277,8 +301,8 @@ pub fn insert_from_clipboard(
             node: JsonNode {
                 id: Uuid::new_v4(),
                 node_type: crate::NodeType::Rect,
-                left: Val::Px(0.0),
-                bottom: Val::Px(0.0),
+                x: position.x,
+                y: position.y,
                 width: Val::Px(resize_width as f32),
                 height: Val::Px(resize_height as f32),
                 text: crate::JsonNodeText {
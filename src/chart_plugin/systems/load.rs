@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 use base64::{engine::general_purpose, Engine};
 use bevy::{
     prelude::*,
@@ -8,18 +6,19 @@ use bevy::{
 
 use super::{
     ui_helpers::{add_tab, spawn_node, BottomPanel, NodeMeta, TabContainer},
-    HighlightEvent, VeloNodeContainer,
+    ChangeTracker, ErrorEvent, HighlightEvent, VeloNodeContainer,
 };
 use crate::canvas::arrow::components::ArrowMeta;
 use crate::canvas::arrow::events::CreateArrow;
-use crate::components::Doc;
-use crate::resources::{AppState, LoadRequest, StaticState};
+use crate::components::Tab;
+use crate::errors::VeloError;
+use crate::formats::velo::{parse_checkpoint, Checkpoint};
+use crate::resources::{AppState, CanvasView, LoadRequest, StaticState};
+use crate::storage::Store;
 use crate::utils::ReflectableUuid;
-use crate::{JsonNode, UiState, MAX_SAVED_DOCS_IN_MEMORY};
-use bevy_pkv::PkvStore;
+use crate::UiState;
 #[cfg(not(target_arch = "wasm32"))]
 use image::{load_from_memory_with_format, ImageFormat};
-use serde_json::Value;
 
 pub fn should_load(request: Option<Res<LoadRequest>>) -> bool {
     request.is_some()
@@ -41,10 +40,22 @@ pub fn load_json(
     mut create_arrow: EventWriter<CreateArrow>,
     mut selected_tabs_query: Query<Entity, With<TabContainer>>,
     mut bottom_panel: Query<Entity, With<BottomPanel>>,
-    pkv: ResMut<PkvStore>,
+    store: Res<Store>,
     mut events: EventWriter<HighlightEvent>,
-) {
+    mut tracker: ResMut<ChangeTracker>,
+    mut errors: EventWriter<ErrorEvent>,
+    mut view: ResMut<CanvasView>,
+) -> Result<(), VeloError> {
+    let doc_id = request
+        .doc_id
+        .unwrap_or_else(|| app_state.current_document.unwrap());
+    // Keep the current canvas if the doc can't be loaded
+    app_state.docs.load(&**store, doc_id)?;
+    app_state.current_document = Some(doc_id);
+
     *ui_state = UiState::default();
+    // Nodes and arrows are respawned over the next frames, it's not an edit
+    tracker.settle_frames = 3;
 
     let bottom_panel = bottom_panel.single_mut();
 
@@ -68,30 +79,6 @@ pub fn load_json(
         commands.entity(entity).despawn_recursive();
     }
 
-    let doc_id = if request.doc_id.is_some() {
-        request.doc_id.unwrap()
-    } else {
-        app_state.current_document.unwrap()
-    };
-
-    if app_state.docs.contains_key(&doc_id) {
-        app_state.current_document = Some(doc_id);
-    } else if let Ok(docs) = pkv.get::<HashMap<ReflectableUuid, Doc>>("docs") {
-        if docs.contains_key(&doc_id) {
-            while (app_state.docs.len() as i32) >= MAX_SAVED_DOCS_IN_MEMORY {
-                let keys = app_state.docs.keys().cloned().collect::<Vec<_>>();
-                app_state.docs.remove(&keys[0]);
-            }
-            app_state
-                .docs
-                .insert(doc_id, docs.get(&doc_id).unwrap().clone());
-            app_state.current_document = Some(doc_id);
-        } else {
-            panic!("Document not found in pkv");
-        }
-    }
-    let doc_id = app_state.current_document.unwrap();
-
     let mut tabs = vec![];
     for tab in app_state.docs.get_mut(&doc_id).unwrap().tabs.iter() {
         let tab_view = add_tab(&mut commands, font.clone(), tab.name.clone(), tab.id);
@@ -101,44 +88,33 @@ pub fn load_json(
 
     for tab in app_state.docs.get_mut(&doc_id).unwrap().tabs.iter_mut() {
         if tab.is_active {
+            *view = tab.view;
             if tab.checkpoints.is_empty() {
                 break;
             }
 
-            let json = if request.drop_last_checkpoint && tab.checkpoints.len() > 1 {
-                tab.checkpoints.pop_back().unwrap()
-            } else {
-                tab.checkpoints.back().unwrap().clone()
+            let Some(checkpoint) = latest_valid_checkpoint(tab, &mut errors) else {
+                break;
             };
-            let mut json: Value = serde_json::from_str(&json).unwrap();
-            let images = json["images"].as_object().unwrap();
-            let nodes = json["nodes"].as_array().unwrap();
-            for node in nodes.iter() {
-                let json_node: JsonNode = serde_json::from_value(node.clone()).unwrap();
-                let image: Option<UiImage> = match images.get(&json_node.id.to_string()) {
-                    Some(image) => {
-                        let image_bytes = general_purpose::STANDARD
-                            .decode(image.as_str().unwrap().as_bytes())
-                            .unwrap();
-                        #[cfg(not(target_arch = "wasm32"))]
-                        {
-                            let img = load_from_memory_with_format(&image_bytes, ImageFormat::Png)
-                                .unwrap();
-                            let size: Extent3d = Extent3d {
-                                width: img.width(),
-                                height: img.height(),
-                                ..Default::default()
-                            };
-                            let image = Image::new(
-                                size,
-                                TextureDimension::D2,
-                                img.into_bytes(),
-                                TextureFormat::Rgba8UnormSrgb,
-                            );
-                            let image_handle = res_images.add(image);
-                            Some(image_handle.into())
+            for json_node in checkpoint.nodes {
+                let node_id = json_node.id.to_string();
+                let image = match checkpoint.inline_images.get(&node_id) {
+                    Some(image) => Some(image.clone()),
+                    None => checkpoint.images.get(&node_id).and_then(|hash| {
+                        let image = store.get_image(hash);
+                        if image.is_none() {
+                            errors.send(ErrorEvent(VeloError::Image(format!(
+                                "image {} is missing",
+                                hash
+                            ))));
                         }
-                        #[cfg(target_arch = "wasm32")]
+                        image
+                    }),
+                };
+                let image = match image.map(|image| decode_image(&image, &mut res_images)) {
+                    Some(Ok(image)) => image,
+                    Some(Err(error)) => {
+                        errors.send(ErrorEvent(error));
                         None
                     }
                     None => None,
@@ -153,20 +129,18 @@ pub fn load_json(
                         image: image.clone(),
                         text: json_node.text.text.clone(),
                         bg_color: json_node.bg_color,
-                        position: (json_node.left, json_node.bottom),
+                        position: (Val::Px(json_node.x), Val::Px(json_node.y)),
                         tags: json_node.tags,
                         text_pos: json_node.text.pos,
                         z_index: json_node.z_index,
                     },
                 );
                 commands
-                    .entity(static_state.main_panel.unwrap())
+                    .entity(static_state.canvas.unwrap())
                     .add_child(entity);
             }
 
-            let arrows = json["arrows"].as_array_mut().unwrap();
-            for arrow in arrows.iter() {
-                let arrow_meta: ArrowMeta = serde_json::from_value(arrow.clone()).unwrap();
+            for arrow_meta in checkpoint.arrows {
                 create_arrow.send(CreateArrow {
                     start: arrow_meta.start,
                     end: arrow_meta.end,
@@ -177,4 +151,50 @@ pub fn load_json(
         }
     }
     events.send(HighlightEvent);
+    Ok(())
+}
+
+/// Parses the latest checkpoint of a tab, falling back to older ones if it's
+/// corrupted. Broken checkpoints are dropped so the next save doesn't keep them.
+fn latest_valid_checkpoint(
+    tab: &mut Tab,
+    errors: &mut EventWriter<ErrorEvent>,
+) -> Option<Checkpoint> {
+    while let Some(json) = tab.checkpoints.back() {
+        match parse_checkpoint(json) {
+            Ok(checkpoint) => return Some(checkpoint),
+            Err(error) => {
+                errors.send(ErrorEvent(error.into()));
+                tab.checkpoints.pop_back();
+            }
+        }
+    }
+    None
+}
+
+#[allow(unused)]
+fn decode_image(image: &str, res_images: &mut Assets<Image>) -> Result<Option<UiImage>, VeloError> {
+    let image_bytes = general_purpose::STANDARD
+        .decode(image.as_bytes())
+        .map_err(|error| VeloError::Image(error.to_string()))?;
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let img = load_from_memory_with_format(&image_bytes, ImageFormat::Png)
+            .map_err(|error| VeloError::Image(error.to_string()))?;
+        let size: Extent3d = Extent3d {
+            width: img.width(),
+            height: img.height(),
+            ..Default::default()
+        };
+        let image = Image::new(
+            size,
+            TextureDimension::D2,
+            img.into_bytes(),
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        let image_handle = res_images.add(image);
+        Ok(Some(image_handle.into()))
+    }
+    #[cfg(target_arch = "wasm32")]
+    Ok(None)
 }
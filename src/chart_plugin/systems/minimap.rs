@@ -0,0 +1,321 @@
+//! Overview of the whole tab in a corner of `MainPanel`. Nodes and arrows are
+//! mirrored as small UI nodes that are only restyled when they change, all
+//! of them only when the bounds of the diagram change.
+use std::collections::{HashMap, HashSet};
+
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use super::panel_origin;
+use super::ui_helpers::{
+    MainPanel, Minimap, MinimapItem, MinimapViewport, NodeRect, VeloNode, VeloNodeContainer,
+};
+use crate::canvas::arrow::components::ArrowMeta;
+use crate::formats::connect_point;
+use crate::resources::CanvasView;
+use crate::utils::ReflectableUuid;
+
+pub const MINIMAP_SIZE: Vec2 = Vec2::new(200., 150.);
+const MINIMAP_PADDING: f32 = 8.;
+const MINIMAP_ARROW_COLOR: Color = Color::DARK_GRAY;
+
+#[derive(Default)]
+pub struct MinimapIndex {
+    containers: HashMap<Entity, ReflectableUuid>,
+    nodes: HashMap<ReflectableUuid, MinimapNode>,
+    arrows: HashMap<Entity, MinimapArrow>,
+    size: Vec2,
+}
+
+struct MinimapNode {
+    item: Option<Entity>,
+    rect: Rect,
+    color: Color,
+}
+
+struct MinimapArrow {
+    item: Option<Entity>,
+    visible: bool,
+}
+
+/// Scale from world to minimap and the margin that centers the diagram.
+fn minimap_scale(bounds: Rect, size: Vec2) -> (f32, Vec2) {
+    let scale = ((size - 2. * MINIMAP_PADDING) / bounds.size().max(Vec2::ONE)).min_element();
+    (scale, (size - bounds.size() * scale) / 2.)
+}
+
+fn to_minimap(bounds: Rect, size: Vec2, point: Vec2) -> Vec2 {
+    let (scale, margin) = minimap_scale(bounds, size);
+    margin + (point - bounds.min) * scale
+}
+
+fn minimap_to_world(bounds: Rect, size: Vec2, point: Vec2) -> Vec2 {
+    let (scale, margin) = minimap_scale(bounds, size);
+    bounds.min + (point - margin) / scale
+}
+
+fn rect_style(rect: Rect) -> Style {
+    Style {
+        position_type: PositionType::Absolute,
+        position: UiRect {
+            left: Val::Px(rect.min.x),
+            bottom: Val::Px(rect.min.y),
+            ..default()
+        },
+        size: Size::new(Val::Px(rect.width()), Val::Px(rect.height())),
+        ..default()
+    }
+}
+
+/// A one pixel high line from `start` to `end`, rotated around its center.
+fn line_style(start: Vec2, end: Vec2) -> (Style, Quat) {
+    let length = start.distance(end);
+    let center = (start + end) / 2.;
+    let style = rect_style(Rect::from_center_size(center, Vec2::new(length, 1.)));
+    // UI transforms grow downwards
+    let angle = (end - start).y.atan2((end - start).x);
+    (style, Quat::from_rotation_z(-angle))
+}
+
+/// Mirrors nodes, arrows and the view on the minimap. The minimap is hidden
+/// while the tab is empty.
+pub fn update_minimap(
+    mut commands: Commands,
+    mut index: Local<MinimapIndex>,
+    view: Res<CanvasView>,
+    mut minimap: Query<
+        (Entity, &Node, &mut Style, &mut Minimap),
+        (Without<MinimapItem>, Without<MinimapViewport>),
+    >,
+    main_panel: Query<&Node, With<MainPanel>>,
+    containers: Query<(Entity, &VeloNodeContainer, Ref<NodeRect>)>,
+    colors: Query<(&VeloNode, Ref<BackgroundColor>)>,
+    arrows: Query<(Entity, Ref<ArrowMeta>, Ref<Visibility>)>,
+    mut removed_containers: RemovedComponents<VeloNodeContainer>,
+    mut items: Query<
+        (
+            &mut Style,
+            &mut BackgroundColor,
+            &mut Transform,
+            &mut Visibility,
+        ),
+        (With<MinimapItem>, Without<VeloNode>, Without<ArrowMeta>),
+    >,
+    mut viewport: Query<&mut Style, (With<MinimapViewport>, Without<MinimapItem>)>,
+) {
+    let index = &mut *index;
+    let (minimap, minimap_node, mut minimap_style, mut minimap_bounds) = minimap.single_mut();
+    let mut changed = HashSet::new();
+
+    for entity in removed_containers.iter() {
+        let Some(id) = index.containers.remove(&entity) else {
+            continue;
+        };
+        if let Some(item) = index.nodes.remove(&id).and_then(|node| node.item) {
+            commands.entity(item).despawn_recursive();
+        }
+        changed.insert(id);
+    }
+    for (entity, container, rect) in containers.iter() {
+        if !rect.is_changed() {
+            continue;
+        }
+        index.containers.insert(entity, container.id);
+        index
+            .nodes
+            .entry(container.id)
+            .or_insert(MinimapNode {
+                item: None,
+                rect: rect.rect(),
+                color: Color::WHITE,
+            })
+            .rect = rect.rect();
+        changed.insert(container.id);
+    }
+    for (node, color) in colors.iter() {
+        // New nodes take their color in the frame they show up
+        if !color.is_changed() && !changed.contains(&node.id) {
+            continue;
+        }
+        if let Some(minimap_node) = index.nodes.get_mut(&node.id) {
+            minimap_node.color = color.0;
+            changed.insert(node.id);
+        }
+    }
+
+    let display = if index.nodes.is_empty() {
+        Display::None
+    } else {
+        Display::Flex
+    };
+    if minimap_style.display != display {
+        minimap_style.display = display;
+    }
+    let bounds = index
+        .nodes
+        .values()
+        .map(|node| node.rect)
+        .reduce(|a, b| a.union(b))
+        .unwrap_or_default();
+    let size = minimap_node.size();
+    let restyle_all = minimap_bounds.bounds != bounds || index.size != size;
+    index.size = size;
+    if minimap_bounds.bounds != bounds {
+        minimap_bounds.bounds = bounds;
+    }
+    let minimap_rect = |rect: Rect| {
+        Rect::from_corners(
+            to_minimap(bounds, size, rect.min),
+            to_minimap(bounds, size, rect.max),
+        )
+    };
+
+    for (id, node) in index.nodes.iter_mut() {
+        if !restyle_all && node.item.is_some() && !changed.contains(id) {
+            continue;
+        }
+        let style = rect_style(minimap_rect(node.rect));
+        match node.item.and_then(|item| items.get_mut(item).ok()) {
+            Some((mut item_style, mut color, _, _)) => {
+                if *item_style != style {
+                    *item_style = style;
+                }
+                if color.0 != node.color {
+                    color.0 = node.color;
+                }
+            }
+            None if node.item.is_none() => {
+                let item = commands
+                    .spawn((
+                        NodeBundle {
+                            style,
+                            background_color: node.color.into(),
+                            ..default()
+                        },
+                        MinimapItem,
+                    ))
+                    .id();
+                commands.entity(minimap).add_child(item);
+                node.item = Some(item);
+            }
+            None => {}
+        }
+    }
+
+    let ends_changed =
+        |meta: &ArrowMeta| changed.contains(&meta.start.id) || changed.contains(&meta.end.id);
+    for (entity, meta, visibility) in arrows.iter() {
+        let visible = *visibility != Visibility::Hidden;
+        let entry = index.arrows.entry(entity).or_insert(MinimapArrow {
+            item: None,
+            visible,
+        });
+        if !restyle_all
+            && entry.item.is_some()
+            && !meta.is_changed()
+            && !visibility.is_changed()
+            && !ends_changed(&meta)
+        {
+            continue;
+        }
+        entry.visible = visible;
+        let ends = index
+            .nodes
+            .get(&meta.start.id)
+            .zip(index.nodes.get(&meta.end.id));
+        let Some((start, end)) = ends else {
+            entry.visible = false;
+            if let Some(item) = entry.item {
+                if let Ok((_, _, _, mut item_visibility)) = items.get_mut(item) {
+                    *item_visibility = Visibility::Hidden;
+                }
+            }
+            continue;
+        };
+        let start = to_minimap(bounds, size, connect_point(start.rect, meta.start.pos));
+        let end = to_minimap(bounds, size, connect_point(end.rect, meta.end.pos));
+        let (style, rotation) = line_style(start, end);
+        let visibility = if entry.visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        match entry.item.and_then(|item| items.get_mut(item).ok()) {
+            Some((mut item_style, _, mut transform, mut item_visibility)) => {
+                if *item_style != style {
+                    *item_style = style;
+                }
+                if transform.rotation != rotation {
+                    transform.rotation = rotation;
+                }
+                if *item_visibility != visibility {
+                    *item_visibility = visibility;
+                }
+            }
+            None if entry.item.is_none() => {
+                let item = commands
+                    .spawn((
+                        NodeBundle {
+                            style,
+                            background_color: MINIMAP_ARROW_COLOR.into(),
+                            transform: Transform::from_rotation(rotation),
+                            visibility,
+                            ..default()
+                        },
+                        MinimapItem,
+                    ))
+                    .id();
+                commands.entity(minimap).add_child(item);
+                entry.item = Some(item);
+            }
+            None => {}
+        }
+    }
+    index.arrows.retain(|entity, arrow| {
+        let exists = arrows.contains(*entity);
+        if let (false, Some(item)) = (exists, arrow.item) {
+            commands.entity(item).despawn_recursive();
+        }
+        exists
+    });
+
+    if restyle_all || view.is_changed() {
+        let panel = main_panel.single().size();
+        let visible = Rect::from_corners(view.to_world(Vec2::ZERO), view.to_world(panel));
+        let style = rect_style(minimap_rect(visible));
+        let mut viewport_style = viewport.single_mut();
+        if *viewport_style != style {
+            *viewport_style = style;
+        }
+    }
+}
+
+/// Clicking or dragging on the minimap centers the view on that point.
+pub fn navigate_minimap(
+    minimap: Query<(&Interaction, &Node, &GlobalTransform, &Minimap)>,
+    main_panel: Query<&Node, With<MainPanel>>,
+    buttons: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut view: ResMut<CanvasView>,
+    mut dragging: Local<bool>,
+) {
+    let (interaction, node, transform, minimap) = minimap.single();
+    if *interaction == Interaction::Clicked {
+        *dragging = true;
+    }
+    if !buttons.pressed(MouseButton::Left) {
+        *dragging = false;
+    }
+    if !*dragging {
+        return;
+    }
+    let window = windows.single();
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+    let point = cursor - panel_origin(node, transform, window);
+    let center = minimap_to_world(minimap.bounds, node.size(), point);
+    let offset = center - main_panel.single().size() / 2. / view.zoom;
+    if view.offset != offset {
+        view.offset = offset;
+    }
+}
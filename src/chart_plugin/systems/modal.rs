@@ -1,11 +1,11 @@
-use std::collections::HashMap;
-
 use bevy::prelude::*;
-use bevy_pkv::PkvStore;
 
 use super::ui_helpers::{DocListItemContainer, ModalCancel, ModalConfirm, ModalEntity, ModalTop};
-use crate::components::Doc;
+use super::{reload_doc, ErrorEvent, UndoHistory};
+use crate::errors::VeloError;
 use crate::resources::{AppState, LoadRequest};
+use crate::storage::images::collect_image_garbage;
+use crate::storage::{StorageBackend, Store};
 use crate::utils::ReflectableUuid;
 use crate::UiState;
 
@@ -40,7 +40,9 @@ pub fn confirm_modal(
     mut ui_state: ResMut<UiState>,
     query_top: Query<(Entity, &ModalTop), With<ModalTop>>,
     mut query_container: Query<(Entity, &DocListItemContainer), With<DocListItemContainer>>,
-    mut pkv: ResMut<PkvStore>,
+    mut store: ResMut<Store>,
+    mut errors: EventWriter<ErrorEvent>,
+    mut history: ResMut<UndoHistory>,
 ) {
     for (interaction, path_modal_confirm) in interaction_query.iter_mut() {
         if let Interaction::Clicked = interaction {
@@ -70,10 +72,7 @@ pub fn confirm_modal(
                             .last_mut()
                             .unwrap();
                         last_tab.is_active = true;
-                        commands.insert_resource(LoadRequest {
-                            doc_id: None,
-                            drop_last_checkpoint: false,
-                        });
+                        commands.insert_resource(LoadRequest { doc_id: None });
                     }
                     if path_modal_confirm.delete == ModalEntity::Document {
                         let id_to_remove = current_document;
@@ -89,14 +88,23 @@ pub fn confirm_modal(
                                 break;
                             }
                         }
-                        commands.insert_resource(LoadRequest {
-                            doc_id: None,
-                            drop_last_checkpoint: false,
-                        });
-                        remove_from_pkv(
-                            &mut pkv,
+                        commands.insert_resource(LoadRequest { doc_id: None });
+                        if let Err(error) = remove_from_store(
+                            &mut **store,
                             id_to_remove,
                             app_state.current_document.unwrap(),
+                        ) {
+                            errors.send(ErrorEvent(error));
+                        }
+                        collect_image_garbage(&mut **store, app_state.docs.values());
+                    }
+                    if let ModalEntity::Reload(doc_id) = path_modal_confirm.delete {
+                        reload_doc(
+                            &mut commands,
+                            &mut app_state,
+                            &mut history,
+                            &**store,
+                            doc_id,
                         );
                     }
                     commands.entity(entity).despawn_recursive();
@@ -114,7 +122,9 @@ pub fn modal_keyboard_input_system(
     query_top: Query<(Entity, &ModalTop), With<ModalTop>>,
     mut commands: Commands,
     mut query_container: Query<(Entity, &DocListItemContainer), With<DocListItemContainer>>,
-    mut pkv: ResMut<PkvStore>,
+    mut store: ResMut<Store>,
+    mut errors: EventWriter<ErrorEvent>,
+    mut history: ResMut<UndoHistory>,
 ) {
     if input.just_pressed(KeyCode::Return) {
         for (entity, path_modal_top) in query_top.iter() {
@@ -151,10 +161,7 @@ pub fn modal_keyboard_input_system(
                         .last_mut()
                         .unwrap();
                     last_tab.is_active = true;
-                    commands.insert_resource(LoadRequest {
-                        doc_id: None,
-                        drop_last_checkpoint: false,
-                    });
+                    commands.insert_resource(LoadRequest { doc_id: None });
                 }
                 if path_modal_top.delete == ModalEntity::Document {
                     let id_to_remove = current_document;
@@ -170,11 +177,24 @@ pub fn modal_keyboard_input_system(
                             break;
                         }
                     }
-                    commands.insert_resource(LoadRequest {
-                        doc_id: None,
-                        drop_last_checkpoint: false,
-                    });
-                    remove_from_pkv(&mut pkv, id_to_remove, app_state.current_document.unwrap());
+                    commands.insert_resource(LoadRequest { doc_id: None });
+                    if let Err(error) = remove_from_store(
+                        &mut **store,
+                        id_to_remove,
+                        app_state.current_document.unwrap(),
+                    ) {
+                        errors.send(ErrorEvent(error));
+                    }
+                    collect_image_garbage(&mut **store, app_state.docs.values());
+                }
+                if let ModalEntity::Reload(doc_id) = path_modal_top.delete {
+                    reload_doc(
+                        &mut commands,
+                        &mut app_state,
+                        &mut history,
+                        &**store,
+                        doc_id,
+                    );
                 }
                 commands.entity(entity).despawn_recursive();
                 ui_state.modal_id = None;
@@ -183,29 +203,14 @@ pub fn modal_keyboard_input_system(
     }
 }
 
-fn remove_from_pkv(
-    pkv: &mut ResMut<PkvStore>,
+fn remove_from_store(
+    store: &mut dyn StorageBackend,
     id_to_remove: ReflectableUuid,
     new_id: ReflectableUuid,
-) {
-    if let Ok(mut docs) = pkv.get::<HashMap<ReflectableUuid, Doc>>("docs") {
-        if docs.remove(&id_to_remove).is_some() {
-            pkv.set("docs", &docs).unwrap();
-        }
-    }
-    if let Ok(mut tags) = pkv.get::<HashMap<ReflectableUuid, Vec<String>>>("tags") {
-        if tags.remove(&id_to_remove).is_some() {
-            pkv.set("tags", &tags).unwrap();
-        }
-    }
-    if let Ok(mut tags) = pkv.get::<HashMap<ReflectableUuid, String>>("names") {
-        if tags.remove(&id_to_remove).is_some() {
-            pkv.set("names", &tags).unwrap();
-        }
-    }
-    if let Ok(last_saved) = pkv.get::<ReflectableUuid>("last_saved") {
-        if last_saved == id_to_remove {
-            pkv.set("last_saved", &new_id).unwrap();
-        }
+) -> Result<(), VeloError> {
+    store.remove_doc(id_to_remove)?;
+    if store.last_saved() == Some(id_to_remove) {
+        store.set_last_saved(new_id)?;
     }
+    Ok(())
 }
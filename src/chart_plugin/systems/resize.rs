@@ -1,7 +1,9 @@
 use super::{
-    ui_helpers::{EditableText, ResizeMarker},
+    ui_helpers::{NodeRect, ResizeMarker},
     RedrawArrow, VeloNode, VeloNodeContainer,
 };
+use crate::resources::{CanvasView, Settings};
+use crate::utils::ReflectableUuid;
 use crate::UiState;
 use bevy::{input::mouse::MouseMotion, prelude::*, window::PrimaryWindow};
 
@@ -42,89 +44,71 @@ pub fn resize_entity_start(
     }
 }
 
+/// Resizes by the mouse motion. The unsnapped rectangle is kept while
+/// resizing, so small moves add up instead of snapping back.
 pub fn resize_entity_end(
     mut mouse_motion_events: EventReader<MouseMotion>,
     state: Res<UiState>,
-    mut rectangle_query: Query<(&VeloNodeContainer, &mut Style), With<VeloNodeContainer>>,
-    mut text_input_query: Query<
-        (&EditableText, &mut Style),
-        (With<EditableText>, Without<VeloNodeContainer>),
-    >,
+    mut rectangle_query: Query<(&VeloNodeContainer, &mut NodeRect), With<VeloNodeContainer>>,
     mut events: EventWriter<RedrawArrow>,
+    view: Res<CanvasView>,
+    settings: Res<Settings>,
+    keys: Res<Input<KeyCode>>,
+    mut unsnapped: Local<Option<(ReflectableUuid, Rect)>>,
 ) {
+    let Some((id, resize_marker)) = state.entity_to_resize else {
+        *unsnapped = None;
+        return;
+    };
+    let snap = |value: f32| {
+        if keys.any_pressed([KeyCode::LAlt, KeyCode::RAlt]) {
+            value
+        } else {
+            settings.snap(value)
+        }
+    };
     for event in mouse_motion_events.iter() {
-        if let Some((id, resize_marker)) = state.entity_to_resize {
-            for (rectangle, mut button_style) in &mut rectangle_query {
-                if id == rectangle.id {
-                    events.send(RedrawArrow { id });
-                    #[allow(unused)]
-                    let mut delta = event.delta;
-                    #[cfg(target_arch = "wasm32")]
-                    {
-                        // MouseMotion returns different values depending on platform
-                        delta = Vec2::new(delta.x / 2., delta.y / 2.);
-                    }
-                    match resize_marker {
-                        ResizeMarker::TopLeft => {
-                            if let Val::Px(width) = button_style.size.width {
-                                button_style.size.width = Val::Px(width - delta.x);
-                            }
-
-                            if let Val::Px(height) = button_style.size.height {
-                                button_style.size.height = Val::Px(height - delta.y);
-                            }
-
-                            if let Val::Px(x) = button_style.position.left {
-                                button_style.position.left = Val::Px(x + delta.x);
-                            }
-                        }
-                        ResizeMarker::TopRight => {
-                            if let Val::Px(width) = button_style.size.width {
-                                button_style.size.width = Val::Px(width + delta.x);
-                            }
-
-                            if let Val::Px(height) = button_style.size.height {
-                                button_style.size.height = Val::Px(height - delta.y);
-                            }
-                        }
-                        ResizeMarker::BottomLeft => {
-                            if let Val::Px(width) = button_style.size.width {
-                                button_style.size.width = Val::Px(width - delta.x);
-                            }
-
-                            if let Val::Px(height) = button_style.size.height {
-                                button_style.size.height = Val::Px(height + delta.y);
-                            }
-
-                            if let Val::Px(x) = button_style.position.left {
-                                button_style.position.left = Val::Px(x + delta.x);
-                            }
-
-                            if let Val::Px(y) = button_style.position.bottom {
-                                button_style.position.bottom = Val::Px(y - delta.y);
-                            }
-                        }
-                        ResizeMarker::BottomRight => {
-                            if let Val::Px(width) = button_style.size.width {
-                                button_style.size.width = Val::Px(width + delta.x);
-                            }
-
-                            if let Val::Px(height) = button_style.size.height {
-                                button_style.size.height = Val::Px(height + delta.y);
-                            }
-
-                            if let Val::Px(y) = button_style.position.bottom {
-                                button_style.position.bottom = Val::Px(y - delta.y);
-                            }
-                        }
-                    }
-                    for (text, mut text_style) in &mut text_input_query {
-                        if text.id == id {
-                            text_style.max_size.width = button_style.size.width;
-                            text_style.max_size.height = button_style.size.height;
-                        }
-                    }
+        for (rectangle, mut rect) in &mut rectangle_query {
+            if id == rectangle.id {
+                events.send(RedrawArrow { id });
+                let mut delta = event.delta;
+                #[cfg(target_arch = "wasm32")]
+                {
+                    // MouseMotion returns different values depending on platform
+                    delta = Vec2::new(delta.x / 2., delta.y / 2.);
+                }
+                delta /= view.zoom;
+                let mut raw = match *unsnapped {
+                    Some((resized, raw)) if resized == id => raw,
+                    _ => rect.rect(),
+                };
+                let mut snapped = raw;
+                // Mouse motion grows downwards, the canvas upwards
+                let (left, top) = match resize_marker {
+                    ResizeMarker::TopLeft => (true, true),
+                    ResizeMarker::TopRight => (false, true),
+                    ResizeMarker::BottomLeft => (true, false),
+                    ResizeMarker::BottomRight => (false, false),
+                };
+                if left {
+                    raw.min.x += delta.x;
+                    snapped.min.x = snap(raw.min.x);
+                } else {
+                    raw.max.x += delta.x;
+                    snapped.max.x = snap(raw.max.x);
+                }
+                if top {
+                    raw.max.y -= delta.y;
+                    snapped.max.y = snap(raw.max.y);
+                } else {
+                    raw.min.y -= delta.y;
+                    snapped.min.y = snap(raw.min.y);
                 }
+                *unsnapped = Some((id, raw));
+                rect.left = Val::Px(snapped.min.x);
+                rect.bottom = Val::Px(snapped.min.y);
+                rect.width = Val::Px(snapped.width());
+                rect.height = Val::Px(snapped.height());
             }
         }
     }
@@ -49,10 +49,7 @@ pub fn selected_tab_handler(
                     tab.is_active = tab.id == selected_tab.id;
                 }
 
-                commands.insert_resource(LoadRequest {
-                    doc_id: None,
-                    drop_last_checkpoint: false,
-                });
+                commands.insert_resource(LoadRequest { doc_id: None });
             }
             Interaction::Hovered => {}
             Interaction::None => {}
@@ -86,11 +83,9 @@ pub fn add_tab_handler(
                     name: "Tab ".to_string() + &(tabs_len + 1).to_string(),
                     checkpoints: VecDeque::new(),
                     is_active: true,
+                    view: Default::default(),
                 });
-                commands.insert_resource(LoadRequest {
-                    doc_id: None,
-                    drop_last_checkpoint: false,
-                });
+                commands.insert_resource(LoadRequest { doc_id: None });
             }
             Interaction::Hovered => {}
             Interaction::None => {}
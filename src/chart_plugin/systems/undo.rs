@@ -0,0 +1,540 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use super::ui_helpers::{get_sections, spawn_node, EditableText, NodeMeta, NodeRect, VeloNode};
+use super::VeloNodeContainer;
+use crate::canvas::arrow::components::ArrowMeta;
+use crate::canvas::arrow::events::{CreateArrow, RedrawArrow};
+use crate::components::Doc;
+use crate::resources::{AppState, StaticState};
+use crate::utils::ReflectableUuid;
+use crate::UiState;
+
+/// Older operations are coalesced once a tab has more than this many.
+const COMPACT_THRESHOLD: usize = 500;
+/// Number of recent operations that are never coalesced.
+const KEEP_UNCOMPACTED: usize = 100;
+
+#[derive(Clone)]
+pub enum Operation {
+    AddNode {
+        node: NodeMeta,
+        arrows: Vec<ArrowMeta>,
+    },
+    RemoveNode {
+        node: NodeMeta,
+        arrows: Vec<ArrowMeta>,
+    },
+    Move {
+        id: ReflectableUuid,
+        from: NodeRect,
+        to: NodeRect,
+    },
+    Resize {
+        id: ReflectableUuid,
+        from: NodeRect,
+        to: NodeRect,
+    },
+    Recolor {
+        id: ReflectableUuid,
+        from: Color,
+        to: Color,
+    },
+    EditText {
+        id: ReflectableUuid,
+        from: String,
+        to: String,
+    },
+    AddArrow {
+        arrow: ArrowMeta,
+    },
+    RemoveArrow {
+        arrow: ArrowMeta,
+    },
+    ZIndex {
+        id: ReflectableUuid,
+        from: i32,
+        to: i32,
+    },
+}
+
+impl Operation {
+    fn inverse(&self) -> Operation {
+        match self.clone() {
+            Operation::AddNode { node, arrows } => Operation::RemoveNode { node, arrows },
+            Operation::RemoveNode { node, arrows } => Operation::AddNode { node, arrows },
+            Operation::Move { id, from, to } => Operation::Move {
+                id,
+                from: to,
+                to: from,
+            },
+            Operation::Resize { id, from, to } => Operation::Resize {
+                id,
+                from: to,
+                to: from,
+            },
+            Operation::Recolor { id, from, to } => Operation::Recolor {
+                id,
+                from: to,
+                to: from,
+            },
+            Operation::EditText { id, from, to } => Operation::EditText {
+                id,
+                from: to,
+                to: from,
+            },
+            Operation::AddArrow { arrow } => Operation::RemoveArrow { arrow },
+            Operation::RemoveArrow { arrow } => Operation::AddArrow { arrow },
+            Operation::ZIndex { id, from, to } => Operation::ZIndex {
+                id,
+                from: to,
+                to: from,
+            },
+        }
+    }
+
+    /// Merges `next` into `self` when both change the same property of the
+    /// same node, keeping the original `from` value.
+    fn merge(&mut self, next: &Operation) -> bool {
+        match (self, next) {
+            (
+                Operation::Move { id, to, .. },
+                Operation::Move {
+                    id: next_id,
+                    to: next_to,
+                    ..
+                },
+            )
+            | (
+                Operation::Resize { id, to, .. },
+                Operation::Resize {
+                    id: next_id,
+                    to: next_to,
+                    ..
+                },
+            ) if id == next_id => {
+                *to = *next_to;
+                true
+            }
+            (
+                Operation::Recolor { id, to, .. },
+                Operation::Recolor {
+                    id: next_id,
+                    to: next_to,
+                    ..
+                },
+            ) if id == next_id => {
+                *to = *next_to;
+                true
+            }
+            (
+                Operation::EditText { id, to, .. },
+                Operation::EditText {
+                    id: next_id,
+                    to: next_to,
+                    ..
+                },
+            ) if id == next_id => {
+                *to = next_to.clone();
+                true
+            }
+            (
+                Operation::ZIndex { id, to, .. },
+                Operation::ZIndex {
+                    id: next_id,
+                    to: next_to,
+                    ..
+                },
+            ) if id == next_id => {
+                *to = *next_to;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TabHistory {
+    undo: Vec<Operation>,
+    redo: Vec<Operation>,
+}
+
+impl TabHistory {
+    pub fn push(&mut self, operation: Operation) {
+        self.redo.clear();
+        self.undo.push(operation);
+        if self.undo.len() > COMPACT_THRESHOLD {
+            self.compact();
+        }
+    }
+
+    /// Coalesces runs of operations on the same node property, except for the
+    /// most recent ones, so the log stays small without a hard limit.
+    pub fn compact(&mut self) {
+        let keep_from = self.undo.len().saturating_sub(KEEP_UNCOMPACTED);
+        let recent = self.undo.split_off(keep_from);
+        let mut compacted: Vec<Operation> = Vec::with_capacity(self.undo.len());
+        for operation in self.undo.drain(..) {
+            if let Some(last) = compacted.last_mut() {
+                if last.merge(&operation) {
+                    continue;
+                }
+            }
+            compacted.push(operation);
+        }
+        compacted.extend(recent);
+        self.undo = compacted;
+    }
+
+    /// Moves the last operation to the redo stack and returns what reverts it.
+    pub fn undo(&mut self) -> Option<Operation> {
+        let operation = self.undo.pop()?;
+        let inverse = operation.inverse();
+        self.redo.push(operation);
+        Some(inverse)
+    }
+
+    /// Moves the last undone operation back and returns it to apply again.
+    pub fn redo(&mut self) -> Option<Operation> {
+        let operation = self.redo.pop()?;
+        self.undo.push(operation.clone());
+        Some(operation)
+    }
+}
+
+#[derive(Default)]
+struct EditSession {
+    tab_id: Option<ReflectableUuid>,
+    text: Option<(ReflectableUuid, String)>,
+    rect: Option<(ReflectableUuid, NodeRect)>,
+}
+
+#[derive(Resource, Default)]
+pub struct UndoHistory {
+    tabs: HashMap<ReflectableUuid, TabHistory>,
+    session: EditSession,
+    pending_redraw: Vec<ReflectableUuid>,
+    pending_arrows: Vec<ArrowMeta>,
+}
+
+impl UndoHistory {
+    /// Records an operation for the active tab of the current document.
+    pub fn record(&mut self, app_state: &AppState, operation: Operation) {
+        if let Some(tab_id) = active_tab_id(app_state) {
+            self.tabs.entry(tab_id).or_default().push(operation);
+        }
+    }
+
+    /// Drops the history of every tab of a doc whose content was replaced,
+    /// its operations refer to nodes that may not exist anymore.
+    pub fn forget_doc(&mut self, doc: &Doc) {
+        for tab in doc.tabs.iter() {
+            self.tabs.remove(&tab.id);
+        }
+        self.session = EditSession::default();
+    }
+}
+
+pub enum HistoryEvent {
+    Undo,
+    Redo,
+}
+
+pub fn active_tab_id(app_state: &AppState) -> Option<ReflectableUuid> {
+    let doc = app_state.docs.get(&app_state.current_document?)?;
+    doc.tabs.iter().find(|x| x.is_active).map(|x| x.id)
+}
+
+/// Text of a node without the trailing cursor section.
+pub fn node_text(text: &Text) -> String {
+    let mut sections = text.sections.clone();
+    sections.pop();
+    sections.iter().map(|x| x.value.as_str()).collect()
+}
+
+pub fn undo_keyboard_input(input: Res<Input<KeyCode>>, mut events: EventWriter<HistoryEvent>) {
+    let command = input.any_pressed([KeyCode::RWin, KeyCode::LWin]);
+    let shift = input.any_pressed([KeyCode::RShift, KeyCode::LShift]);
+    if command && input.just_pressed(KeyCode::Z) {
+        if shift {
+            events.send(HistoryEvent::Redo);
+        } else {
+            events.send(HistoryEvent::Undo);
+        }
+    }
+}
+
+/// Turns finished text edits, drags and resizes into operations. They are
+/// recorded once the node is released instead of on every frame.
+pub fn record_edit_operations(
+    ui_state: Res<UiState>,
+    app_state: Res<AppState>,
+    mut history: ResMut<UndoHistory>,
+    containers: Query<(&VeloNodeContainer, &NodeRect)>,
+    texts: Query<(&EditableText, &Text)>,
+) {
+    let tab_id = active_tab_id(&app_state);
+    let history = &mut *history;
+    let session = &mut history.session;
+    if session.tab_id != tab_id {
+        *session = EditSession {
+            tab_id,
+            ..default()
+        };
+    }
+    let Some(tab_id) = tab_id else {
+        return;
+    };
+
+    let editing = ui_state.entity_to_edit;
+    if session.text.as_ref().map(|x| x.0) != editing {
+        if let Some((id, from)) = session.text.take() {
+            if let Some((_, text)) = texts.iter().find(|(x, _)| x.id == id) {
+                let to = node_text(text);
+                if to != from {
+                    history
+                        .tabs
+                        .entry(tab_id)
+                        .or_default()
+                        .push(Operation::EditText { id, from, to });
+                }
+            }
+        }
+        session.text = editing.and_then(|id| {
+            let (_, text) = texts.iter().find(|(x, _)| x.id == id)?;
+            Some((id, node_text(text)))
+        });
+    }
+
+    let transforming = ui_state
+        .hold_entity
+        .or(ui_state.entity_to_resize.map(|(id, _)| id));
+    if session.rect.as_ref().map(|x| x.0) != transforming {
+        if let Some((id, from)) = session.rect.take() {
+            if let Some((_, to)) = containers.iter().find(|(x, _)| x.id == id) {
+                let to = *to;
+                if to != from {
+                    let operation = if (to.width, to.height) == (from.width, from.height) {
+                        Operation::Move { id, from, to }
+                    } else {
+                        Operation::Resize { id, from, to }
+                    };
+                    history.tabs.entry(tab_id).or_default().push(operation);
+                }
+            }
+        }
+        session.rect = transforming.and_then(|id| {
+            let (_, rect) = containers.iter().find(|(x, _)| x.id == id)?;
+            Some((id, *rect))
+        });
+    }
+}
+
+pub fn undo_redo(
+    mut commands: Commands,
+    mut events: EventReader<HistoryEvent>,
+    mut history: ResMut<UndoHistory>,
+    mut ui_state: ResMut<UiState>,
+    app_state: Res<AppState>,
+    static_state: Res<StaticState>,
+    mut containers: Query<(Entity, &VeloNodeContainer, &mut NodeRect, &mut ZIndex)>,
+    mut nodes: Query<(&VeloNode, &mut BackgroundColor), Without<VeloNodeContainer>>,
+    mut texts: Query<(&EditableText, &mut Text)>,
+    mut arrows: Query<(Entity, &ArrowMeta, &mut Visibility)>,
+    mut create_arrow: EventWriter<CreateArrow>,
+    mut redraw_arrow: EventWriter<RedrawArrow>,
+) {
+    // Arrow markers are only placed after layout, so arrows are created and
+    // redrawn a frame after the nodes they connect change
+    for id in history.pending_redraw.drain(..) {
+        redraw_arrow.send(RedrawArrow { id });
+    }
+    for arrow in history.pending_arrows.drain(..) {
+        create_arrow.send(CreateArrow {
+            arrow_type: arrow.arrow_type,
+            start: arrow.start,
+            end: arrow.end,
+        });
+    }
+    let Some(tab_id) = active_tab_id(&app_state) else {
+        return;
+    };
+    for event in events.iter() {
+        let tab_history = history.tabs.entry(tab_id).or_default();
+        let operation = match event {
+            HistoryEvent::Undo => tab_history.undo(),
+            HistoryEvent::Redo => tab_history.redo(),
+        };
+        let Some(operation) = operation else {
+            continue;
+        };
+        *ui_state = UiState::default();
+        history.session = EditSession::default();
+        match operation {
+            Operation::AddNode { node, arrows } => {
+                let font = static_state.font.as_ref().unwrap().clone();
+                let entity = spawn_node(&mut commands, NodeMeta { font, ..node });
+                commands
+                    .entity(static_state.canvas.unwrap())
+                    .add_child(entity);
+                history.pending_arrows.extend(arrows);
+            }
+            Operation::RemoveNode { node, .. } => {
+                for (entity, container, _, _) in containers.iter() {
+                    if container.id == node.id {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+                #[allow(unused)]
+                for (entity, arrow, mut visibility) in arrows.iter_mut() {
+                    if arrow.start.id == node.id || arrow.end.id == node.id {
+                        #[cfg(not(target_arch = "wasm32"))]
+                        {
+                            commands.entity(entity).despawn_recursive();
+                        }
+                        #[cfg(target_arch = "wasm32")]
+                        {
+                            *visibility = Visibility::Hidden;
+                        }
+                    }
+                }
+            }
+            Operation::Move { id, to, .. } | Operation::Resize { id, to, .. } => {
+                for (_, container, mut rect, _) in containers.iter_mut() {
+                    if container.id == id {
+                        *rect = to;
+                    }
+                }
+                history.pending_redraw.push(id);
+            }
+            Operation::Recolor { id, to, .. } => {
+                for (node, mut bg_color) in nodes.iter_mut() {
+                    if node.id == id {
+                        bg_color.0 = to;
+                    }
+                }
+            }
+            Operation::EditText { id, to, .. } => {
+                let font = static_state.font.as_ref().unwrap().clone();
+                for (text_id, mut text) in texts.iter_mut() {
+                    if text_id.id == id {
+                        text.sections = get_sections(to.clone(), font.clone()).0;
+                    }
+                }
+            }
+            Operation::AddArrow { arrow } => {
+                create_arrow.send(CreateArrow {
+                    arrow_type: arrow.arrow_type,
+                    start: arrow.start,
+                    end: arrow.end,
+                });
+            }
+            Operation::RemoveArrow { arrow } => {
+                #[allow(unused)]
+                for (entity, arrow_meta, mut visibility) in arrows.iter_mut() {
+                    if arrow_meta.start.id == arrow.start.id
+                        && arrow_meta.end.id == arrow.end.id
+                        && arrow_meta.arrow_type == arrow.arrow_type
+                    {
+                        #[cfg(not(target_arch = "wasm32"))]
+                        {
+                            commands.entity(entity).despawn_recursive();
+                        }
+                        #[cfg(target_arch = "wasm32")]
+                        {
+                            *visibility = Visibility::Hidden;
+                        }
+                    }
+                }
+            }
+            Operation::ZIndex { id, to, .. } => {
+                for (_, container, _, mut z_index) in containers.iter_mut() {
+                    if container.id == id {
+                        *z_index = ZIndex::Local(to);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(left: f32) -> NodeRect {
+        NodeRect {
+            left: Val::Px(left),
+            bottom: Val::Px(0.),
+            width: Val::Px(100.),
+            height: Val::Px(50.),
+        }
+    }
+
+    fn apply(state: &mut HashMap<ReflectableUuid, NodeRect>, operation: Operation) {
+        if let Operation::Move { id, to, .. } = operation {
+            state.insert(id, to);
+        }
+    }
+
+    fn move_by(id: ReflectableUuid, from: f32, to: f32) -> Operation {
+        Operation::Move {
+            id,
+            from: rect(from),
+            to: rect(to),
+        }
+    }
+
+    #[test]
+    fn undo_then_redo_restores_state() {
+        let id = ReflectableUuid(uuid::Uuid::new_v4());
+        let mut state = HashMap::from([(id, rect(0.))]);
+        let mut history = TabHistory::default();
+        for (from, to) in [(0., 10.), (10., 30.)] {
+            apply(&mut state, move_by(id, from, to));
+            history.push(move_by(id, from, to));
+        }
+        apply(&mut state, history.undo().unwrap());
+        assert_eq!(state[&id], rect(10.));
+        apply(&mut state, history.undo().unwrap());
+        assert_eq!(state[&id], rect(0.));
+        assert!(history.undo().is_none());
+        apply(&mut state, history.redo().unwrap());
+        apply(&mut state, history.redo().unwrap());
+        assert_eq!(state[&id], rect(30.));
+        assert!(history.redo().is_none());
+
+        // A new operation drops what could be redone
+        history.undo();
+        history.push(move_by(id, 10., 20.));
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn compaction_keeps_recent_steps() {
+        let (a, b) = (
+            ReflectableUuid(uuid::Uuid::new_v4()),
+            ReflectableUuid(uuid::Uuid::new_v4()),
+        );
+        let mut history = TabHistory::default();
+        let old = COMPACT_THRESHOLD + 1 - KEEP_UNCOMPACTED;
+        for i in 0..old {
+            history.push(move_by(a, i as f32, i as f32 + 1.));
+        }
+        for i in 0..KEEP_UNCOMPACTED {
+            history.push(move_by(b, i as f32, i as f32 + 1.));
+        }
+        // The moves of `a` became one step, the recent ones are kept as they are
+        assert_eq!(history.undo.len(), KEEP_UNCOMPACTED + 1);
+        let mut state = HashMap::new();
+        for i in (0..KEEP_UNCOMPACTED).rev() {
+            apply(&mut state, history.undo().unwrap());
+            assert_eq!(state[&b], rect(i as f32));
+        }
+        apply(&mut state, history.undo().unwrap());
+        assert_eq!(state[&a], rect(0.));
+        assert!(history.undo().is_none());
+    }
+}
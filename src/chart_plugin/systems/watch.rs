@@ -0,0 +1,126 @@
+use bevy::prelude::*;
+use uuid::Uuid;
+
+use super::ui_helpers::{
+    add_list_item, spawn_modal, DocList, DocListItemContainer, DocListItemText, ModalEntity,
+};
+use super::{ErrorEvent, UndoHistory};
+use crate::errors::VeloError;
+use crate::resources::{AppState, LoadRequest, SaveRequest, StaticState};
+use crate::storage::{DocChange, StorageBackend, Store};
+use crate::utils::ReflectableUuid;
+use crate::{get_timestamp, UiState};
+
+const WATCH_INTERVAL_MS: f64 = 1000.;
+
+/// Picks up docs added, changed or removed outside of the app. Changed docs
+/// are reloaded unless they have unsaved changes, then the user is asked.
+pub fn watch_store(
+    mut commands: Commands,
+    mut store: ResMut<Store>,
+    mut app_state: ResMut<AppState>,
+    mut ui_state: ResMut<UiState>,
+    static_state: Res<StaticState>,
+    doc_list: Query<Entity, With<DocList>>,
+    list_items: Query<(Entity, &DocListItemContainer)>,
+    mut list_texts: Query<(&DocListItemText, &mut Text)>,
+    save_request: Option<Res<SaveRequest>>,
+    load_request: Option<Res<LoadRequest>>,
+    mut history: ResMut<UndoHistory>,
+    mut errors: EventWriter<ErrorEvent>,
+    mut last_poll: Local<f64>,
+) {
+    let now = get_timestamp();
+    if now - *last_poll < WATCH_INTERVAL_MS || save_request.is_some() || load_request.is_some() {
+        return;
+    }
+    *last_poll = now;
+    let font = static_state.font.as_ref().unwrap().clone();
+    for change in store.poll_changes() {
+        match change {
+            DocChange::Updated { id, name } => {
+                if list_items.iter().all(|(_, item)| item.id != id) {
+                    let button = add_list_item(&mut commands, font.clone(), id, name.clone());
+                    commands.entity(doc_list.single()).add_child(button);
+                }
+                for (item, mut text) in list_texts.iter_mut() {
+                    if item.id == id {
+                        text.sections[0].value = name.clone();
+                    }
+                }
+                if !app_state.docs.contains_key(&id) {
+                    continue;
+                }
+                if app_state.docs.is_dirty(&id) {
+                    let modal_id = ReflectableUuid(Uuid::new_v4());
+                    *ui_state = UiState::default();
+                    ui_state.modal_id = Some(modal_id);
+                    let entity = spawn_modal(
+                        &mut commands,
+                        font.clone(),
+                        modal_id,
+                        ModalEntity::Reload(id),
+                    );
+                    commands
+                        .entity(static_state.main_panel.unwrap())
+                        .add_child(entity);
+                } else {
+                    reload_doc(&mut commands, &mut app_state, &mut history, &**store, id);
+                }
+            }
+            DocChange::Removed(id) => {
+                // Unsaved work is kept, the next save writes the file again
+                if app_state.docs.is_dirty(&id) {
+                    continue;
+                }
+                if app_state.current_document == Some(id) {
+                    let Some((_, other)) = list_items.iter().find(|(_, item)| item.id != id) else {
+                        // The canvas needs a doc, keep it and write it again on save
+                        app_state.docs.mark_dirty(id);
+                        let name = app_state.docs.get(&id).map(|doc| doc.name.clone());
+                        errors.send(ErrorEvent(VeloError::Storage(format!(
+                            "{} was removed outside of Velo, the next save writes it again",
+                            name.unwrap_or_else(|| id.0.to_string())
+                        ))));
+                        continue;
+                    };
+                    app_state.current_document = Some(other.id);
+                    commands.insert_resource(LoadRequest {
+                        doc_id: Some(other.id),
+                    });
+                }
+                app_state.docs.remove(&id);
+                for (entity, item) in list_items.iter() {
+                    if item.id == id {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Drops the in-memory copy of a doc and its undo history, so it's read from
+/// the store again the next time it's needed. The current doc is reloaded
+/// right away.
+pub fn reload_doc(
+    commands: &mut Commands,
+    app_state: &mut AppState,
+    history: &mut UndoHistory,
+    store: &dyn StorageBackend,
+    id: ReflectableUuid,
+) {
+    if let Some(doc) = app_state.docs.remove(&id) {
+        history.forget_doc(&doc);
+    }
+    let stored = store.load_doc(id);
+    if let Some(doc) = stored.as_ref() {
+        history.forget_doc(doc);
+    }
+    if app_state.current_document == Some(id) {
+        if let Some(doc) = stored {
+            app_state.docs.insert(id, doc);
+        }
+        commands.insert_resource(LoadRequest { doc_id: Some(id) });
+    }
+}
@@ -10,7 +10,10 @@ use bevy::{
 
 use crate::utils::ReflectableUuid;
 
-use super::{DeleteDoc, DocListItemButton, DocListItemContainer, DocListItemText, GenericButton};
+use super::{
+    DeleteDoc, DocListItemButton, DocListItemContainer, DocListItemDirty, DocListItemText,
+    GenericButton,
+};
 
 pub fn add_list_item(
     commands: &mut Commands,
@@ -85,6 +88,31 @@ pub fn add_list_item(
             Label,
         ))
         .id();
+    let dirty_label = commands
+        .spawn((
+            TextBundle {
+                text: Text {
+                    sections: vec![TextSection {
+                        value: "*".to_string(),
+                        style: TextStyle {
+                            font: font.clone(),
+                            font_size: 18.,
+                            color: Color::BLACK,
+                        },
+                    }],
+                    ..default()
+                },
+                style: Style {
+                    margin: UiRect::all(Val::Px(5.)),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            DocListItemDirty { id },
+            Label,
+        ))
+        .id();
     let del_button = commands
         .spawn((
             ButtonBundle {
@@ -130,6 +158,7 @@ pub fn add_list_item(
         ))
         .id();
     commands.entity(doc_button).add_child(doc_label);
+    commands.entity(doc_button).add_child(dirty_label);
     commands.entity(del_button).add_child(del_label);
     commands.entity(root).add_child(doc_button);
     commands.entity(root).add_child(del_button);
@@ -1,3 +1,4 @@
+use crate::formats::px;
 use crate::utils::ReflectableUuid;
 use bevy::prelude::*;
 
@@ -33,6 +34,9 @@ pub struct SaveDoc;
 #[derive(Component)]
 pub struct DeleteDoc;
 
+#[derive(Component)]
+pub struct BackupAll;
+
 #[derive(Component)]
 pub struct SelectedTab {
     pub id: ReflectableUuid,
@@ -68,6 +72,16 @@ pub struct DocListItemText {
     pub id: ReflectableUuid,
 }
 
+#[derive(Component)]
+pub struct DocListItemDirty {
+    pub id: ReflectableUuid,
+}
+
+#[derive(Component)]
+pub struct ErrorNotice {
+    pub timer: Timer,
+}
+
 #[derive(Component)]
 pub struct ChangeColor {
     pub color: Color,
@@ -111,6 +125,37 @@ pub struct VeloNodeContainer {
     pub id: ReflectableUuid,
 }
 
+/// World position and size of a node container, its `Style` follows the zoom.
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct NodeRect {
+    pub left: Val,
+    pub bottom: Val,
+    pub width: Val,
+    pub height: Val,
+}
+
+impl NodeRect {
+    pub fn rect(&self) -> Rect {
+        let min = Vec2::new(px(self.left), px(self.bottom));
+        Rect::from_corners(min, min + Vec2::new(px(self.width), px(self.height)))
+    }
+}
+
+/// Overview of the tab in a corner of `MainPanel`, `bounds` is the world
+/// rectangle it shows.
+#[derive(Component, Default)]
+pub struct Minimap {
+    pub bounds: Rect,
+}
+
+/// Node or arrow drawn on the minimap.
+#[derive(Component)]
+pub struct MinimapItem;
+
+/// Part of the canvas that is visible in `MainPanel`.
+#[derive(Component)]
+pub struct MinimapViewport;
+
 #[derive(Component, Default, Reflect, Debug)]
 #[reflect(Component)]
 pub struct VeloNode {
@@ -155,6 +200,7 @@ pub struct ModalTop {
 pub enum ModalEntity {
     Document,
     Tab,
+    Reload(ReflectableUuid), // Doc changed on disk while it has unsaved changes
 }
 
 impl std::fmt::Display for ModalEntity {
@@ -162,6 +208,7 @@ impl std::fmt::Display for ModalEntity {
         match self {
             ModalEntity::Document => write!(f, "document"),
             ModalEntity::Tab => write!(f, "tab"),
+            ModalEntity::Reload(_) => write!(f, "document"),
         }
     }
 }
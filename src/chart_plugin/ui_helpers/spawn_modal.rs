@@ -12,6 +12,10 @@ pub fn spawn_modal(
 ) -> Entity {
     let width = 300.;
     let height = 200.;
+    let question = match modal_entity {
+        ModalEntity::Reload(_) => "Changed on disk.\nReload and drop your changes?".to_string(),
+        _ => format!("Are you sure you want to delete {}?", modal_entity),
+    };
     commands
         .spawn((
             NodeBundle {
@@ -60,10 +64,7 @@ pub fn spawn_modal(
                             ..default()
                         })
                         .with_children(|builder| {
-                            builder.spawn(add_rectangle_txt(
-                                font.clone(),
-                                format!("Are you sure you want to delete {}?", modal_entity),
-                            ));
+                            builder.spawn(add_rectangle_txt(font.clone(), question));
                         });
                 });
 
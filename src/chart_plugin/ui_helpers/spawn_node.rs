@@ -6,7 +6,7 @@ use crate::TextPos;
 
 use super::{
     create_arrow_marker, create_rectangle_btn, create_rectangle_txt, create_resize_marker,
-    EditableText, ResizeMarker, VeloNode, VeloNodeContainer,
+    EditableText, NodeRect, ResizeMarker, VeloNode, VeloNodeContainer,
 };
 use crate::canvas::arrow::components::{ArrowConnect, ArrowConnectPos};
 use crate::utils::ReflectableUuid;
@@ -45,6 +45,12 @@ pub fn spawn_node(commands: &mut Commands, item_meta: NodeMeta) -> Entity {
                 ..default()
             },
             VeloNodeContainer { id: item_meta.id },
+            NodeRect {
+                left: item_meta.position.0,
+                bottom: item_meta.position.1,
+                width: item_meta.size.0,
+                height: item_meta.size.1,
+            },
         ))
         .id();
     let button = commands
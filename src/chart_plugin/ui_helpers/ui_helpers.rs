@@ -22,6 +22,11 @@ pub use add_tab::*;
 mod add_list_item;
 pub use add_list_item::*;
 
+/// Side of the arrow connectors at the middle of node sides, at zoom 1.
+pub const ARROW_MARKER_SIZE: f32 = 4.;
+/// Side of the resize handles at node corners, at zoom 1.
+pub const RESIZE_MARKER_SIZE: f32 = 10.;
+
 fn get_marker_style(position: UiRect, size: f32) -> Style {
     Style {
         position_type: PositionType::Absolute,
@@ -108,7 +113,7 @@ fn create_arrow_marker(left: f32, right: f32, top: f32, bottom: f32) -> ButtonBu
                 top: Val::Percent(top),
                 bottom: Val::Percent(bottom),
             },
-            4.,
+            ARROW_MARKER_SIZE,
         ),
         ..default()
     }
@@ -123,7 +128,7 @@ fn create_resize_marker(left: f32, right: f32, top: f32, bottom: f32) -> ButtonB
                 top: Val::Percent(top),
                 bottom: Val::Percent(bottom),
             },
-            10.,
+            RESIZE_MARKER_SIZE,
         ),
         background_color: Color::rgba(0., 0., 0., 0.).into(),
         ..default()
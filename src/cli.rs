@@ -0,0 +1,541 @@
+//! Headless management of stored documents, used by the `velo-cli` binary.
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use ab_glyph::FontArc;
+use base64::{engine::general_purpose, Engine};
+use bevy::prelude::Val;
+use uuid::Uuid;
+
+use crate::components::{Doc, Tab};
+use crate::formats::csv::{is_edges_csv, parse_csv, CSV_FILE_EXTENSION};
+use crate::formats::dot::{checkpoint_to_dot, DOT_FILE_EXTENSIONS};
+use crate::formats::html::{doc_to_html, HTML_FILE_EXTENSION};
+use crate::formats::json_canvas::{checkpoint_to_canvas, CANVAS_FILE_EXTENSION};
+use crate::formats::markdown::{checkpoint_to_markdown, MARKDOWN_FILE_EXTENSION};
+use crate::formats::mermaid::{checkpoint_to_mermaid, MERMAID_FILE_EXTENSIONS};
+use crate::formats::opml::{checkpoint_to_opml, OPML_FILE_EXTENSION};
+use crate::formats::png::{checkpoint_to_png, DEFAULT_FONT_PATH, PNG_FILE_EXTENSION};
+use crate::formats::svg::{checkpoint_to_svg, SVG_FILE_EXTENSION};
+use crate::formats::velo::{
+    checkpoint_to_string, latest_checkpoint, parse_checkpoint, parse_velo_file,
+    velo_file_to_string, Checkpoint, VeloFile, VELO_FILE_EXTENSION,
+};
+use crate::formats::{file_name, tab_parser};
+use crate::resources::Settings;
+use crate::storage::backup::{backup_store, restore_store, RestoreStrategy, BACKUP_FILE_EXTENSION};
+use crate::storage::images::{collect_image_garbage, put_image};
+use crate::storage::{StorageBackend, StorageKind, Store};
+use crate::utils::ReflectableUuid;
+
+const USAGE: &str = "Usage: velo-cli [--dir <path>] <command>
+
+Opens the app database, or the document directory given with --dir.
+
+Commands:
+  list                       list documents and their tabs
+  show <doc> [<tab>]         print nodes and arrows of a tab, the active one by default
+  export <doc> [<file>]      export a document to a .velo or interactive .html file, or
+                             its active tab to a .svg, Mermaid .mmd, Graphviz .dot,
+                             JSON Canvas .canvas, Markdown outline .md or .opml file
+  png <doc> [<tab>]          render a tab to a .png file, the active one by default
+  import <file>              import a .velo, Mermaid .mmd, Graphviz .dot, .excalidraw,
+                             .drawio, .canvas or .opml file as a new document
+  import <nodes.csv> [<edges.csv>]
+                             import a nodes table and an edges table as a new document
+  backup [<file>]            write every document, image and the settings to a .zip file
+  restore <file>             merge a backup into the store
+  rename <doc> <name>        rename a document
+  delete <doc>               delete a document
+  settings [<key> [<value>]] print the settings or one of them, or change one, nested
+                             keys are separated by dots as in csv_columns.text
+
+<doc> and <tab> are ids or names.
+
+Options of png:
+  --scale <factor>           size relative to the canvas, 1 by default
+  --font <path>              font of node text, assets/fonts/iosevka-regular.ttf by default
+  --out <file>               where to write the image, <doc>.png by default
+
+Options of import:
+  --columns <field=header,...>  CSV headers of the fields id, text, color, tags, x, y,
+                             from, to and type, the stored settings by default
+
+Options of restore:
+  --strategy <replace|keep-both>  what happens to documents that are stored already,
+                             the stored settings by default, keep-both unless set";
+
+#[derive(Default)]
+struct PngOptions {
+    scale: Option<f32>,
+    font: Option<String>,
+    out: Option<String>,
+}
+
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let mut args = args.into_iter();
+    let mut kind = StorageKind::Pkv;
+    let mut command = vec![];
+    let mut png_options = PngOptions::default();
+    let mut columns = None;
+    let mut strategy = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dir" => {
+                let dir = args.next().ok_or("--dir needs a path")?;
+                kind = StorageKind::Directory(PathBuf::from(dir));
+            }
+            "--scale" => {
+                let scale = args.next().ok_or("--scale needs a factor")?;
+                let scale = scale.parse().map_err(|_| format!("Bad scale {}", scale))?;
+                png_options.scale = Some(scale);
+            }
+            "--font" => png_options.font = Some(args.next().ok_or("--font needs a path")?),
+            "--out" => png_options.out = Some(args.next().ok_or("--out needs a file")?),
+            "--columns" => columns = Some(args.next().ok_or("--columns needs a mapping")?),
+            "--strategy" => {
+                strategy = match args.next().as_deref() {
+                    Some("replace") => Some(RestoreStrategy::Replace),
+                    Some("keep-both") => Some(RestoreStrategy::KeepBoth),
+                    _ => return Err("--strategy needs replace or keep-both".to_string()),
+                }
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => command.push(arg),
+        }
+    }
+    let mut store = Store::new(&kind).map_err(|e| e.to_string())?;
+    let store = &mut *store;
+    store.migrate().map_err(|e| e.to_string())?;
+    match command.iter().map(|x| x.as_str()).collect::<Vec<_>>()[..] {
+        ["list"] => list(store),
+        ["show", doc] => show(store, doc, None),
+        ["show", doc, tab] => show(store, doc, Some(tab)),
+        ["export", doc] => export(store, doc, None),
+        ["export", doc, file] => export(store, doc, Some(file)),
+        ["png", doc] => png(store, doc, None, png_options),
+        ["png", doc, tab] => png(store, doc, Some(tab), png_options),
+        ["import", file] if extension(file) == CSV_FILE_EXTENSION => {
+            import_csv(store, file, None, columns)
+        }
+        ["import", nodes, edges] => import_csv(store, nodes, Some(edges), columns),
+        ["import", file] => import(store, file),
+        ["backup"] => backup(store, None),
+        ["backup", file] => backup(store, Some(file)),
+        ["restore", file] => restore(store, file, strategy),
+        ["rename", doc, name] => rename(store, doc, name),
+        ["delete", doc] => delete(store, doc),
+        ["settings"] => settings(store, None, None),
+        ["settings", key] => settings(store, Some(key), None),
+        ["settings", key, value] => settings(store, Some(key), Some(value)),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn list(store: &dyn StorageBackend) -> Result<(), String> {
+    let mut names: Vec<_> = store.doc_names().into_iter().collect();
+    names.sort_by_key(|(_, name)| name.to_lowercase());
+    for (id, name) in names {
+        println!("{}  {}", id.0, name);
+        let Some(doc) = store.load_doc(id) else {
+            continue;
+        };
+        for tab in doc.tabs.iter() {
+            let active = if tab.is_active { " (active)" } else { "" };
+            println!("    {}  {}{}", tab.id.0, tab.name, active);
+        }
+    }
+    Ok(())
+}
+
+fn show(store: &dyn StorageBackend, doc: &str, tab: Option<&str>) -> Result<(), String> {
+    let doc = find_doc(store, doc)?;
+    let tab = find_tab(&doc, tab)?;
+    let Some(json) = tab.checkpoints.back() else {
+        println!("{} is empty", tab.name);
+        return Ok(());
+    };
+    let checkpoint = parse_checkpoint(json).map_err(|e| e.to_string())?;
+    println!("Nodes:");
+    for node in checkpoint.nodes.iter() {
+        let color = node.bg_color.as_rgba_f32();
+        println!(
+            "  {}  x {} y {} width {} height {}  z {}  color {:.2},{:.2},{:.2},{:.2}  {:?}",
+            node.id,
+            node.x,
+            node.y,
+            val(node.width),
+            val(node.height),
+            node.z_index,
+            color[0],
+            color[1],
+            color[2],
+            color[3],
+            node.text.text,
+        );
+    }
+    println!("Arrows:");
+    for arrow in checkpoint.arrows.iter() {
+        println!(
+            "  {} {:?} -> {} {:?}  {:?}",
+            arrow.start.id.0, arrow.start.pos, arrow.end.id.0, arrow.end.pos, arrow.arrow_type,
+        );
+    }
+    Ok(())
+}
+
+fn export(store: &dyn StorageBackend, doc: &str, file: Option<&str>) -> Result<(), String> {
+    let doc = find_doc(store, doc)?;
+    let file = file
+        .map(|x| x.to_string())
+        .unwrap_or_else(|| format!("{}.{}", file_name(&doc.name), VELO_FILE_EXTENSION));
+    // Formats other than .velo and .html hold a single tab
+    let checkpoint = || -> Result<Checkpoint, String> {
+        latest_checkpoint(find_tab(&doc, None)?).map_err(|e| e.to_string())
+    };
+    let content = match extension(&file).as_str() {
+        x if MERMAID_FILE_EXTENSIONS.contains(&x) => checkpoint_to_mermaid(&checkpoint()?),
+        x if DOT_FILE_EXTENSIONS.contains(&x) => checkpoint_to_dot(&checkpoint()?),
+        CANVAS_FILE_EXTENSION => checkpoint_to_canvas(&checkpoint()?, |hash| store.get_image(hash))
+            .map_err(|e| e.to_string())?,
+        MARKDOWN_FILE_EXTENSION => checkpoint_to_markdown(&checkpoint()?),
+        OPML_FILE_EXTENSION => checkpoint_to_opml(&checkpoint()?, &doc.name),
+        SVG_FILE_EXTENSION => checkpoint_to_svg(&checkpoint()?, |hash| store.get_image(hash)),
+        HTML_FILE_EXTENSION => {
+            doc_to_html(&doc, |hash| store.get_image(hash)).map_err(|e| e.to_string())?
+        }
+        _ => {
+            let velo_file = VeloFile::from_doc(&doc, |hash| store.get_image(hash))
+                .map_err(|e| e.to_string())?;
+            velo_file_to_string(&velo_file).map_err(|e| e.to_string())?
+        }
+    };
+    std::fs::write(&file, content).map_err(|e| e.to_string())?;
+    println!("Exported {} to {}", doc.name, file);
+    Ok(())
+}
+
+fn png(
+    store: &dyn StorageBackend,
+    doc: &str,
+    tab: Option<&str>,
+    options: PngOptions,
+) -> Result<(), String> {
+    let doc = find_doc(store, doc)?;
+    let tab = find_tab(&doc, tab)?;
+    let checkpoint = latest_checkpoint(tab).map_err(|e| e.to_string())?;
+    let font_path = options.font.as_deref().unwrap_or(DEFAULT_FONT_PATH);
+    let font = std::fs::read(font_path).map_err(|e| format!("{}: {}", font_path, e))?;
+    let font = FontArc::try_from_vec(font).map_err(|e| format!("{}: {}", font_path, e))?;
+    let scale = options.scale.unwrap_or(1.);
+    let png = checkpoint_to_png(&checkpoint, |hash| store.get_image(hash), &font, scale)
+        .map_err(|e| e.to_string())?;
+    let file = options
+        .out
+        .unwrap_or_else(|| format!("{}.{}", file_name(&doc.name), PNG_FILE_EXTENSION));
+    std::fs::write(&file, png).map_err(|e| e.to_string())?;
+    println!("Rendered {} of {} to {}", tab.name, doc.name, file);
+    Ok(())
+}
+
+fn import(store: &mut dyn StorageBackend, file: &str) -> Result<(), String> {
+    let source = std::fs::read_to_string(file).map_err(|e| e.to_string())?;
+    let mut doc = match tab_parser(&extension(file)) {
+        Some(parse) => {
+            let (checkpoint, summary) = parse(&source).map_err(|e| e.to_string())?;
+            if !summary.is_empty() {
+                println!("{}: {}", file, summary);
+            }
+            single_tab_doc(file, &checkpoint)?
+        }
+        None => {
+            let velo_file = parse_velo_file(&source).map_err(|e| e.to_string())?;
+            velo_file.into_doc().map_err(|e| e.to_string())?
+        }
+    };
+    if store.doc_names().contains_key(&doc.id) {
+        doc.id = ReflectableUuid(Uuid::new_v4());
+    }
+    for tab in doc.tabs.iter_mut() {
+        store_inline_images(store, tab)?;
+    }
+    store.save_doc(&doc).map_err(|e| e.to_string())?;
+    println!("Imported {} as {}", doc.name, doc.id.0);
+    Ok(())
+}
+
+// Tables are told apart by their columns like when dropped on the app
+fn import_csv(
+    store: &mut dyn StorageBackend,
+    nodes: &str,
+    edges: Option<&str>,
+    mapping: Option<String>,
+) -> Result<(), String> {
+    let mut columns = store.load_settings().unwrap_or_default().csv_columns;
+    for pair in mapping.iter().flat_map(|x| x.split(',')) {
+        let (field, header) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("Bad column mapping {}, expected field=header", pair))?;
+        columns
+            .set(field.trim(), header.trim())
+            .map_err(|e| e.to_string())?;
+    }
+    let read = |file: &str| std::fs::read_to_string(file).map_err(|e| e.to_string());
+    let mut tables = vec![(nodes, read(nodes)?)];
+    if let Some(edges) = edges {
+        tables.push((edges, read(edges)?));
+    }
+    let (edges, nodes): (Vec<_>, Vec<_>) = tables
+        .iter()
+        .partition(|(_, source)| is_edges_csv(source, &columns));
+    let (file, _) = nodes.first().or(edges.first()).unwrap();
+    let nodes_source = nodes.first().map(|(_, source)| source.as_str());
+    let edges_source = edges.first().map(|(_, source)| source.as_str());
+    let (checkpoint, summary) =
+        parse_csv(nodes_source, edges_source, &columns).map_err(|e| e.to_string())?;
+    if !summary.is_empty() {
+        println!("{}: {}", file, summary);
+    }
+    let doc = single_tab_doc(file, &checkpoint)?;
+    store.save_doc(&doc).map_err(|e| e.to_string())?;
+    println!("Imported {} as {}", doc.name, doc.id.0);
+    Ok(())
+}
+
+// A doc named after the file it was imported from
+fn single_tab_doc(file: &str, checkpoint: &Checkpoint) -> Result<Doc, String> {
+    let name = Path::new(file)
+        .file_stem()
+        .and_then(|x| x.to_str())
+        .unwrap_or("Untitled")
+        .to_string();
+    let json = checkpoint_to_string(checkpoint).map_err(|e| e.to_string())?;
+    Ok(Doc {
+        id: ReflectableUuid(Uuid::new_v4()),
+        name,
+        tabs: vec![Tab {
+            id: ReflectableUuid(Uuid::new_v4()),
+            name: "Tab 1".to_string(),
+            checkpoints: VecDeque::from([json]),
+            is_active: true,
+            view: Default::default(),
+        }],
+        tags: vec![],
+    })
+}
+
+fn extension(file: &str) -> String {
+    let extension = Path::new(file).extension().and_then(|x| x.to_str());
+    extension.unwrap_or_default().to_lowercase()
+}
+
+fn backup(store: &dyn StorageBackend, file: Option<&str>) -> Result<(), String> {
+    let file = file
+        .map(|x| x.to_string())
+        .unwrap_or_else(|| format!("velo-backup.{}", BACKUP_FILE_EXTENSION));
+    let archive = backup_store(store, std::iter::empty()).map_err(|e| e.to_string())?;
+    std::fs::write(&file, archive).map_err(|e| e.to_string())?;
+    println!(
+        "Backed up {} documents to {}",
+        store.doc_names().len(),
+        file
+    );
+    Ok(())
+}
+
+fn restore(
+    store: &mut dyn StorageBackend,
+    file: &str,
+    strategy: Option<RestoreStrategy>,
+) -> Result<(), String> {
+    let strategy =
+        strategy.unwrap_or_else(|| store.load_settings().unwrap_or_default().restore_strategy);
+    let archive = std::fs::read(file).map_err(|e| e.to_string())?;
+    let summary = restore_store(store, &archive, strategy).map_err(|e| e.to_string())?;
+    println!("Restored {}: {}", file, summary);
+    Ok(())
+}
+
+fn rename(store: &mut dyn StorageBackend, doc: &str, name: &str) -> Result<(), String> {
+    let mut doc = find_doc(store, doc)?;
+    doc.name = name.to_string();
+    store.save_doc(&doc).map_err(|e| e.to_string())
+}
+
+fn delete(store: &mut dyn StorageBackend, doc: &str) -> Result<(), String> {
+    let doc = find_doc(store, doc)?;
+    store.remove_doc(doc.id).map_err(|e| e.to_string())?;
+    if store.last_saved() == Some(doc.id) {
+        if let Some(id) = store.doc_names().into_keys().next() {
+            store.set_last_saved(id).map_err(|e| e.to_string())?;
+        }
+    }
+    collect_image_garbage(store, std::iter::empty());
+    println!("Deleted {}", doc.name);
+    Ok(())
+}
+
+fn settings(
+    store: &mut dyn StorageBackend,
+    key: Option<&str>,
+    value: Option<&str>,
+) -> Result<(), String> {
+    let settings = store.load_settings().unwrap_or_default();
+    let mut json = serde_json::to_value(&settings).map_err(|e| e.to_string())?;
+    let Some(key) = key else {
+        println!("{}", serde_json::to_string_pretty(&json).unwrap());
+        return Ok(());
+    };
+    let Some(field) = json.pointer_mut(&format!("/{}", key.replace('.', "/"))) else {
+        return Err(format!("Unknown setting {}", key));
+    };
+    let Some(value) = value else {
+        println!("{}", field);
+        return Ok(());
+    };
+    // Words don't need quotes, as in `settings grid lines`
+    *field = serde_json::from_str(value).unwrap_or_else(|_| value.into());
+    let settings: Settings =
+        serde_json::from_value(json).map_err(|e| format!("Bad value for {}: {}", key, e))?;
+    store.save_settings(&settings).map_err(|e| e.to_string())?;
+    println!("{} = {}", key, value);
+    Ok(())
+}
+
+/// Finds a doc by id or by name, names have to be unique.
+fn find_doc(store: &dyn StorageBackend, doc: &str) -> Result<Doc, String> {
+    let names = store.doc_names();
+    let mut ids: Vec<_> = names
+        .iter()
+        .filter(|(id, name)| id.0.to_string() == doc || name.as_str() == doc)
+        .map(|(id, _)| *id)
+        .collect();
+    match ids.len() {
+        0 => Err(format!("No document {}", doc)),
+        1 => store
+            .load_doc(ids.pop().unwrap())
+            .ok_or(format!("Document {} can't be read", doc)),
+        _ => Err(format!("{} is ambiguous, use the document id", doc)),
+    }
+}
+
+// The active tab if none is given
+fn find_tab<'a>(doc: &'a Doc, tab: Option<&str>) -> Result<&'a Tab, String> {
+    match tab {
+        Some(tab) => doc
+            .tabs
+            .iter()
+            .find(|x| x.id.0.to_string() == tab || x.name == tab)
+            .ok_or(format!("No tab {} in {}", tab, doc.name)),
+        None => doc
+            .tabs
+            .iter()
+            .find(|x| x.is_active)
+            .ok_or(format!("{} has no tabs", doc.name)),
+    }
+}
+
+// Files carry images inline, the store keeps them by hash
+fn store_inline_images(store: &mut dyn StorageBackend, tab: &mut Tab) -> Result<(), String> {
+    for json in tab.checkpoints.iter_mut() {
+        let mut checkpoint = parse_checkpoint(json).map_err(|e| e.to_string())?;
+        for (node_id, image) in checkpoint.inline_images.drain() {
+            let png = general_purpose::STANDARD
+                .decode(image.as_bytes())
+                .map_err(|e| e.to_string())?;
+            let hash = put_image(store, &png).map_err(|e| e.to_string())?;
+            checkpoint.images.insert(node_id, hash);
+        }
+        *json = checkpoint_to_string(&checkpoint).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn val(val: Val) -> String {
+    match val {
+        Val::Px(x) => format!("{}px", x),
+        Val::Percent(x) => format!("{}%", x),
+        _ => format!("{:?}", val),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::GridStyle;
+    use crate::storage::memory::MemoryStore;
+
+    #[test]
+    fn settings_are_changed_by_key() {
+        let mut store = MemoryStore::default();
+        settings(&mut store, Some("grid"), Some("lines")).unwrap();
+        settings(&mut store, Some("grid_spacing"), Some("25")).unwrap();
+        settings(&mut store, Some("csv_columns.text"), Some("Name")).unwrap();
+        let stored = store.load_settings().unwrap();
+        assert_eq!(stored.grid, GridStyle::Lines);
+        assert_eq!(stored.grid_spacing, 25.);
+        assert_eq!(stored.csv_columns.text, "Name");
+
+        assert!(settings(&mut store, Some("grid"), Some("stripes")).is_err());
+        assert!(settings(&mut store, Some("gird"), Some("dots")).is_err());
+        assert_eq!(store.load_settings().unwrap().grid, GridStyle::Lines);
+    }
+
+    #[test]
+    fn inline_images_are_moved_to_the_store() {
+        let mut store = MemoryStore::default();
+        let mut checkpoint = Checkpoint::default();
+        checkpoint
+            .inline_images
+            .insert("node".to_string(), general_purpose::STANDARD.encode(b"png"));
+        let mut tab = Tab {
+            id: ReflectableUuid(Uuid::new_v4()),
+            name: "Tab 1".to_string(),
+            checkpoints: VecDeque::from([checkpoint_to_string(&checkpoint).unwrap()]),
+            is_active: true,
+            view: Default::default(),
+        };
+        store_inline_images(&mut store, &mut tab).unwrap();
+
+        let checkpoint = parse_checkpoint(&tab.checkpoints[0]).unwrap();
+        assert!(checkpoint.inline_images.is_empty());
+        let hash = &checkpoint.images["node"];
+        assert!(store.image_hashes().contains(hash));
+
+        tab.checkpoints[0] = r#"{"nodes":[],"arrows":[],"inline_images":{"node":"%"}}"#.into();
+        assert!(store_inline_images(&mut store, &mut tab).is_err());
+    }
+
+    #[test]
+    fn imported_files_are_exported_and_rendered() {
+        let root = std::env::temp_dir().join(format!("velo-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = |name: &str| root.join(name).to_string_lossy().into_owned();
+        let velo_cli = |args: &[&str]| {
+            let mut all = vec!["--dir".to_string(), path("store")];
+            all.extend(args.iter().map(|x| x.to_string()));
+            run(all)
+        };
+        std::fs::write(
+            path("flow.mmd"),
+            "flowchart TD\n    a[\"one\"] --> b[\"two\"]\n",
+        )
+        .unwrap();
+
+        velo_cli(&["import", &path("flow.mmd")]).unwrap();
+        velo_cli(&["export", "flow", &path("flow.md")]).unwrap();
+        let markdown = std::fs::read_to_string(path("flow.md")).unwrap();
+        assert!(markdown.contains("one") && markdown.contains("two"));
+
+        velo_cli(&["png", "flow", "--scale", "2", "--out", &path("flow.png")]).unwrap();
+        let png = std::fs::read(path("flow.png")).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+
+        assert!(velo_cli(&["export", "missing"]).is_err());
+        assert!(velo_cli(&["import", &path("missing.mmd")]).is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,3 +1,4 @@
+use crate::resources::CanvasView;
 use crate::utils::ReflectableUuid;
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -5,12 +6,18 @@ use std::collections::VecDeque;
 #[derive(Component)]
 pub struct MainCamera;
 
+/// Grid drawn behind the nodes and arrows, see `Settings::grid`.
+#[derive(Component)]
+pub struct Grid;
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Tab {
     pub is_active: bool,
     pub id: ReflectableUuid,
     pub name: String,
     pub checkpoints: VecDeque<String>,
+    #[serde(default)]
+    pub view: CanvasView,
 }
 
 #[derive(Default, Serialize, Deserialize, Clone, Debug)]
@@ -0,0 +1,34 @@
+use std::fmt;
+
+use crate::utils::ReflectableUuid;
+
+#[derive(Debug)]
+pub enum VeloError {
+    DocNotFound(ReflectableUuid),
+    Checkpoint(serde_json::Error),
+    Image(String),
+    Storage(String),
+    Export(String),
+    Import(String),
+}
+
+impl fmt::Display for VeloError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VeloError::DocNotFound(id) => write!(f, "Document {} not found", id.0),
+            VeloError::Checkpoint(error) => write!(f, "Corrupted checkpoint: {}", error),
+            VeloError::Image(error) => write!(f, "Image error: {}", error),
+            VeloError::Storage(error) => write!(f, "Storage error: {}", error),
+            VeloError::Export(error) => write!(f, "Export failed: {}", error),
+            VeloError::Import(error) => write!(f, "Import failed: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for VeloError {}
+
+impl From<serde_json::Error> for VeloError {
+    fn from(error: serde_json::Error) -> Self {
+        VeloError::Checkpoint(error)
+    }
+}
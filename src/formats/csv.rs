@@ -0,0 +1,338 @@
+//! CSV import of a nodes table and an optional edges table, e.g. service
+//! inventories exported from spreadsheets.
+//!
+//! Columns are found by header name, case insensitive, as configured in
+//! `CsvColumns`. Nodes are laid out in layers along the edges unless every
+//! row has a position. Edges whose ends are not in the nodes table are
+//! skipped, without a nodes table their ends become the nodes.
+use std::collections::HashMap;
+
+use ::csv::{ReaderBuilder, StringRecord, Trim};
+use bevy::prelude::{Rect, Vec2};
+use serde::{Deserialize, Serialize};
+
+use super::layout::{connect, layered_layout, move_to_origin, new_node, Direction, NODE_SIZE};
+use super::velo::Checkpoint;
+use super::{node_rect, parse_color, ImportSummary};
+use crate::canvas::arrow::components::ArrowType;
+use crate::errors::VeloError;
+use crate::JsonNode;
+
+pub const CSV_FILE_EXTENSION: &str = "csv";
+
+/// Header names of the columns read from the nodes and edges tables.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct CsvColumns {
+    pub id: String,         // Falls back to the text when missing
+    pub text: String,       // Falls back to the id when missing
+    pub color: String,      // Hex or a color name
+    pub tags: String,       // Separated by ",", ";" or "|"
+    pub x: String,          // Left side in canvas pixels
+    pub y: String,          // Bottom side in canvas pixels, grows upwards
+    pub from: String,       // Id of the node an edge starts at
+    pub to: String,         // Id of the node an edge ends at
+    pub arrow_type: String, // Arrow, Line, DoubleArrow, ... or ->, --, <->
+}
+
+impl Default for CsvColumns {
+    fn default() -> Self {
+        Self {
+            id: "id".to_string(),
+            text: "text".to_string(),
+            color: "color".to_string(),
+            tags: "tags".to_string(),
+            x: "x".to_string(),
+            y: "y".to_string(),
+            from: "from".to_string(),
+            to: "to".to_string(),
+            arrow_type: "type".to_string(),
+        }
+    }
+}
+
+impl CsvColumns {
+    /// Sets the column of a field by name, as in `text=Service`.
+    pub fn set(&mut self, field: &str, column: &str) -> Result<(), VeloError> {
+        let target = match field {
+            "id" => &mut self.id,
+            "text" => &mut self.text,
+            "color" => &mut self.color,
+            "tags" => &mut self.tags,
+            "x" => &mut self.x,
+            "y" => &mut self.y,
+            "from" => &mut self.from,
+            "to" => &mut self.to,
+            "type" | "arrow_type" => &mut self.arrow_type,
+            _ => {
+                return Err(VeloError::Import(format!(
+                "unknown CSV field {}, expected one of id, text, color, tags, x, y, from, to, type",
+                field
+            )))
+            }
+        };
+        *target = column.to_string();
+        Ok(())
+    }
+}
+
+/// Whether a table has the edge columns, to tell the two files apart.
+pub fn is_edges_csv(source: &str, columns: &CsvColumns) -> bool {
+    match Table::read(source) {
+        Ok(table) => table.column(&columns.from).is_some() && table.column(&columns.to).is_some(),
+        Err(_) => false,
+    }
+}
+
+pub fn parse_csv(
+    nodes: Option<&str>,
+    edges: Option<&str>,
+    columns: &CsvColumns,
+) -> Result<(Checkpoint, ImportSummary), VeloError> {
+    let mut summary = ImportSummary::default();
+    let mut checkpoint = Checkpoint::default();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut positions = vec![];
+
+    if let Some(source) = nodes {
+        let table = Table::read(source)?;
+        let id_column = table.column(&columns.id);
+        let text_column = table.column(&columns.text);
+        if id_column.is_none() && text_column.is_none() {
+            return Err(VeloError::Import(format!(
+                "nodes CSV needs an {} or {} column",
+                columns.id, columns.text
+            )));
+        }
+        let color_column = table.column(&columns.color);
+        let tags_column = table.column(&columns.tags);
+        let (x_column, y_column) = (table.column(&columns.x), table.column(&columns.y));
+        for row in table.rows.iter() {
+            let text = Table::get(row, text_column.or(id_column));
+            let id = Table::get(row, id_column.or(text_column));
+            if id.is_empty() && text.is_empty() {
+                continue;
+            }
+            if index.contains_key(id) {
+                summary.skip("duplicate node");
+                continue;
+            }
+            let mut node = new_node(text, Rect::from_corners(Vec2::ZERO, NODE_SIZE));
+            let color = Table::get(row, color_column);
+            if !color.is_empty() {
+                match parse_color(color) {
+                    Some(color) => node.bg_color = color,
+                    None => summary.skip("unknown color"),
+                }
+            }
+            node.tags = Table::get(row, tags_column)
+                .split([',', ';', '|'])
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect();
+            let coordinate = |column| Table::get(row, column).parse::<f32>().ok();
+            positions.push(coordinate(x_column).zip(coordinate(y_column)));
+            index.insert(id.to_string(), checkpoint.nodes.len());
+            checkpoint.nodes.push(node);
+        }
+    }
+
+    let mut edges_found = vec![];
+    if let Some(source) = edges {
+        let table = Table::read(source)?;
+        let (Some(from_column), Some(to_column)) =
+            (table.column(&columns.from), table.column(&columns.to))
+        else {
+            return Err(VeloError::Import(format!(
+                "edges CSV needs {} and {} columns",
+                columns.from, columns.to
+            )));
+        };
+        let type_column = table.column(&columns.arrow_type);
+        for row in table.rows.iter() {
+            let Some(arrow_type) = parse_arrow_type(Table::get(row, type_column)) else {
+                summary.skip("edge with unknown type");
+                continue;
+            };
+            let mut end = |id: &str| {
+                if let Some(i) = index.get(id) {
+                    return Some(*i);
+                }
+                if nodes.is_some() || id.is_empty() {
+                    return None;
+                }
+                index.insert(id.to_string(), checkpoint.nodes.len());
+                checkpoint
+                    .nodes
+                    .push(new_node(id, Rect::from_corners(Vec2::ZERO, NODE_SIZE)));
+                positions.push(None);
+                Some(checkpoint.nodes.len() - 1)
+            };
+            match (
+                end(Table::get(row, Some(from_column))),
+                end(Table::get(row, Some(to_column))),
+            ) {
+                (Some(from), Some(to)) => edges_found.push((from, to, arrow_type)),
+                _ => summary.skip("edge to unknown node"),
+            }
+        }
+    }
+
+    if !positions.is_empty() && positions.iter().all(|x| x.is_some()) {
+        for (node, position) in checkpoint.nodes.iter_mut().zip(positions.iter()) {
+            move_node(node, position.unwrap().into());
+        }
+        let negative = checkpoint.nodes.iter().any(|node| {
+            let min = node_rect(node).min;
+            min.x < 0. || min.y < 0.
+        });
+        if negative {
+            move_to_origin(&mut checkpoint);
+        }
+    } else {
+        let pairs: Vec<_> = edges_found
+            .iter()
+            .map(|(from, to, _)| (*from, *to))
+            .collect();
+        let layout = layered_layout(checkpoint.nodes.len(), &pairs, Direction::Down);
+        for (node, position) in checkpoint.nodes.iter_mut().zip(layout) {
+            move_node(node, position);
+        }
+    }
+    checkpoint.arrows = edges_found
+        .iter()
+        .map(|(from, to, arrow_type)| {
+            connect(
+                &checkpoint.nodes[*from],
+                &checkpoint.nodes[*to],
+                *arrow_type,
+            )
+        })
+        .collect();
+    Ok((checkpoint, summary))
+}
+
+fn move_node(node: &mut JsonNode, bottom_left: Vec2) {
+    node.x = bottom_left.x;
+    node.y = bottom_left.y;
+}
+
+// Empty means the default arrow
+fn parse_arrow_type(value: &str) -> Option<ArrowType> {
+    let arrow_type = match value {
+        "" | "->" => ArrowType::Arrow,
+        "--" => ArrowType::Line,
+        "<->" => ArrowType::DoubleArrow,
+        _ => match value.to_lowercase().replace([' ', '_', '-'], "").as_str() {
+            "arrow" => ArrowType::Arrow,
+            "line" => ArrowType::Line,
+            "doublearrow" => ArrowType::DoubleArrow,
+            "parallelline" => ArrowType::ParallelLine,
+            "parallelarrow" => ArrowType::ParallelArrow,
+            "paralleldoublearrow" => ArrowType::ParallelDoubleArrow,
+            _ => return None,
+        },
+    };
+    Some(arrow_type)
+}
+
+struct Table {
+    headers: StringRecord,
+    rows: Vec<StringRecord>,
+}
+
+impl Table {
+    fn read(source: &str) -> Result<Self, VeloError> {
+        let error = |error: ::csv::Error| VeloError::Import(error.to_string());
+        // Spreadsheets often save with a byte order mark
+        let source = source.trim_start_matches('\u{feff}');
+        let mut reader = ReaderBuilder::new()
+            .flexible(true)
+            .trim(Trim::All)
+            .from_reader(source.as_bytes());
+        let headers = reader.headers().map_err(error)?.clone();
+        let rows = reader
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(error)?;
+        Ok(Self { headers, rows })
+    }
+
+    fn column(&self, name: &str) -> Option<usize> {
+        self.headers
+            .iter()
+            .position(|header| header.eq_ignore_ascii_case(name.trim()))
+    }
+
+    // Missing columns and short rows read as empty
+    fn get(row: &StringRecord, column: Option<usize>) -> &str {
+        column.and_then(|i| row.get(i)).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::Color;
+
+    const NODES: &str = "Service,Name,Owner colour,Labels
+api,API gateway,#ff0000,edge; public
+auth,Auth,lightblue,
+db,Postgres,,storage
+api,Duplicate,,
+";
+    const EDGES: &str = "from,to,type
+api,auth,->
+auth,db,line
+api,cache,
+api,db,wormhole
+";
+
+    #[test]
+    fn mapped_columns_are_imported() {
+        let mut columns = CsvColumns::default();
+        columns.set("id", "service").unwrap();
+        columns.set("text", "Name").unwrap();
+        columns.set("color", "Owner colour").unwrap();
+        columns.set("tags", "labels").unwrap();
+        assert!(!is_edges_csv(NODES, &columns));
+        assert!(is_edges_csv(EDGES, &columns));
+
+        let (checkpoint, summary) = parse_csv(Some(NODES), Some(EDGES), &columns).unwrap();
+        let texts: Vec<_> = checkpoint
+            .nodes
+            .iter()
+            .map(|x| x.text.text.as_str())
+            .collect();
+        assert_eq!(texts, vec!["API gateway", "Auth", "Postgres"]);
+        assert_eq!(checkpoint.nodes[0].bg_color, Color::rgb_u8(255, 0, 0));
+        assert_eq!(checkpoint.nodes[0].tags, vec!["edge", "public"]);
+        assert_eq!(checkpoint.nodes[2].tags, vec!["storage"]);
+        let types: Vec<_> = checkpoint.arrows.iter().map(|x| x.arrow_type).collect();
+        assert_eq!(types, vec![ArrowType::Arrow, ArrowType::Line]);
+        assert_eq!(
+            summary.to_string(),
+            "skipped unsupported elements: 1 duplicate node, 1 edge to unknown node, 1 edge with unknown type"
+        );
+        // Layers follow the edges downwards
+        let rects: Vec<_> = checkpoint.nodes.iter().map(node_rect).collect();
+        assert!(rects[0].min.y > rects[1].max.y);
+        assert!(rects[1].min.y > rects[2].max.y);
+    }
+
+    #[test]
+    fn positions_and_edges_only() {
+        let nodes = "id,text,x,y\na,A,0,-100\nb,B,300,0\n";
+        let (checkpoint, _) = parse_csv(Some(nodes), None, &CsvColumns::default()).unwrap();
+        let rects: Vec<_> = checkpoint.nodes.iter().map(node_rect).collect();
+        assert_eq!(rects[1].min - rects[0].min, Vec2::new(300., 100.));
+
+        let (checkpoint, summary) = parse_csv(None, Some(EDGES), &CsvColumns::default()).unwrap();
+        assert_eq!(checkpoint.nodes.len(), 4);
+        assert_eq!(checkpoint.arrows.len(), 3);
+        assert_eq!(
+            summary.to_string(),
+            "skipped unsupported elements: 1 edge with unknown type"
+        );
+    }
+}
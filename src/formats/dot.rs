@@ -0,0 +1,581 @@
+//! Graphviz DOT import and export.
+//!
+//! Export pins every node with a `pos` attribute in points, which are taken
+//! as canvas pixels, so a round-trip keeps the layout. Import reads `pos`,
+//! `width` and `height` when every node has them and lays the graph out
+//! otherwise. Labels and fill colors are kept, other attributes are dropped.
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use bevy::prelude::{Color, Rect, Vec2};
+
+use super::layout::{connect, layered_layout, new_node, Direction, NODE_SIZE};
+use super::velo::Checkpoint;
+use super::{node_rect, parse_color};
+use crate::canvas::arrow::components::ArrowType;
+use crate::errors::VeloError;
+
+pub const DOT_FILE_EXTENSIONS: [&str; 2] = ["dot", "gv"];
+const POINTS_PER_INCH: f32 = 72.;
+
+pub fn checkpoint_to_dot(checkpoint: &Checkpoint) -> String {
+    let mut nodes: Vec<_> = checkpoint.nodes.iter().collect();
+    nodes.sort_by(|a, b| {
+        let (a, b) = (node_rect(a), node_rect(b));
+        b.max
+            .y
+            .total_cmp(&a.max.y)
+            .then(a.min.x.total_cmp(&b.min.x))
+    });
+    let ids: HashMap<_, _> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id, format!("n{}", i + 1)))
+        .collect();
+
+    let mut dot = "digraph {\n    node [shape=box, style=filled];\n".to_string();
+    for node in nodes.iter() {
+        let rect = node_rect(node);
+        let center = rect.center();
+        writeln!(
+            dot,
+            "    {} [label=\"{}\", fillcolor=\"{}\", pos=\"{},{}!\", width={}, height={}];",
+            ids[&node.id],
+            escape(&node.text.text),
+            hex(node.bg_color),
+            center.x,
+            center.y,
+            rect.width() / POINTS_PER_INCH,
+            rect.height() / POINTS_PER_INCH,
+        )
+        .unwrap();
+    }
+    for arrow in checkpoint.arrows.iter() {
+        let (Some(start), Some(end)) = (ids.get(&arrow.start.id.0), ids.get(&arrow.end.id.0))
+        else {
+            continue;
+        };
+        let attributes = match arrow.arrow_type {
+            ArrowType::Line | ArrowType::ParallelLine => " [dir=none]",
+            ArrowType::Arrow | ArrowType::ParallelArrow => "",
+            ArrowType::DoubleArrow | ArrowType::ParallelDoubleArrow => " [dir=both]",
+        };
+        writeln!(dot, "    {} -> {}{};", start, end, attributes).unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Id(String),
+    Edge,
+    Punct(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    let mut line_start = true;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line_start = true;
+            i += 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        // Preprocessor output lines start with #
+        if (c == '#' && line_start) || starts(&chars, i, "//") {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        line_start = false;
+        if starts(&chars, i, "/*") {
+            i += 2;
+            while i < chars.len() && !starts(&chars, i, "*/") {
+                i += 1;
+            }
+            i += 2;
+        } else if starts(&chars, i, "->") || starts(&chars, i, "--") {
+            tokens.push(Token::Edge);
+            i += 2;
+        } else if c == '"' {
+            let mut value = String::new();
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                // Escapes are kept as is and decoded with the label, only
+                // an escaped quote or backslash can't end the string
+                if chars[i] == '\\' && matches!(chars.get(i + 1), Some('"' | '\\')) {
+                    value.push(chars[i]);
+                    i += 1;
+                }
+                value.push(chars[i]);
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err("string is never closed".to_string());
+            }
+            i += 1;
+            // "a" + "b" is one string
+            if tokens.last() == Some(&Token::Punct('+')) {
+                tokens.pop();
+                match tokens.last_mut() {
+                    Some(Token::Id(previous)) => previous.push_str(&value),
+                    _ => return Err("\"+\" without a string before it".to_string()),
+                }
+            } else {
+                tokens.push(Token::Id(value));
+            }
+        } else if c == '<' {
+            // HTML label, kept as is
+            let mut depth = 0;
+            let start = i;
+            while i < chars.len() {
+                match chars[i] {
+                    '<' => depth += 1,
+                    '>' => depth -= 1,
+                    _ => {}
+                }
+                i += 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            if depth != 0 {
+                return Err("HTML label is never closed".to_string());
+            }
+            tokens.push(Token::Id(chars[start + 1..i - 1].iter().collect()));
+        } else if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || "_.-".contains(chars[i])) {
+                if starts(&chars, i, "->") || starts(&chars, i, "--") {
+                    break;
+                }
+                i += 1;
+            }
+            tokens.push(Token::Id(chars[start..i].iter().collect()));
+        } else if c == '+' {
+            tokens.push(Token::Punct('+'));
+            i += 1;
+        } else if "{}[];,=:".contains(c) {
+            tokens.push(Token::Punct(c));
+            i += 1;
+        } else {
+            return Err(format!("unexpected \"{}\"", c));
+        }
+    }
+    Ok(tokens)
+}
+
+fn starts(chars: &[char], i: usize, pattern: &str) -> bool {
+    pattern
+        .chars()
+        .enumerate()
+        .all(|(j, c)| chars.get(i + j) == Some(&c))
+}
+
+type Attributes = HashMap<String, String>;
+
+#[derive(Default)]
+struct Graph {
+    directed: bool,
+    index: HashMap<String, usize>,
+    names: Vec<String>,
+    attributes: Vec<Attributes>,
+    edges: Vec<(usize, usize, Attributes)>,
+    graph_attributes: Attributes,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(&Token::Punct(c));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expected \"{}\", got {:?}", c, self.peek()))
+        }
+    }
+
+    fn id(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Id(id)) => Ok(id),
+            token => Err(format!("expected an id, got {:?}", token)),
+        }
+    }
+
+    fn keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Id(id)) if id.eq_ignore_ascii_case(keyword))
+    }
+
+    // `[a=1, b=2][c=3]`
+    fn attributes(&mut self, attributes: &mut Attributes) -> Result<(), String> {
+        while self.eat('[') {
+            while !self.eat(']') {
+                let key = self.id()?;
+                self.expect('=')?;
+                let value = self.id()?;
+                attributes.insert(key, value);
+                let _ = self.eat(',') || self.eat(';');
+            }
+        }
+        Ok(())
+    }
+
+    fn statements(
+        &mut self,
+        graph: &mut Graph,
+        node_defaults: &Attributes,
+        edge_defaults: &Attributes,
+    ) -> Result<Vec<usize>, String> {
+        let (mut node_defaults, mut edge_defaults) = (node_defaults.clone(), edge_defaults.clone());
+        let mut mentioned = vec![];
+        while !self.eat('}') {
+            if self.peek().is_none() {
+                return Err("\"{\" is never closed".to_string());
+            }
+            if self.eat(';') {
+                continue;
+            }
+            if self.keyword("graph") || self.keyword("node") || self.keyword("edge") {
+                let keyword = self.id()?.to_lowercase();
+                let target = match keyword.as_str() {
+                    "node" => &mut node_defaults,
+                    "edge" => &mut edge_defaults,
+                    _ => &mut graph.graph_attributes,
+                };
+                self.attributes(target)?;
+                continue;
+            }
+            if matches!(self.tokens.get(self.pos + 1), Some(Token::Punct('=')))
+                && !self.keyword("subgraph")
+            {
+                let key = self.id()?;
+                self.pos += 1;
+                let value = self.id()?;
+                graph.graph_attributes.insert(key, value);
+                continue;
+            }
+            // Node statement or a chain of edges
+            let mut group = self.operand(graph, &node_defaults, &edge_defaults)?;
+            mentioned.extend(group.iter());
+            let mut chain = vec![group.clone()];
+            while self.peek() == Some(&Token::Edge) {
+                self.pos += 1;
+                group = self.operand(graph, &node_defaults, &edge_defaults)?;
+                mentioned.extend(group.iter());
+                chain.push(group.clone());
+            }
+            let mut attributes = Attributes::new();
+            self.attributes(&mut attributes)?;
+            if chain.len() == 1 {
+                for node in chain[0].iter() {
+                    graph.attributes[*node].extend(attributes.clone());
+                }
+            } else {
+                let mut edge_attributes = edge_defaults.clone();
+                edge_attributes.extend(attributes);
+                for pair in chain.windows(2) {
+                    for from in pair[0].iter() {
+                        for to in pair[1].iter() {
+                            graph.edges.push((*from, *to, edge_attributes.clone()));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(mentioned)
+    }
+
+    // A node id or a subgraph standing for all of its nodes
+    fn operand(
+        &mut self,
+        graph: &mut Graph,
+        node_defaults: &Attributes,
+        edge_defaults: &Attributes,
+    ) -> Result<Vec<usize>, String> {
+        if self.keyword("subgraph") {
+            self.pos += 1;
+            if !matches!(self.peek(), Some(Token::Punct('{'))) {
+                self.id()?;
+            }
+        }
+        if self.eat('{') {
+            return self.statements(graph, node_defaults, edge_defaults);
+        }
+        let name = self.id()?;
+        // Ports like `a:n` don't matter for rectangles
+        while self.eat(':') {
+            self.id()?;
+        }
+        let next = graph.names.len();
+        let node = *graph.index.entry(name.clone()).or_insert(next);
+        if node == next {
+            graph.names.push(name);
+            graph.attributes.push(node_defaults.clone());
+        }
+        Ok(vec![node])
+    }
+}
+
+pub fn parse_dot(source: &str) -> Result<Checkpoint, VeloError> {
+    let error = |error: String| VeloError::Import(error);
+    let mut parser = Parser {
+        tokens: tokenize(source).map_err(error)?,
+        pos: 0,
+    };
+    if parser.keyword("strict") {
+        parser.pos += 1;
+    }
+    let mut graph = Graph::default();
+    if parser.keyword("digraph") {
+        graph.directed = true;
+    } else if !parser.keyword("graph") {
+        return Err(error("expected a graph or digraph".to_string()));
+    }
+    parser.pos += 1;
+    if !matches!(parser.peek(), Some(Token::Punct('{'))) {
+        parser.id().map_err(error)?;
+    }
+    parser.expect('{').map_err(error)?;
+    let defaults = Attributes::new();
+    parser
+        .statements(&mut graph, &defaults, &defaults)
+        .map_err(error)?;
+    Ok(build_checkpoint(&graph))
+}
+
+fn build_checkpoint(graph: &Graph) -> Checkpoint {
+    let positions: Option<Vec<Vec2>> = graph.attributes.iter().map(position).collect();
+    let positions = positions.unwrap_or_else(|| {
+        let edges: Vec<_> = graph
+            .edges
+            .iter()
+            .map(|(from, to, _)| (*from, *to))
+            .collect();
+        let direction = match graph.graph_attributes.get("rankdir").map(|x| x.as_str()) {
+            Some("LR") => Direction::Right,
+            Some("RL") => Direction::Left,
+            Some("BT") => Direction::Up,
+            _ => Direction::Down,
+        };
+        layered_layout(graph.names.len(), &edges, direction)
+            .into_iter()
+            .map(|corner| corner + NODE_SIZE / 2.)
+            .collect()
+    });
+
+    let mut nodes = vec![];
+    for (i, attributes) in graph.attributes.iter().enumerate() {
+        let inches = |key: &str| {
+            let value = attributes.get(key)?.parse::<f32>().ok()?;
+            Some((value * POINTS_PER_INCH * 100.).round() / 100.)
+        };
+        let size = Vec2::new(
+            inches("width").unwrap_or(NODE_SIZE.x),
+            inches("height").unwrap_or(NODE_SIZE.y),
+        );
+        let label = match attributes.get("label") {
+            Some(label) => unescape(label, &graph.names[i]),
+            // Quoted names only escape quotes
+            None => graph.names[i].replace("\\\"", "\""),
+        };
+        let mut node = new_node(&label, Rect::from_center_size(positions[i], size));
+        if let Some(color) = fill_color(attributes) {
+            node.bg_color = color;
+        }
+        nodes.push(node);
+    }
+
+    let mut arrows = vec![];
+    for (from, to, attributes) in graph.edges.iter() {
+        let default_dir = if graph.directed { "forward" } else { "none" };
+        let dir = attributes.get("dir").map_or(default_dir, |x| x.as_str());
+        let head = attributes.get("arrowhead").map(|x| x.as_str()) != Some("none");
+        let (from, to, arrow_type) = match dir {
+            "both" => (from, to, ArrowType::DoubleArrow),
+            "back" => (to, from, ArrowType::Arrow),
+            "forward" if head => (from, to, ArrowType::Arrow),
+            _ => (from, to, ArrowType::Line),
+        };
+        arrows.push(connect(&nodes[*from], &nodes[*to], arrow_type));
+    }
+    Checkpoint {
+        nodes,
+        arrows,
+        ..Default::default()
+    }
+}
+
+// Center of a node, `pos="x,y"` with an optional `!` pin
+fn position(attributes: &Attributes) -> Option<Vec2> {
+    let pos = attributes.get("pos")?.trim_end_matches('!');
+    let (x, y) = pos.split_once(',')?;
+    Some(Vec2::new(x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+fn fill_color(attributes: &Attributes) -> Option<Color> {
+    let filled = matches!(attributes.get("style"), Some(style) if style.contains("filled"));
+    let color = match attributes.get("fillcolor") {
+        Some(color) => color,
+        None if filled => attributes.get("color")?,
+        None => return None,
+    };
+    // Color lists like "red:blue" fill with the first one
+    parse_color(color.split(':').next()?)
+}
+
+fn hex(color: Color) -> String {
+    let [r, g, b, a] = color
+        .as_rgba_f32()
+        .map(|x| (x.clamp(0., 1.) * 255.).round() as u8);
+    if a == 255 {
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    } else {
+        format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// `\n`, `\l` and `\r` end lines, `\N` is the node name
+fn unescape(label: &str, name: &str) -> String {
+    let mut text = String::new();
+    let mut chars = label.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'l' | 'r') => text.push('\n'),
+            Some('N') => text.push_str(name),
+            Some(c) => text.push(c),
+            None => text.push('\\'),
+        }
+    }
+    text.trim_end_matches('\n').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digraph_is_imported() {
+        let source = r##"
+            // dependency graph
+            digraph deps {
+                rankdir=LR;
+                node [shape=box, style=filled, fillcolor=lightblue];
+                app [label="velo\napp"];
+                app -> { bevy serde } [color=red];
+                bevy -> wgpu -> "naga" + "-ir";
+                serde -> app [dir=both];
+                wgpu [fillcolor="#ff000080"];
+                subgraph cluster_0 { a; b -- c [dir=none] }
+            }
+        "##;
+        let checkpoint = parse_dot(source).unwrap();
+        let texts: Vec<_> = checkpoint
+            .nodes
+            .iter()
+            .map(|x| x.text.text.as_str())
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "velo\napp",
+                "bevy",
+                "serde",
+                "wgpu",
+                "naga-ir",
+                "a",
+                "b",
+                "c"
+            ]
+        );
+        assert_eq!(checkpoint.nodes[0].bg_color, Color::rgb_u8(173, 216, 230));
+        assert_eq!(checkpoint.nodes[3].bg_color, Color::rgba_u8(255, 0, 0, 128));
+        use ArrowType::*;
+        let types: Vec<_> = checkpoint.arrows.iter().map(|x| x.arrow_type).collect();
+        assert_eq!(types, vec![Arrow, Arrow, Arrow, Arrow, DoubleArrow, Line]);
+    }
+
+    #[test]
+    fn unterminated_tokens_are_errors() {
+        for source in [
+            "digraph { a -> <",
+            "digraph { a [label=<<b>x</b>] }",
+            "graph { \"a }",
+        ] {
+            assert!(
+                matches!(parse_dot(source), Err(VeloError::Import(_))),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn export_round_trips_layout() {
+        let source = r#"graph { a [pos="100,200"]; b [pos="400,50!", width=2, height=1]; a -- b }"#;
+        let checkpoint = parse_dot(source).unwrap();
+        assert_eq!(node_rect(&checkpoint.nodes[1]).size(), Vec2::new(144., 72.));
+        let again = parse_dot(&checkpoint_to_dot(&checkpoint)).unwrap();
+        for (a, b) in checkpoint.nodes.iter().zip(again.nodes.iter()) {
+            assert_eq!(node_rect(a), node_rect(b));
+            assert_eq!(a.text.text, b.text.text);
+        }
+        assert_eq!(again.arrows[0].arrow_type, ArrowType::Line);
+    }
+
+    #[test]
+    fn backslashes_round_trip() {
+        for text in ["C:\\", "say \\\"hi", "a \\n b"] {
+            let checkpoint = Checkpoint {
+                nodes: vec![new_node(
+                    text,
+                    Rect::from_center_size(Vec2::ZERO, NODE_SIZE),
+                )],
+                ..Default::default()
+            };
+            let again = parse_dot(&checkpoint_to_dot(&checkpoint)).unwrap();
+            assert_eq!(again.nodes[0].text.text, text);
+        }
+        let checkpoint = parse_dot(r#"graph { "say \"hi\"" }"#).unwrap();
+        assert_eq!(checkpoint.nodes[0].text.text, "say \"hi\"");
+    }
+}
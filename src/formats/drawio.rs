@@ -0,0 +1,347 @@
+//! draw.io import.
+//!
+//! Reads the first page of a `.drawio` file, plain or compressed. Rectangles
+//! and text become nodes and edges between them become arrows attached to
+//! the sides closest to their exit and entry points. Everything else is
+//! counted in the import summary.
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose, Engine};
+use bevy::prelude::{Color, Rect, Vec2};
+use percent_encoding::percent_decode_str;
+use roxmltree::{Document, Node};
+
+use super::layout::{aligned_text_pos, connect, move_to_origin, nearest_side, new_node};
+use super::velo::Checkpoint;
+use super::{node_rect, ImportSummary};
+use crate::canvas::arrow::components::ArrowType;
+use crate::errors::VeloError;
+
+pub const DRAWIO_FILE_EXTENSION: &str = "drawio";
+
+type Style<'a> = HashMap<&'a str, &'a str>;
+
+struct Cell<'a> {
+    id: &'a str,
+    value: &'a str,
+    style: Style<'a>,
+    // Bare style entries like `ellipse` or `text` name the shape
+    shape: Option<&'a str>,
+    vertex: bool,
+    edge: bool,
+    parent: Option<&'a str>,
+    source: Option<&'a str>,
+    target: Option<&'a str>,
+    geometry: Option<Rect>,
+}
+
+pub fn parse_drawio(source: &str) -> Result<(Checkpoint, ImportSummary), VeloError> {
+    let error = |error: String| VeloError::Import(error);
+    let document = Document::parse(source).map_err(|e| error(e.to_string()))?;
+    let root = document.root_element();
+    let mut summary = ImportSummary::default();
+    if root.has_tag_name("mxGraphModel") {
+        return Ok((read_model(root, &mut summary), summary));
+    }
+    if !root.has_tag_name("mxfile") {
+        return Err(error(format!(
+            "expected a draw.io file, got <{}>",
+            root.tag_name().name()
+        )));
+    }
+    let mut pages = root.children().filter(|x| x.has_tag_name("diagram"));
+    let page = pages
+        .next()
+        .ok_or_else(|| error("file has no pages".to_string()))?;
+    for _ in pages {
+        summary.skip("other page");
+    }
+    if let Some(model) = page.children().find(|x| x.has_tag_name("mxGraphModel")) {
+        return Ok((read_model(model, &mut summary), summary));
+    }
+    let xml = decompress(page.text().unwrap_or_default()).map_err(error)?;
+    let document = Document::parse(&xml).map_err(|e| error(e.to_string()))?;
+    Ok((read_model(document.root_element(), &mut summary), summary))
+}
+
+// Pages are deflated, base64 encoded and URL encoded XML
+fn decompress(page: &str) -> Result<String, String> {
+    let deflated = general_purpose::STANDARD
+        .decode(page.trim())
+        .map_err(|e| e.to_string())?;
+    let encoded = miniz_oxide::inflate::decompress_to_vec(&deflated)
+        .map_err(|e| format!("can't inflate page: {:?}", e))?;
+    let encoded = String::from_utf8(encoded).map_err(|e| e.to_string())?;
+    let xml = percent_decode_str(&encoded)
+        .decode_utf8()
+        .map_err(|e| e.to_string())?;
+    Ok(xml.to_string())
+}
+
+fn read_model(model: Node, summary: &mut ImportSummary) -> Checkpoint {
+    let cells: Vec<_> = model
+        .children()
+        .filter(|x| x.has_tag_name("root"))
+        .flat_map(|root| root.children())
+        .filter_map(read_cell)
+        .collect();
+    let by_id: HashMap<_, _> = cells.iter().map(|cell| (cell.id, cell)).collect();
+    // Children of groups and containers are placed relative to their parent
+    let offset = |cell: &Cell| {
+        let mut offset = Vec2::ZERO;
+        let mut parent = cell.parent.and_then(|id| by_id.get(id));
+        // A cyclic parent chain would never end
+        let mut depth = 0;
+        while let Some(cell) = parent.filter(|x| x.vertex && depth < cells.len()) {
+            depth += 1;
+            offset += cell.geometry.map_or(Vec2::ZERO, |x| x.min);
+            parent = cell.parent.and_then(|id| by_id.get(id));
+        }
+        offset
+    };
+
+    let mut checkpoint = Checkpoint::default();
+    let mut index = HashMap::new();
+    for cell in cells.iter().filter(|x| x.vertex) {
+        let kind = cell.shape.unwrap_or("rect");
+        let default_fill = match kind {
+            "rect" | "rectangle" | "label" => "#ffffff",
+            "text" => "none",
+            // Invisible, only holds its children together
+            "group" => continue,
+            "edgeLabel" => {
+                summary.skip("arrow label");
+                continue;
+            }
+            kind => {
+                summary.skip(kind);
+                continue;
+            }
+        };
+        let Some(geometry) = cell.geometry else {
+            continue;
+        };
+        let min = geometry.min + offset(cell);
+        let max = min + geometry.size();
+        // draw.io y grows downwards
+        let rect = Rect::new(min.x, -min.y, max.x, -max.y);
+        let text = match cell.style.get("html") {
+            Some(&"1") => html_to_text(cell.value),
+            _ => cell.value.to_string(),
+        };
+        let mut node = new_node(&text, rect);
+        node.bg_color = color(cell.style.get("fillColor").unwrap_or(&default_fill));
+        let get = |key: &str, default: &'static str| *cell.style.get(key).unwrap_or(&default);
+        let vertical = if kind == "text" { "top" } else { "middle" };
+        node.text.pos = aligned_text_pos(get("align", "center"), get("verticalAlign", vertical));
+        index.insert(cell.id, checkpoint.nodes.len());
+        checkpoint.nodes.push(node);
+    }
+
+    for cell in cells.iter().filter(|x| x.edge) {
+        let node = |id: Option<&str>| index.get(id?).map(|i| &checkpoint.nodes[*i]);
+        let (Some(start), Some(end)) = (node(cell.source), node(cell.target)) else {
+            summary.skip("unconnected arrow");
+            continue;
+        };
+        if !cell.value.is_empty() {
+            summary.skip("arrow label");
+        }
+        let head = |key: &str, default: &str| cell.style.get(key).unwrap_or(&default) != &"none";
+        let (start, end, exit, entry, arrow_type) =
+            match (head("startArrow", "none"), head("endArrow", "classic")) {
+                (true, true) => (start, end, "exit", "entry", ArrowType::DoubleArrow),
+                (false, true) => (start, end, "exit", "entry", ArrowType::Arrow),
+                (true, false) => (end, start, "entry", "exit", ArrowType::Arrow),
+                (false, false) => (start, end, "exit", "entry", ArrowType::Line),
+            };
+        let mut arrow = connect(start, end, arrow_type);
+        // Fixed connection points are fractions of the node size from its top left
+        let point = |node, prefix: &str| {
+            let fraction = |axis: &str| cell.style.get(format!("{}{}", prefix, axis).as_str());
+            let (x, y) = (
+                fraction("X")?.parse::<f32>().ok()?,
+                fraction("Y")?.parse::<f32>().ok()?,
+            );
+            let rect = node_rect(node);
+            let point = Vec2::new(
+                rect.min.x + x * rect.width(),
+                rect.max.y - y * rect.height(),
+            );
+            Some(nearest_side(rect, point))
+        };
+        if let Some(pos) = point(start, exit) {
+            arrow.start.pos = pos;
+        }
+        if let Some(pos) = point(end, entry) {
+            arrow.end.pos = pos;
+        }
+        checkpoint.arrows.push(arrow);
+    }
+
+    move_to_origin(&mut checkpoint);
+    checkpoint
+}
+
+// `<mxCell>`, or an `<object>` or `<UserObject>` with custom properties around it
+fn read_cell<'a>(node: Node<'a, 'a>) -> Option<Cell<'a>> {
+    let (cell, value) = if node.has_tag_name("mxCell") {
+        (node, node.attribute("value"))
+    } else {
+        let cell = node.children().find(|x| x.has_tag_name("mxCell"))?;
+        (cell, node.attribute("label"))
+    };
+    let mut style = Style::new();
+    let mut shape = None;
+    for entry in cell.attribute("style").unwrap_or_default().split(';') {
+        match entry.split_once('=') {
+            Some((key, value)) => {
+                style.insert(key, value);
+            }
+            None if !entry.is_empty() && shape.is_none() => shape = Some(entry),
+            None => {}
+        }
+    }
+    if let Some(name) = style.get("shape") {
+        shape = Some(name);
+    }
+    if style.contains_key("image") {
+        shape = Some("image");
+    }
+    let geometry = cell
+        .children()
+        .find(|x| x.has_tag_name("mxGeometry"))
+        .map(|geometry| {
+            let get = |key| geometry.attribute(key).and_then(|x| x.parse().ok());
+            let min = Vec2::new(get("x").unwrap_or(0.), get("y").unwrap_or(0.));
+            let size = Vec2::new(get("width").unwrap_or(0.), get("height").unwrap_or(0.));
+            Rect::from_corners(min, min + size)
+        });
+    Some(Cell {
+        id: node.attribute("id")?,
+        value: value.unwrap_or_default(),
+        style,
+        shape,
+        vertex: cell.attribute("vertex") == Some("1"),
+        edge: cell.attribute("edge") == Some("1"),
+        parent: cell.attribute("parent"),
+        source: cell.attribute("source"),
+        target: cell.attribute("target"),
+        geometry,
+    })
+}
+
+fn color(value: &str) -> Color {
+    match value.strip_prefix('#') {
+        Some(hex) => Color::hex(hex).unwrap_or(Color::WHITE),
+        None if value == "none" => Color::NONE,
+        None => Color::WHITE,
+    }
+}
+
+// Labels with `html=1` are HTML fragments
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = rest[start + 1..start + end]
+            .trim_start_matches('/')
+            .to_lowercase();
+        let name = tag.split(|c: char| c.is_whitespace() || c == '/').next();
+        if matches!(name, Some("br" | "div" | "p" | "li"))
+            && !text.is_empty()
+            && !text.ends_with('\n')
+        {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .trim_end_matches('\n')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::arrow::components::ArrowConnectPos;
+
+    const MODEL: &str = r##"<mxGraphModel><root>
+        <mxCell id="0"/>
+        <mxCell id="1" parent="0"/>
+        <mxCell id="a" value="&lt;b&gt;Start&lt;/b&gt;&lt;br&gt;here" style="rounded=0;whiteSpace=wrap;html=1;fillColor=#dae8fc;" vertex="1" parent="1">
+            <mxGeometry x="0" y="0" width="120" height="60" as="geometry"/>
+        </mxCell>
+        <object id="b" label="End" owner="me">
+            <mxCell style="whiteSpace=wrap;" vertex="1" parent="1">
+                <mxGeometry x="0" y="200" width="120" height="60" as="geometry"/>
+            </mxCell>
+        </object>
+        <mxCell id="c" value="Round" style="ellipse;whiteSpace=wrap;" vertex="1" parent="1">
+            <mxGeometry x="300" y="0" width="80" height="80" as="geometry"/>
+        </mxCell>
+        <mxCell id="ab" style="edgeStyle=orthogonalEdgeStyle;exitX=1;exitY=0.5;entryX=1;entryY=0.5;" edge="1" parent="1" source="a" target="b">
+            <mxGeometry relative="1" as="geometry"/>
+        </mxCell>
+        <mxCell id="ac" style="endArrow=none;" edge="1" parent="1" source="a" target="c">
+            <mxGeometry relative="1" as="geometry"/>
+        </mxCell>
+    </root></mxGraphModel>"##;
+
+    #[test]
+    fn cyclic_parents_terminate() {
+        let model = r#"<mxGraphModel><root>
+            <mxCell id="a" value="A" vertex="1" parent="b"><mxGeometry x="0" y="0" width="10" height="10" as="geometry"/></mxCell>
+            <mxCell id="b" value="B" vertex="1" parent="a"><mxGeometry x="0" y="0" width="10" height="10" as="geometry"/></mxCell>
+        </root></mxGraphModel>"#;
+        let (checkpoint, _) = parse_drawio(model).unwrap();
+        assert_eq!(checkpoint.nodes.len(), 2);
+    }
+
+    #[test]
+    fn model_is_imported() {
+        let (checkpoint, summary) = parse_drawio(MODEL).unwrap();
+        let texts: Vec<_> = checkpoint
+            .nodes
+            .iter()
+            .map(|x| x.text.text.as_str())
+            .collect();
+        assert_eq!(texts, vec!["Start\nhere", "End"]);
+        assert_eq!(checkpoint.nodes[0].bg_color, Color::hex("dae8fc").unwrap());
+        assert!(node_rect(&checkpoint.nodes[0]).min.y > node_rect(&checkpoint.nodes[1]).min.y);
+        assert_eq!(checkpoint.arrows.len(), 1);
+        assert_eq!(checkpoint.arrows[0].arrow_type, ArrowType::Arrow);
+        assert_eq!(checkpoint.arrows[0].start.pos, ArrowConnectPos::Right);
+        assert_eq!(checkpoint.arrows[0].end.pos, ArrowConnectPos::Right);
+        assert_eq!(
+            summary.to_string(),
+            "skipped unsupported elements: 1 ellipse, 1 unconnected arrow"
+        );
+    }
+
+    #[test]
+    fn compressed_pages_are_read() {
+        let encoded: String =
+            percent_encoding::utf8_percent_encode(MODEL, percent_encoding::NON_ALPHANUMERIC)
+                .collect();
+        let deflated = miniz_oxide::deflate::compress_to_vec(encoded.as_bytes(), 6);
+        let page = general_purpose::STANDARD.encode(deflated);
+        let file = format!(
+            r#"<mxfile><diagram name="Page-1">{}</diagram><diagram name="Page-2"/></mxfile>"#,
+            page
+        );
+        let (checkpoint, summary) = parse_drawio(&file).unwrap();
+        assert_eq!(checkpoint.nodes.len(), 2);
+        assert_eq!(summary.skipped["other page"], 1);
+    }
+}
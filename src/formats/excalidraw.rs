@@ -0,0 +1,223 @@
+//! Excalidraw import.
+//!
+//! Rectangles and free text become nodes, text bound to a rectangle becomes
+//! its label and arrows bound on both ends become arrows attached to the
+//! sides closest to where they start and end. Everything else is counted in
+//! the import summary.
+use std::collections::HashMap;
+
+use bevy::prelude::{Color, Rect, Vec2};
+use serde::Deserialize;
+
+use super::layout::{aligned_text_pos, move_to_origin, nearest_side, new_node};
+use super::velo::Checkpoint;
+use super::{node_rect, ImportSummary};
+use crate::canvas::arrow::components::{ArrowConnect, ArrowMeta, ArrowType};
+use crate::errors::VeloError;
+use crate::utils::ReflectableUuid;
+
+pub const EXCALIDRAW_FILE_EXTENSION: &str = "excalidraw";
+
+#[derive(Deserialize)]
+struct ExcalidrawFile {
+    elements: Vec<Element>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct Element {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    background_color: String,
+    opacity: Option<f32>,
+    is_deleted: bool,
+    text: String,
+    original_text: Option<String>,
+    container_id: Option<String>,
+    text_align: String,
+    vertical_align: String,
+    points: Vec<[f32; 2]>,
+    start_binding: Option<Binding>,
+    end_binding: Option<Binding>,
+    start_arrowhead: Option<String>,
+    end_arrowhead: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Binding {
+    element_id: String,
+}
+
+impl Element {
+    // Excalidraw y grows downwards
+    fn rect(&self) -> Rect {
+        Rect::new(self.x, -self.y, self.x + self.width, -self.y - self.height)
+    }
+
+    fn point(&self, i: usize) -> Option<Vec2> {
+        let [x, y] = self.points.get(i)?;
+        Some(Vec2::new(self.x + x, -self.y - y))
+    }
+
+    fn label(&self) -> String {
+        self.original_text
+            .clone()
+            .unwrap_or_else(|| self.text.clone())
+    }
+
+    // "transparent" shows the white canvas underneath
+    fn color(&self) -> Color {
+        let hex = self.background_color.strip_prefix('#');
+        let color = hex
+            .and_then(|hex| Color::hex(hex).ok())
+            .unwrap_or(Color::WHITE);
+        let opacity = self.opacity.unwrap_or(100.) / 100.;
+        color.with_a(color.a() * opacity)
+    }
+}
+
+pub fn parse_excalidraw(source: &str) -> Result<(Checkpoint, ImportSummary), VeloError> {
+    let file: ExcalidrawFile =
+        serde_json::from_str(source).map_err(|error| VeloError::Import(error.to_string()))?;
+    let elements: Vec<_> = file.elements.iter().filter(|x| !x.is_deleted).collect();
+    let kinds: HashMap<_, _> = elements
+        .iter()
+        .map(|x| (x.id.as_str(), x.kind.as_str()))
+        .collect();
+    let mut summary = ImportSummary::default();
+    let mut checkpoint = Checkpoint::default();
+    let mut index = HashMap::new();
+
+    for element in elements.iter() {
+        let node = match element.kind.as_str() {
+            "rectangle" => {
+                let mut node = new_node("", element.rect());
+                node.bg_color = element.color();
+                node
+            }
+            "text" if element.container_id.is_none() => {
+                let mut node = new_node(&element.label(), element.rect());
+                node.text.pos = aligned_text_pos(&element.text_align, "top");
+                node.bg_color = Color::NONE;
+                node
+            }
+            "text" | "arrow" => continue,
+            kind => {
+                summary.skip(kind);
+                continue;
+            }
+        };
+        index.insert(element.id.as_str(), checkpoint.nodes.len());
+        checkpoint.nodes.push(node);
+    }
+
+    for element in elements.iter() {
+        let Some(container) = element.container_id.as_deref() else {
+            continue;
+        };
+        if let Some(i) = index.get(container) {
+            let node = &mut checkpoint.nodes[*i];
+            node.text.text = element.label();
+            node.text.pos = aligned_text_pos(&element.text_align, &element.vertical_align);
+        } else if kinds.get(container) == Some(&"arrow") {
+            summary.skip("arrow label");
+        }
+    }
+
+    for element in elements.iter().filter(|x| x.kind == "arrow") {
+        let node = |binding: &Option<Binding>| {
+            let id = binding.as_ref()?.element_id.as_str();
+            index.get(id).map(|i| &checkpoint.nodes[*i])
+        };
+        let (Some(start), Some(end)) = (node(&element.start_binding), node(&element.end_binding))
+        else {
+            summary.skip("unbound arrow");
+            continue;
+        };
+        let first = element.point(0).unwrap_or(Vec2::new(element.x, -element.y));
+        let last = element
+            .point(element.points.len().max(1) - 1)
+            .unwrap_or(first);
+        let head = |arrowhead: &Option<String>| arrowhead.is_some();
+        let (start, end, first, last, arrow_type) =
+            match (head(&element.start_arrowhead), head(&element.end_arrowhead)) {
+                (true, true) => (start, end, first, last, ArrowType::DoubleArrow),
+                (false, true) => (start, end, first, last, ArrowType::Arrow),
+                (true, false) => (end, start, last, first, ArrowType::Arrow),
+                (false, false) => (start, end, first, last, ArrowType::Line),
+            };
+        let arrow = ArrowMeta {
+            arrow_type,
+            start: ArrowConnect {
+                id: ReflectableUuid(start.id),
+                pos: nearest_side(node_rect(start), first),
+            },
+            end: ArrowConnect {
+                id: ReflectableUuid(end.id),
+                pos: nearest_side(node_rect(end), last),
+            },
+        };
+        checkpoint.arrows.push(arrow);
+    }
+
+    move_to_origin(&mut checkpoint);
+    Ok((checkpoint, summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::arrow::components::ArrowConnectPos;
+
+    #[test]
+    fn bound_shapes_are_imported() {
+        let source = r##"{
+            "type": "excalidraw",
+            "elements": [
+                {"id": "a", "type": "rectangle", "x": 0, "y": 0, "width": 100, "height": 50,
+                 "backgroundColor": "#ffc9c9", "boundElements": [{"id": "t", "type": "text"}]},
+                {"id": "t", "type": "text", "x": 10, "y": 10, "width": 80, "height": 20,
+                 "text": "wrapped", "originalText": "Hello world", "containerId": "a",
+                 "textAlign": "center", "verticalAlign": "middle"},
+                {"id": "b", "type": "rectangle", "x": 300, "y": 0, "width": 100, "height": 50,
+                 "backgroundColor": "transparent"},
+                {"id": "note", "type": "text", "x": 0, "y": 200, "width": 50, "height": 20,
+                 "text": "note", "textAlign": "left"},
+                {"id": "e", "type": "ellipse", "x": 0, "y": 300, "width": 50, "height": 50},
+                {"id": "gone", "type": "diamond", "isDeleted": true},
+                {"id": "ab", "type": "arrow", "x": 100, "y": 25, "points": [[0, 0], [200, 0]],
+                 "startBinding": {"elementId": "a"}, "endBinding": {"elementId": "b"},
+                 "startArrowhead": null, "endArrowhead": "arrow"},
+                {"id": "loose", "type": "arrow", "x": 0, "y": 0, "points": [[0, 0], [10, 10]],
+                 "endArrowhead": "arrow"}
+            ]
+        }"##;
+        let (checkpoint, summary) = parse_excalidraw(source).unwrap();
+        let texts: Vec<_> = checkpoint
+            .nodes
+            .iter()
+            .map(|x| x.text.text.as_str())
+            .collect();
+        assert_eq!(texts, vec!["Hello world", "", "note"]);
+        assert_eq!(checkpoint.nodes[0].bg_color, Color::hex("ffc9c9").unwrap());
+        assert_eq!(checkpoint.nodes[1].bg_color, Color::WHITE);
+        assert_eq!(
+            node_rect(&checkpoint.nodes[1]).min - node_rect(&checkpoint.nodes[0]).min,
+            Vec2::new(300., 0.)
+        );
+        let arrow = checkpoint.arrows[0];
+        assert_eq!(arrow.arrow_type, ArrowType::Arrow);
+        assert_eq!(arrow.start.pos, ArrowConnectPos::Right);
+        assert_eq!(arrow.end.pos, ArrowConnectPos::Left);
+        assert_eq!(
+            summary.to_string(),
+            "skipped unsupported elements: 1 ellipse, 1 unbound arrow"
+        );
+    }
+}
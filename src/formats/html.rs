@@ -0,0 +1,178 @@
+//! Self-contained HTML page of a whole doc, to share diagrams with people
+//! who don't have Velo.
+//!
+//! Every tab is an inline SVG with clickable links. A bar at the bottom
+//! switches tabs like the one in the app, dragging pans, the mouse wheel
+//! zooms and a double click fits the diagram again.
+use std::fmt::Write;
+
+use super::svg::checkpoint_to_svg;
+use super::velo::latest_checkpoint;
+use crate::components::Doc;
+
+pub const HTML_FILE_EXTENSION: &str = "html";
+
+// Sizes and colors of the tab bar follow `add_tab` and `BottomPanel`
+const STYLE: &str = r#"html, body { margin: 0; height: 100%; overflow: hidden; font-family: Iosevka, monospace; }
+main { position: absolute; top: 0; left: 0; right: 0; bottom: 50px; }
+section { width: 100%; height: 100%; }
+section[hidden] { display: none; }
+section svg { width: 100%; height: 100%; cursor: grab; touch-action: none; }
+section svg.panning { cursor: grabbing; }
+nav { position: absolute; left: 0; right: 0; bottom: 0; height: 50px; display: flex; align-items: center; overflow-x: auto; }
+nav button { min-width: 80px; height: 30px; margin: 10px; padding: 0 8px; border: 1px solid #000; font: inherit; font-size: 18px; white-space: nowrap; background: rgba(204, 204, 204, 0.5); cursor: pointer; }
+nav button.active { background: #f0f8ff; }
+"#;
+
+const SCRIPT: &str = r#"const buttons = document.querySelectorAll("nav button");
+for (const button of buttons) {
+  button.addEventListener("click", () => {
+    for (const other of buttons) {
+      other.classList.toggle("active", other === button);
+      document.getElementById(other.dataset.tab).hidden = other !== button;
+    }
+  });
+}
+for (const svg of document.querySelectorAll("section svg")) {
+  const fit = svg.getAttribute("viewBox");
+  let box = fit.split(" ").map(Number);
+  const show = () => svg.setAttribute("viewBox", box.join(" "));
+  const toSvg = (x, y) => {
+    const point = svg.createSVGPoint();
+    point.x = x;
+    point.y = y;
+    return point.matrixTransform(svg.getScreenCTM().inverse());
+  };
+  svg.addEventListener("wheel", (event) => {
+    event.preventDefault();
+    const point = toSvg(event.clientX, event.clientY);
+    const factor = Math.exp(event.deltaY * 0.002);
+    box = [
+      point.x - (point.x - box[0]) * factor,
+      point.y - (point.y - box[1]) * factor,
+      box[2] * factor,
+      box[3] * factor,
+    ];
+    show();
+  }, { passive: false });
+  let drag = null;
+  svg.addEventListener("pointerdown", (event) => {
+    if (event.target.closest("a")) return;
+    drag = { point: toSvg(event.clientX, event.clientY), pointer: event.pointerId };
+    svg.setPointerCapture(event.pointerId);
+    svg.classList.add("panning");
+  });
+  svg.addEventListener("pointermove", (event) => {
+    if (!drag || drag.pointer !== event.pointerId) return;
+    const point = toSvg(event.clientX, event.clientY);
+    box[0] -= point.x - drag.point.x;
+    box[1] -= point.y - drag.point.y;
+    show();
+  });
+  const stop = () => {
+    drag = null;
+    svg.classList.remove("panning");
+  };
+  svg.addEventListener("pointerup", stop);
+  svg.addEventListener("pointercancel", stop);
+  svg.addEventListener("dblclick", () => {
+    box = fit.split(" ").map(Number);
+    show();
+  });
+}
+"#;
+
+/// Renders the latest checkpoint of every tab, the active tab is shown
+/// first. Images are looked up by hash with `get_image` and embedded.
+pub fn doc_to_html(
+    doc: &Doc,
+    get_image: impl Fn(&str) -> Option<String>,
+) -> serde_json::Result<String> {
+    let active = doc.tabs.iter().position(|tab| tab.is_active).unwrap_or(0);
+    let mut sections = String::new();
+    let mut buttons = String::new();
+    for (i, tab) in doc.tabs.iter().enumerate() {
+        let checkpoint = latest_checkpoint(tab)?;
+        let hidden = if i == active { "" } else { " hidden" };
+        write!(
+            sections,
+            "<section id=\"tab-{}\"{}>\n{}</section>\n",
+            i,
+            hidden,
+            checkpoint_to_svg(&checkpoint, &get_image)
+        )
+        .unwrap();
+        let class = if i == active { " class=\"active\"" } else { "" };
+        writeln!(
+            buttons,
+            "<button data-tab=\"tab-{}\"{}>{}</button>",
+            i,
+            class,
+            escape(&tab.name)
+        )
+        .unwrap();
+    }
+    Ok(format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n<main>\n{}</main>\n<nav>\n{}</nav>\n<script>\n{}</script>\n</body>\n</html>\n",
+        escape(&doc.name),
+        STYLE,
+        sections,
+        buttons,
+        SCRIPT
+    ))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use bevy::prelude::Rect;
+
+    use super::*;
+    use crate::components::Tab;
+    use crate::formats::layout::new_node;
+    use crate::formats::velo::{checkpoint_to_string, Checkpoint};
+    use crate::utils::ReflectableUuid;
+
+    #[test]
+    fn every_tab_is_rendered() {
+        let tab = |name: &str, text: &str, is_active: bool| {
+            let checkpoint = Checkpoint {
+                nodes: vec![new_node(text, Rect::new(0., 0., 300., 100.))],
+                ..Default::default()
+            };
+            Tab {
+                id: ReflectableUuid(uuid::Uuid::new_v4()),
+                name: name.to_string(),
+                checkpoints: VecDeque::from([checkpoint_to_string(&checkpoint).unwrap()]),
+                is_active,
+                view: Default::default(),
+            }
+        };
+        let doc = Doc {
+            id: ReflectableUuid(uuid::Uuid::new_v4()),
+            name: "Services & teams".to_string(),
+            tabs: vec![
+                tab("Overview", "see https://example.com", false),
+                tab("<Details>", "db", true),
+            ],
+            tags: vec![],
+        };
+        let html = doc_to_html(&doc, |_| None).unwrap();
+        assert!(html.contains("<title>Services &amp; teams</title>"));
+        assert_eq!(html.matches("<svg").count(), 2);
+        assert!(html.contains("<section id=\"tab-0\" hidden>"));
+        assert!(html.contains("<section id=\"tab-1\">"));
+        assert!(
+            html.contains("<button data-tab=\"tab-1\" class=\"active\">&lt;Details&gt;</button>")
+        );
+        assert!(html.contains("<a href=\"https://example.com\""));
+    }
+}
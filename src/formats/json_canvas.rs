@@ -0,0 +1,395 @@
+//! JSON Canvas (`.canvas`) import and export, the open format of Obsidian
+//! boards.
+//!
+//! Nodes and edges map onto the format directly. What it has no place for,
+//! text position, tags, z index, images and parallel arrows, is kept in a
+//! `velo` field that other apps ignore, so a tab survives a round-trip.
+use std::collections::HashMap;
+
+use bevy::prelude::{Color, Rect, Vec2};
+use serde::{Deserialize, Serialize, Serializer};
+use uuid::Uuid;
+
+use super::layout::{connect, move_to_origin, new_node};
+use super::velo::Checkpoint;
+use super::{node_rect, ImportSummary};
+use crate::canvas::arrow::components::{ArrowConnect, ArrowConnectPos, ArrowType};
+use crate::errors::VeloError;
+use crate::utils::ReflectableUuid;
+use crate::TextPos;
+
+pub const CANVAS_FILE_EXTENSION: &str = "canvas";
+
+// Obsidian's colors for presets "1" to "6"
+const PRESET_COLORS: [&str; 6] = ["fb464c", "e9973f", "e0de71", "44cf6e", "53dfdd", "a882ff"];
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct CanvasFile {
+    nodes: Vec<CanvasNode>,
+    edges: Vec<CanvasEdge>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CanvasNode {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(serialize_with = "whole_number")]
+    x: f32,
+    #[serde(serialize_with = "whole_number")]
+    y: f32,
+    #[serde(serialize_with = "whole_number")]
+    width: f32,
+    #[serde(serialize_with = "whole_number")]
+    height: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    velo: Option<VeloNode>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct VeloNode {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text_pos: Option<TextPos>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "is_zero")]
+    z_index: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CanvasEdge {
+    id: String,
+    from_node: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    from_side: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    from_end: Option<String>,
+    to_node: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    to_side: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    to_end: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    velo: Option<VeloEdge>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VeloEdge {
+    arrow_type: ArrowType,
+}
+
+fn is_zero(x: &i32) -> bool {
+    *x == 0
+}
+
+// The spec asks for integers, fractions are only written when a node has them
+fn whole_number<S: Serializer>(x: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+    if x.fract() == 0. {
+        serializer.serialize_i64(*x as i64)
+    } else {
+        serializer.serialize_f32(*x)
+    }
+}
+
+/// Nodes become text nodes, images are looked up by hash with `get_image`
+/// and kept in the `velo` field.
+pub fn checkpoint_to_canvas(
+    checkpoint: &Checkpoint,
+    get_image: impl Fn(&str) -> Option<String>,
+) -> serde_json::Result<String> {
+    // Later nodes are drawn on top
+    let mut nodes: Vec<_> = checkpoint.nodes.iter().collect();
+    nodes.sort_by_key(|node| node.z_index);
+    let mut file = CanvasFile::default();
+    for node in nodes {
+        let rect = node_rect(node);
+        let id = node.id.to_string();
+        let image = match checkpoint.images.get(&id) {
+            Some(hash) => get_image(hash),
+            None => checkpoint.inline_images.get(&id).cloned(),
+        };
+        let velo = VeloNode {
+            text_pos: match node.text.pos {
+                TextPos::Center => None,
+                ref pos => Some(pos.clone()),
+            },
+            tags: node.tags.clone(),
+            z_index: node.z_index,
+            image,
+        };
+        let keep = velo.text_pos.is_some()
+            || !velo.tags.is_empty()
+            || velo.z_index != 0
+            || velo.image.is_some();
+        file.nodes.push(CanvasNode {
+            id,
+            kind: "text".to_string(),
+            x: rect.min.x,
+            // Canvas y grows downwards
+            y: -rect.max.y,
+            width: rect.width(),
+            height: rect.height(),
+            color: (node.bg_color != Color::WHITE).then(|| hex(node.bg_color)),
+            text: Some(node.text.text.clone()),
+            file: None,
+            url: None,
+            label: None,
+            velo: keep.then_some(velo),
+        });
+    }
+    for (i, arrow) in checkpoint.arrows.iter().enumerate() {
+        let (from_end, to_end) = match arrow.arrow_type {
+            ArrowType::Line | ArrowType::ParallelLine => (None, Some("none")),
+            ArrowType::Arrow | ArrowType::ParallelArrow => (None, None),
+            ArrowType::DoubleArrow | ArrowType::ParallelDoubleArrow => (Some("arrow"), None),
+        };
+        let parallel = matches!(
+            arrow.arrow_type,
+            ArrowType::ParallelLine | ArrowType::ParallelArrow | ArrowType::ParallelDoubleArrow
+        );
+        file.edges.push(CanvasEdge {
+            id: format!("edge-{}", i + 1),
+            from_node: arrow.start.id.0.to_string(),
+            from_side: Some(side(arrow.start.pos).to_string()),
+            from_end: from_end.map(|x| x.to_string()),
+            to_node: arrow.end.id.0.to_string(),
+            to_side: Some(side(arrow.end.pos).to_string()),
+            to_end: to_end.map(|x| x.to_string()),
+            label: None,
+            velo: parallel.then_some(VeloEdge {
+                arrow_type: arrow.arrow_type,
+            }),
+        });
+    }
+    serde_json::to_string_pretty(&file)
+}
+
+pub fn parse_canvas(source: &str) -> Result<(Checkpoint, ImportSummary), VeloError> {
+    let file: CanvasFile =
+        serde_json::from_str(source).map_err(|error| VeloError::Import(error.to_string()))?;
+    let mut summary = ImportSummary::default();
+    let mut checkpoint = Checkpoint::default();
+    let mut index = HashMap::new();
+    for node in file.nodes.iter() {
+        let text = match node.kind.as_str() {
+            "text" => node.text.clone(),
+            "file" => node.file.clone(),
+            // Links in text are clickable on the canvas
+            "link" => node.url.clone(),
+            "group" => node.label.clone(),
+            kind => {
+                summary.skip(kind);
+                continue;
+            }
+        };
+        let min = Vec2::new(node.x, -node.y - node.height);
+        let rect = Rect::from_corners(min, min + Vec2::new(node.width, node.height));
+        let mut json_node = new_node(&text.unwrap_or_default(), rect);
+        // Ids of other apps are usually not UUIDs
+        json_node.id = Uuid::parse_str(&node.id).unwrap_or_else(|_| Uuid::new_v4());
+        json_node.bg_color = node.color.as_deref().map_or(Color::WHITE, color);
+        if node.kind == "group" {
+            json_node.text.pos = TextPos::TopLeft;
+            json_node.bg_color = json_node.bg_color.with_a(0.);
+            json_node.z_index = -1;
+        }
+        if let Some(velo) = &node.velo {
+            if let Some(pos) = &velo.text_pos {
+                json_node.text.pos = pos.clone();
+            }
+            json_node.tags = velo.tags.clone();
+            json_node.z_index = velo.z_index;
+            if let Some(image) = &velo.image {
+                checkpoint
+                    .inline_images
+                    .insert(json_node.id.to_string(), image.clone());
+            }
+        }
+        index.insert(node.id.as_str(), checkpoint.nodes.len());
+        checkpoint.nodes.push(json_node);
+    }
+
+    for edge in file.edges.iter() {
+        let node = |id: &str| index.get(id).map(|i| &checkpoint.nodes[*i]);
+        let (Some(from), Some(to)) = (node(&edge.from_node), node(&edge.to_node)) else {
+            summary.skip("unconnected arrow");
+            continue;
+        };
+        if edge.label.is_some() {
+            summary.skip("arrow label");
+        }
+        let from_end = edge.from_end.as_deref().unwrap_or("none") != "none";
+        let to_end = edge.to_end.as_deref().unwrap_or("arrow") != "none";
+        let ends = (
+            (from, edge.from_side.as_deref()),
+            (to, edge.to_side.as_deref()),
+        );
+        let (((start, start_side), (end, end_side)), arrow_type) = match (from_end, to_end) {
+            (true, true) => (ends, ArrowType::DoubleArrow),
+            (false, true) => (ends, ArrowType::Arrow),
+            (true, false) => ((ends.1, ends.0), ArrowType::Arrow),
+            (false, false) => (ends, ArrowType::Line),
+        };
+        let mut arrow = connect(start, end, arrow_type);
+        if let Some(velo) = &edge.velo {
+            arrow.arrow_type = velo.arrow_type;
+        }
+        if let Some(pos) = start_side.and_then(connect_pos) {
+            arrow.start = ArrowConnect {
+                id: ReflectableUuid(start.id),
+                pos,
+            };
+        }
+        if let Some(pos) = end_side.and_then(connect_pos) {
+            arrow.end = ArrowConnect {
+                id: ReflectableUuid(end.id),
+                pos,
+            };
+        }
+        checkpoint.arrows.push(arrow);
+    }
+
+    // Boards from other apps are centered around 0, Velo's start at 0
+    let below_canvas = checkpoint.nodes.iter().any(|node| {
+        let rect = node_rect(node);
+        rect.min.x < 0. || rect.min.y < 0.
+    });
+    if below_canvas {
+        move_to_origin(&mut checkpoint);
+    }
+    Ok((checkpoint, summary))
+}
+
+fn side(pos: ArrowConnectPos) -> &'static str {
+    match pos {
+        ArrowConnectPos::Top => "top",
+        ArrowConnectPos::Bottom => "bottom",
+        ArrowConnectPos::Left => "left",
+        ArrowConnectPos::Right => "right",
+    }
+}
+
+fn connect_pos(side: &str) -> Option<ArrowConnectPos> {
+    match side {
+        "top" => Some(ArrowConnectPos::Top),
+        "bottom" => Some(ArrowConnectPos::Bottom),
+        "left" => Some(ArrowConnectPos::Left),
+        "right" => Some(ArrowConnectPos::Right),
+        _ => None,
+    }
+}
+
+fn color(value: &str) -> Color {
+    let hex = match value.parse::<usize>() {
+        Ok(preset @ 1..=6) => PRESET_COLORS[preset - 1],
+        _ => value.trim_start_matches('#'),
+    };
+    Color::hex(hex).unwrap_or(Color::WHITE)
+}
+
+fn hex(color: Color) -> String {
+    let [r, g, b, a] = color
+        .as_rgba_f32()
+        .map(|x| (x.clamp(0., 1.) * 255.).round() as u8);
+    if a == 255 {
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    } else {
+        format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obsidian_canvas_is_imported() {
+        let source = r##"{
+            "nodes": [
+                {"id": "6f0ad84f44ce9c17", "type": "group", "x": -400, "y": -300, "width": 800, "height": 600, "label": "Ideas"},
+                {"id": "a1", "type": "text", "x": -250, "y": -100, "width": 250, "height": 60, "text": "# Plan", "color": "4"},
+                {"id": "b2", "type": "link", "x": 100, "y": -100, "width": 250, "height": 60, "url": "https://obsidian.md"},
+                {"id": "c3", "type": "file", "x": 100, "y": 100, "width": 250, "height": 60, "file": "Notes/Todo.md"}
+            ],
+            "edges": [
+                {"id": "e1", "fromNode": "a1", "fromSide": "right", "toNode": "b2", "toSide": "left"},
+                {"id": "e2", "fromNode": "b2", "toNode": "c3", "toEnd": "none", "label": "see"},
+                {"id": "e3", "fromNode": "c3", "toNode": "gone"}
+            ]
+        }"##;
+        let (checkpoint, summary) = parse_canvas(source).unwrap();
+        let texts: Vec<_> = checkpoint
+            .nodes
+            .iter()
+            .map(|x| x.text.text.as_str())
+            .collect();
+        assert_eq!(
+            texts,
+            vec!["Ideas", "# Plan", "https://obsidian.md", "Notes/Todo.md"]
+        );
+        assert_eq!(node_rect(&checkpoint.nodes[0]).min, Vec2::new(50., 50.));
+        assert_eq!(checkpoint.nodes[1].bg_color, Color::hex("44cf6e").unwrap());
+        let arrow = checkpoint.arrows[0];
+        assert_eq!(arrow.arrow_type, ArrowType::Arrow);
+        assert_eq!(
+            (arrow.start.pos, arrow.end.pos),
+            (ArrowConnectPos::Right, ArrowConnectPos::Left)
+        );
+        assert_eq!(checkpoint.arrows[1].arrow_type, ArrowType::Line);
+        assert_eq!(
+            summary.to_string(),
+            "skipped unsupported elements: 1 arrow label, 1 unconnected arrow"
+        );
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let mut a = new_node("a", Rect::new(10., 20., 110.5, 120.));
+        a.text.pos = TextPos::BottomRight;
+        a.tags = vec!["todo".to_string()];
+        a.z_index = 3;
+        a.bg_color = Color::rgba_u8(255, 0, 0, 128);
+        let b = new_node("b", Rect::new(300., 20., 400., 120.));
+        let mut arrow = connect(&a, &b, ArrowType::ParallelDoubleArrow);
+        arrow.end.pos = ArrowConnectPos::Top;
+        let mut checkpoint = Checkpoint {
+            arrows: vec![arrow],
+            nodes: vec![a, b],
+            ..Default::default()
+        };
+        checkpoint
+            .inline_images
+            .insert(checkpoint.nodes[0].id.to_string(), "aGk=".to_string());
+
+        let exported = checkpoint_to_canvas(&checkpoint, |_| None).unwrap();
+        assert!(exported.contains(r#""width": 100.5"#));
+        assert!(exported.contains(r#""height": 100,"#));
+        let (again, summary) = parse_canvas(&exported).unwrap();
+        assert!(summary.is_empty());
+        let json = |checkpoint: &Checkpoint| {
+            let mut checkpoint = serde_json::to_value(checkpoint).unwrap();
+            let nodes = checkpoint["nodes"].as_array_mut().unwrap();
+            nodes.sort_by_key(|node| node["id"].as_str().unwrap().to_string());
+            checkpoint
+        };
+        assert_eq!(json(&checkpoint), json(&again));
+    }
+}
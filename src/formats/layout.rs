@@ -0,0 +1,242 @@
+//! Generated layout for diagrams imported from formats without positions.
+use bevy::prelude::{Color, Rect, Val, Vec2};
+use uuid::Uuid;
+
+use super::velo::Checkpoint;
+use super::{connect_point, node_rect};
+use crate::canvas::arrow::components::{ArrowConnect, ArrowConnectPos, ArrowMeta, ArrowType};
+use crate::utils::ReflectableUuid;
+use crate::{JsonNode, JsonNodeText, NodeType, TextPos};
+
+pub const NODE_SIZE: Vec2 = Vec2::new(150., 80.);
+const GAP: Vec2 = Vec2::new(60., 80.);
+// Space between siblings of a tree
+const SIBLING_GAP: f32 = 20.;
+// Bottom left corner of generated diagrams on the canvas
+const ORIGIN: Vec2 = Vec2::new(50., 50.);
+// Wider layers wrap onto more rows, e.g. hundreds of unconnected nodes
+const MAX_LAYER_WIDTH: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    #[default]
+    Down,
+    Up,
+    Right,
+    Left,
+}
+
+/// Bottom left corners of `count` nodes placed in layers, so that edges
+/// point from one layer to a later one. Cycles are broken at the node that
+/// shows up first. Layers wider than `MAX_LAYER_WIDTH` wrap.
+pub fn layered_layout(count: usize, edges: &[(usize, usize)], direction: Direction) -> Vec<Vec2> {
+    let mut layers = vec![0; count];
+    let mut incoming = vec![0; count];
+    for (_, to) in edges.iter() {
+        incoming[*to] += 1;
+    }
+    let mut placed = vec![false; count];
+    for _ in 0..count {
+        let next = (0..count)
+            .find(|i| !placed[*i] && incoming[*i] == 0)
+            .or_else(|| (0..count).find(|i| !placed[*i]))
+            .unwrap();
+        placed[next] = true;
+        for (from, to) in edges.iter().filter(|(from, _)| *from == next) {
+            if !placed[*to] {
+                incoming[*to] -= 1;
+                layers[*to] = layers[*to].max(layers[*from] + 1);
+            }
+        }
+    }
+
+    let mut layered: Vec<Vec<usize>> = vec![vec![]; layers.iter().max().map_or(0, |x| x + 1)];
+    for (i, layer) in layers.iter().enumerate() {
+        layered[*layer].push(i);
+    }
+    let rows: Vec<&[usize]> = layered
+        .iter()
+        .flat_map(|layer| layer.chunks(MAX_LAYER_WIDTH))
+        .collect();
+    let layer_count = rows.len();
+    let widest = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let step = NODE_SIZE + GAP;
+    let mut positions = vec![Vec2::ZERO; count];
+    for (layer, row) in rows.iter().enumerate() {
+        // Shorter layers are centered against the widest one
+        let offset = (widest - row.len()) as f32 / 2.;
+        for (i, node) in row.iter().enumerate() {
+            let across = offset + i as f32;
+            let along = layer as f32;
+            let reversed = (layer_count - 1 - layer) as f32;
+            positions[*node] = match direction {
+                Direction::Down => Vec2::new(across * step.x, reversed * step.y),
+                Direction::Up => Vec2::new(across * step.x, along * step.y),
+                Direction::Right => {
+                    Vec2::new(along * step.x, (widest as f32 - 1. - across) * step.y)
+                }
+                Direction::Left => {
+                    Vec2::new(reversed * step.x, (widest as f32 - 1. - across) * step.y)
+                }
+            };
+        }
+    }
+    positions
+        .iter()
+        .map(|position| ORIGIN + *position)
+        .collect()
+}
+
+/// Bottom left corners of a tree growing to the right like a mind map, with
+/// every parent centered next to its children. `parents` holds the parent
+/// index of each node, which comes before the node itself.
+pub fn tree_layout(parents: &[Option<usize>]) -> Vec<Vec2> {
+    let count = parents.len();
+    let mut children = vec![vec![]; count];
+    let mut depths = vec![0; count];
+    for (i, parent) in parents.iter().enumerate() {
+        if let Some(parent) = parent {
+            children[*parent].push(i);
+            depths[i] = depths[*parent] + 1;
+        }
+    }
+    let mut rows = vec![0.; count];
+    let mut next_row = 0.;
+    // Leaves take the next free row in outline order
+    for i in 0..count {
+        if children[i].is_empty() {
+            rows[i] = next_row;
+            next_row += 1.;
+        }
+    }
+    // Children come after their parent, so going backwards places them first
+    for i in (0..count).rev() {
+        if let (Some(first), Some(last)) = (children[i].first(), children[i].last()) {
+            rows[i] = (rows[*first] + rows[*last]) / 2.;
+        }
+    }
+    let step = Vec2::new(NODE_SIZE.x + GAP.x, NODE_SIZE.y + SIBLING_GAP);
+    (0..count)
+        .map(|i| {
+            let y = (next_row - 1. - rows[i]) * step.y;
+            ORIGIN + Vec2::new(depths[i] as f32 * step.x, y)
+        })
+        .collect()
+}
+
+/// Rectangles for `texts` connected by `edges` of node indices, placed by
+/// `layered_layout`.
+pub fn graph_checkpoint(
+    texts: &[String],
+    edges: &[(usize, usize, ArrowType)],
+    direction: Direction,
+) -> Checkpoint {
+    let pairs: Vec<_> = edges.iter().map(|(from, to, _)| (*from, *to)).collect();
+    let nodes: Vec<_> = layered_layout(texts.len(), &pairs, direction)
+        .iter()
+        .zip(texts)
+        .map(|(position, text)| {
+            new_node(text, Rect::from_corners(*position, *position + NODE_SIZE))
+        })
+        .collect();
+    let arrows = edges
+        .iter()
+        .map(|(from, to, arrow_type)| connect(&nodes[*from], &nodes[*to], *arrow_type))
+        .collect();
+    Checkpoint {
+        nodes,
+        arrows,
+        ..Default::default()
+    }
+}
+
+/// A white rectangle with centered text.
+pub fn new_node(text: &str, rect: Rect) -> JsonNode {
+    JsonNode {
+        id: Uuid::new_v4(),
+        node_type: NodeType::Rect,
+        x: rect.min.x,
+        y: rect.min.y,
+        width: Val::Px(rect.width()),
+        height: Val::Px(rect.height()),
+        text: JsonNodeText {
+            text: text.to_string(),
+            pos: TextPos::Center,
+        },
+        bg_color: Color::WHITE,
+        tags: vec![],
+        z_index: 0,
+    }
+}
+
+/// Closest `TextPos` to a horizontal ("left", "center", "right") and vertical
+/// ("top", "middle", "bottom") alignment.
+pub fn aligned_text_pos(horizontal: &str, vertical: &str) -> TextPos {
+    match (vertical, horizontal) {
+        ("top", "left") => TextPos::TopLeft,
+        ("top", "right") => TextPos::TopRight,
+        ("bottom", "left") => TextPos::BottomLeft,
+        ("bottom", "right") => TextPos::BottomRight,
+        _ => TextPos::Center,
+    }
+}
+
+/// Side of a rectangle whose middle is closest to `point`.
+pub fn nearest_side(rect: Rect, point: Vec2) -> ArrowConnectPos {
+    use ArrowConnectPos::*;
+    [Top, Bottom, Left, Right]
+        .into_iter()
+        .min_by(|a, b| {
+            let distance = |pos| connect_point(rect, pos).distance(point);
+            distance(*a).total_cmp(&distance(*b))
+        })
+        .unwrap()
+}
+
+/// Shifts nodes so the bottom left corner of the diagram is at `ORIGIN`, for
+/// formats whose coordinates can be anywhere.
+pub fn move_to_origin(checkpoint: &mut Checkpoint) {
+    let Some(min) = checkpoint
+        .nodes
+        .iter()
+        .map(|node| node_rect(node).min)
+        .reduce(Vec2::min)
+    else {
+        return;
+    };
+    let offset = ORIGIN - min;
+    for node in checkpoint.nodes.iter_mut() {
+        node.x += offset.x;
+        node.y += offset.y;
+    }
+}
+
+/// An arrow between the closest sides of two nodes, like the canvas draws it.
+pub fn connect(start: &JsonNode, end: &JsonNode, arrow_type: ArrowType) -> ArrowMeta {
+    use ArrowConnectPos::*;
+    let (start_rect, end_rect) = (node_rect(start), node_rect(end));
+    let mut sides = vec![];
+    for start_pos in [Top, Bottom, Left, Right] {
+        for end_pos in [Top, Bottom, Left, Right] {
+            sides.push((start_pos, end_pos));
+        }
+    }
+    let distance = |(start_pos, end_pos): &(ArrowConnectPos, ArrowConnectPos)| {
+        connect_point(start_rect, *start_pos).distance(connect_point(end_rect, *end_pos))
+    };
+    let (start_pos, end_pos) = sides
+        .into_iter()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .unwrap();
+    ArrowMeta {
+        arrow_type,
+        start: ArrowConnect {
+            id: ReflectableUuid(start.id),
+            pos: start_pos,
+        },
+        end: ArrowConnect {
+            id: ReflectableUuid(end.id),
+            pos: end_pos,
+        },
+    }
+}
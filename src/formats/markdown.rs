@@ -0,0 +1,95 @@
+//! Markdown outline export of a tab, nested the way `tree_entries` infers
+//! from arrows. Repeated nodes are marked "see above" or "cycle" and their
+//! children are not written again.
+use bevy::prelude::Handle;
+
+use super::tree::{tree_entries, Repeat};
+use super::velo::Checkpoint;
+use crate::get_sections;
+
+pub const MARKDOWN_FILE_EXTENSION: &str = "md";
+const INDENT: &str = "  ";
+
+pub fn checkpoint_to_markdown(checkpoint: &Checkpoint) -> String {
+    let mut markdown = String::new();
+    for entry in tree_entries(checkpoint) {
+        let indent = INDENT.repeat(entry.depth);
+        let text = markdown_text(&entry.node.text.text);
+        let mut lines = text.lines();
+        let first = lines.next().unwrap_or_default();
+        markdown.push_str(&format!("{}- {}", indent, first));
+        for line in lines {
+            // Continuation lines belong to the same item
+            markdown.push_str(&format!("\n{}{}{}", indent, INDENT, line));
+        }
+        match entry.repeat {
+            Some(Repeat::SeeAbove) => markdown.push_str(" _(see above)_"),
+            Some(Repeat::Cycle) => markdown.push_str(" _(cycle)_"),
+            None => {}
+        }
+        markdown.push('\n');
+    }
+    markdown
+}
+
+// Links found the way the canvas finds them become autolinks
+fn markdown_text(text: &str) -> String {
+    let (sections, is_link) = get_sections(text.to_string(), Handle::default());
+    sections
+        .iter()
+        .zip(is_link)
+        .map(|(section, is_link)| {
+            if is_link {
+                format!("<{}>", section.value)
+            } else {
+                section.value.clone()
+            }
+        })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Rect;
+
+    use super::*;
+    use crate::canvas::arrow::components::ArrowType;
+    use crate::formats::layout::{connect, new_node};
+
+    #[test]
+    fn arrows_become_nesting() {
+        let node = |text: &str, x: f32, y: f32| new_node(text, Rect::new(x, y, x + 100., y + 50.));
+        let goal = node("Goal", 0., 400.);
+        let research = node("Research\nsee https://example.com", 0., 300.);
+        let build = node("Build", 200., 300.);
+        let test = node("Test", 100., 200.);
+        let loose = node("Loose idea", 400., 400.);
+        let arrows = vec![
+            connect(&goal, &research, ArrowType::Arrow),
+            connect(&goal, &build, ArrowType::Arrow),
+            connect(&research, &test, ArrowType::Arrow),
+            connect(&build, &test, ArrowType::Arrow),
+            connect(&test, &research, ArrowType::Arrow),
+        ];
+        let checkpoint = Checkpoint {
+            nodes: vec![test, loose, build, research, goal],
+            arrows,
+            ..Default::default()
+        };
+        assert_eq!(
+            checkpoint_to_markdown(&checkpoint),
+            "- Goal
+  - Research
+    see <https://example.com>
+    - Test
+      - Research
+        see <https://example.com> _(cycle)_
+  - Build
+    - Test _(see above)_
+- Loose idea
+"
+        );
+    }
+}
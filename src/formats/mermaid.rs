@@ -0,0 +1,365 @@
+//! Mermaid `flowchart` import and export.
+//!
+//! Export writes every node as `nX["text"]` followed by the links between
+//! them. Import understands the common subset of flowchart syntax: node
+//! shapes, chained links, `&` groups and link labels. Labels, styles and
+//! subgraphs are dropped, nodes get a generated layout.
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::layout::{graph_checkpoint, Direction};
+use super::node_rect;
+use super::velo::Checkpoint;
+use crate::canvas::arrow::components::ArrowType;
+use crate::errors::VeloError;
+
+pub const MERMAID_FILE_EXTENSIONS: [&str; 2] = ["mmd", "mermaid"];
+
+// Statements that don't add nodes or links
+const IGNORED: [&str; 8] = [
+    "subgraph",
+    "end",
+    "style",
+    "classDef",
+    "class",
+    "click",
+    "linkStyle",
+    "direction",
+];
+
+pub fn checkpoint_to_mermaid(checkpoint: &Checkpoint) -> String {
+    // Top to bottom, left to right, so the text reads like the diagram
+    let mut nodes: Vec<_> = checkpoint.nodes.iter().collect();
+    nodes.sort_by(|a, b| {
+        let (a, b) = (node_rect(a), node_rect(b));
+        b.max
+            .y
+            .total_cmp(&a.max.y)
+            .then(a.min.x.total_cmp(&b.min.x))
+    });
+    let ids: HashMap<_, _> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id, format!("n{}", i + 1)))
+        .collect();
+
+    let mut mermaid = "flowchart TD\n".to_string();
+    for node in nodes.iter() {
+        writeln!(
+            mermaid,
+            "    {}[\"{}\"]",
+            ids[&node.id],
+            escape(&node.text.text)
+        )
+        .unwrap();
+    }
+    for arrow in checkpoint.arrows.iter() {
+        let (Some(start), Some(end)) = (ids.get(&arrow.start.id.0), ids.get(&arrow.end.id.0))
+        else {
+            continue;
+        };
+        let link = match arrow.arrow_type {
+            ArrowType::Line | ArrowType::ParallelLine => "---",
+            ArrowType::Arrow | ArrowType::ParallelArrow => "-->",
+            ArrowType::DoubleArrow | ArrowType::ParallelDoubleArrow => "<-->",
+        };
+        writeln!(mermaid, "    {} {} {}", start, link, end).unwrap();
+    }
+    mermaid
+}
+
+#[derive(Default)]
+struct Graph {
+    index: HashMap<String, usize>,
+    texts: Vec<String>,
+    edges: Vec<(usize, usize, ArrowType)>,
+}
+
+impl Graph {
+    fn node(&mut self, id: String, text: Option<String>) -> usize {
+        let i = *self.index.entry(id.clone()).or_insert_with(|| {
+            self.texts.push(id);
+            self.texts.len() - 1
+        });
+        if let Some(text) = text {
+            self.texts[i] = text;
+        }
+        i
+    }
+}
+
+pub fn parse_mermaid(source: &str) -> Result<Checkpoint, VeloError> {
+    let mut lines = source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim().trim_end_matches(';')))
+        // Code fences are kept when a snippet is copied out of markdown
+        .filter(|(_, line)| {
+            !line.is_empty() && !line.starts_with("%%") && !line.starts_with("```")
+        });
+    let Some((_, header)) = lines.next() else {
+        return Err(VeloError::Import("empty Mermaid diagram".to_string()));
+    };
+    let mut words = header.split_whitespace();
+    if !matches!(words.next(), Some("flowchart" | "graph")) {
+        return Err(VeloError::Import(format!(
+            "only Mermaid flowcharts are supported, got \"{}\"",
+            header
+        )));
+    }
+    let direction = match words.next() {
+        Some("BT") => Direction::Up,
+        Some("LR") => Direction::Right,
+        Some("RL") => Direction::Left,
+        _ => Direction::Down,
+    };
+
+    let mut graph = Graph::default();
+    for (number, line) in lines {
+        let keyword = line.split_whitespace().next().unwrap();
+        if IGNORED.contains(&keyword) {
+            continue;
+        }
+        parse_statement(line, &mut graph)
+            .map_err(|error| VeloError::Import(format!("line {}: {}", number, error)))?;
+    }
+    Ok(graph_checkpoint(&graph.texts, &graph.edges, direction))
+}
+
+struct Cursor {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Cursor {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().filter(|c| f(*c)).is_some() {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn skip_spaces(&mut self) {
+        self.take_while(char::is_whitespace);
+    }
+
+    fn rest(&self) -> String {
+        self.chars[self.pos..].iter().collect()
+    }
+
+    // Moves past the next `pattern` and returns what was skipped
+    fn take_until(&mut self, pattern: &str) -> Option<String> {
+        let rest = self.rest();
+        let end = rest.find(pattern)?;
+        self.pos += rest[..end].chars().count() + pattern.chars().count();
+        Some(rest[..end].to_string())
+    }
+}
+
+// `a --> b & c --- d`
+fn parse_statement(line: &str, graph: &mut Graph) -> Result<(), String> {
+    let mut cursor = Cursor {
+        chars: line.chars().collect(),
+        pos: 0,
+    };
+    let mut sources = parse_group(&mut cursor, graph)?;
+    loop {
+        cursor.skip_spaces();
+        if cursor.peek().is_none() {
+            return Ok(());
+        }
+        let arrow_type = parse_link(&mut cursor)?;
+        let targets = parse_group(&mut cursor, graph)?;
+        for source in sources.iter() {
+            for target in targets.iter() {
+                graph.edges.push((*source, *target, arrow_type));
+            }
+        }
+        sources = targets;
+    }
+}
+
+fn parse_group(cursor: &mut Cursor, graph: &mut Graph) -> Result<Vec<usize>, String> {
+    let mut nodes = vec![];
+    loop {
+        cursor.skip_spaces();
+        nodes.push(parse_node(cursor, graph)?);
+        cursor.skip_spaces();
+        if !cursor.eat('&') {
+            return Ok(nodes);
+        }
+    }
+}
+
+// `id`, `id[text]`, `id((text))`, `id{"text"}`, ...
+fn parse_node(cursor: &mut Cursor, graph: &mut Graph) -> Result<usize, String> {
+    let id = cursor.take_while(|c| c.is_alphanumeric() || c == '_');
+    if id.is_empty() {
+        return Err(format!("expected a node at \"{}\"", cursor.rest()));
+    }
+    let mut opening = cursor.take_while(|c| "[({>".contains(c));
+    let text = if opening.is_empty() {
+        None
+    } else {
+        if let Some(c) = cursor.peek().filter(|c| *c == '/' || *c == '\\') {
+            opening.push(c);
+            cursor.pos += 1;
+        }
+        let closing: String = opening
+            .chars()
+            .rev()
+            .map(|c| match c {
+                '[' | '>' => ']',
+                '(' => ')',
+                '{' => '}',
+                c => c,
+            })
+            .collect();
+        cursor.skip_spaces();
+        let text = if cursor.eat('"') {
+            // Quoted text may contain the closing brackets
+            let text = cursor.take_until("\"").ok_or("quote is never closed")?;
+            cursor.take_until(&closing);
+            text
+        } else {
+            let text = cursor.take_until(&closing);
+            text.ok_or(format!("\"{}\" is never closed", opening))?
+        };
+        let text = text.trim();
+        Some(unescape(text))
+    };
+    // Class shorthand, `id:::class`
+    if cursor.rest().starts_with(":::") {
+        cursor.pos += 3;
+        cursor.take_while(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    }
+    Ok(graph.node(id, text))
+}
+
+// `---`, `-->`, `<-->`, `==>`, `-.->`, `-- text -->`, `-->|text|`
+fn parse_link(cursor: &mut Cursor) -> Result<ArrowType, String> {
+    let start = cursor.rest();
+    let head = cursor.eat('<');
+    let line = cursor.take_while(|c| "-=.".contains(c));
+    if line.len() < 2 {
+        return Err(format!("expected a link at \"{}\"", start));
+    }
+    let mut tail = cursor.eat('>');
+    if !tail && line.len() == 2 && matches!(cursor.peek(), Some(c) if c.is_whitespace()) {
+        // The label is followed by the rest of the link
+        let rest = cursor.rest();
+        let end = ["-->", "---", "==>", "===", ".->", ".-"]
+            .iter()
+            .filter_map(|pattern| rest.find(pattern))
+            .min()
+            .ok_or(format!("link \"{}\" is never closed", start))?;
+        cursor.pos += rest[..end].chars().count();
+        cursor.take_while(|c| "-=.".contains(c));
+        tail = cursor.eat('>');
+    }
+    // Circle and cross ends have no counterpart, they become arrow heads
+    if !tail && matches!(cursor.peek(), Some('o' | 'x')) {
+        let next = cursor.chars.get(cursor.pos + 1);
+        if !matches!(next, Some(c) if !c.is_whitespace()) {
+            cursor.pos += 1;
+            tail = true;
+        }
+    }
+    cursor.skip_spaces();
+    if cursor.eat('|') {
+        cursor
+            .take_until("|")
+            .ok_or(format!("link label in \"{}\" is never closed", start))?;
+    }
+    Ok(match (head, tail) {
+        (true, true) => ArrowType::DoubleArrow,
+        (_, true) => ArrowType::Arrow,
+        _ => ArrowType::Line,
+    })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('"', "#quot;").replace('\n', "<br>")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("#quot;", "\"")
+        .replace("<br/>", "\n")
+        .replace("<br />", "\n")
+        .replace("<br>", "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flowchart_from_readme_is_imported() {
+        let source = r#"
+```mermaid
+flowchart LR
+    %% comment
+    A[Client] -->|HTTP| B(API "gateway")
+    A --> F["a ] b"]
+    B --> C[(Database)] & D{{"Cache <br> layer"}}
+    C -- replicates --- E
+    E <--> A;
+    style A fill:#f9f
+```"#;
+        let checkpoint = parse_mermaid(source).unwrap();
+        let texts: Vec<_> = checkpoint
+            .nodes
+            .iter()
+            .map(|x| x.text.text.as_str())
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Client",
+                "API \"gateway\"",
+                "a ] b",
+                "Database",
+                "Cache \n layer",
+                "E"
+            ]
+        );
+        let types: Vec<_> = checkpoint.arrows.iter().map(|x| x.arrow_type).collect();
+        use ArrowType::*;
+        assert_eq!(types, vec![Arrow, Arrow, Arrow, Arrow, Line, DoubleArrow]);
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let source =
+            "flowchart TD\n    a[\"one\"] --> b[\"two\"]\n    b --- c[\"three\"]\n    c <--> a\n";
+        let checkpoint = parse_mermaid(source).unwrap();
+        let exported = checkpoint_to_mermaid(&checkpoint);
+        let again = parse_mermaid(&exported).unwrap();
+        let texts = |checkpoint: &Checkpoint| {
+            let mut texts: Vec<_> = checkpoint
+                .nodes
+                .iter()
+                .map(|x| x.text.text.clone())
+                .collect();
+            texts.sort();
+            texts
+        };
+        assert_eq!(texts(&checkpoint), texts(&again));
+        assert_eq!(exported.matches("-->").count(), 2);
+        assert_eq!(exported.matches("---").count(), 1);
+        assert!(parse_mermaid("sequenceDiagram\n    A->>B: hi").is_err());
+    }
+}
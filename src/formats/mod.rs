@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use bevy::prelude::{Color, Rect, Val, Vec2};
+
+use crate::canvas::arrow::components::{ArrowConnect, ArrowConnectPos, ArrowMeta};
+use crate::canvas::arrow::utils::arrow_lines;
+use crate::errors::VeloError;
+use crate::{JsonNode, TextPos};
+use velo::Checkpoint;
+
+pub mod csv;
+pub mod dot;
+pub mod drawio;
+pub mod excalidraw;
+pub mod html;
+pub mod json_canvas;
+pub mod layout;
+pub mod markdown;
+pub mod mermaid;
+pub mod opml;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod png;
+pub mod svg;
+pub mod tree;
+pub mod velo;
+
+/// Turns a doc name into something safe to use as a file name.
+pub fn file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.trim().is_empty() {
+        "Untitled".to_string()
+    } else {
+        name
+    }
+}
+
+/// Elements an import couldn't bring over, counted by kind.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub skipped: BTreeMap<String, usize>,
+}
+
+impl ImportSummary {
+    pub fn skip(&mut self, kind: &str) {
+        *self.skipped.entry(kind.to_string()).or_default() += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.skipped.is_empty()
+    }
+}
+
+impl fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let skipped: Vec<_> = self
+            .skipped
+            .iter()
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect();
+        write!(f, "skipped unsupported elements: {}", skipped.join(", "))
+    }
+}
+
+pub type TabParser = fn(&str) -> Result<(Checkpoint, ImportSummary), VeloError>;
+
+/// Parser of a single tab format by file extension, `None` for .velo files
+/// and anything unknown.
+pub fn tab_parser(extension: &str) -> Option<TabParser> {
+    let extension = extension.to_lowercase();
+    let extension = extension.as_str();
+    if mermaid::MERMAID_FILE_EXTENSIONS.contains(&extension) {
+        Some(|source| Ok((mermaid::parse_mermaid(source)?, ImportSummary::default())))
+    } else if dot::DOT_FILE_EXTENSIONS.contains(&extension) {
+        Some(|source| Ok((dot::parse_dot(source)?, ImportSummary::default())))
+    } else if extension == excalidraw::EXCALIDRAW_FILE_EXTENSION {
+        Some(excalidraw::parse_excalidraw)
+    } else if extension == drawio::DRAWIO_FILE_EXTENSION {
+        Some(drawio::parse_drawio)
+    } else if extension == json_canvas::CANVAS_FILE_EXTENSION {
+        Some(json_canvas::parse_canvas)
+    } else if extension == opml::OPML_FILE_EXTENSION {
+        Some(opml::parse_opml)
+    } else {
+        None
+    }
+}
+
+/// Color written as `#rrggbb`, `#rrggbbaa` or one of the common X11 names
+/// that show up most in generated files.
+pub fn parse_color(value: &str) -> Option<Color> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        return Color::hex(hex).ok();
+    }
+    let rgb = match value.to_lowercase().as_str() {
+        "white" => [255, 255, 255],
+        "black" => [0, 0, 0],
+        "red" => [255, 0, 0],
+        "green" => [0, 255, 0],
+        "blue" => [0, 0, 255],
+        "yellow" => [255, 255, 0],
+        "orange" => [255, 165, 0],
+        "pink" => [255, 192, 203],
+        "purple" => [160, 32, 240],
+        "cyan" => [0, 255, 255],
+        "gray" | "grey" => [190, 190, 190],
+        "lightgray" | "lightgrey" => [211, 211, 211],
+        "lightblue" => [173, 216, 230],
+        "lightgreen" | "palegreen" => [152, 251, 152],
+        "lightyellow" => [255, 255, 224],
+        "lightpink" => [255, 182, 193],
+        "salmon" => [250, 128, 114],
+        "gold" => [255, 215, 0],
+        "khaki" => [240, 230, 140],
+        "lavender" => [230, 230, 250],
+        _ => return None,
+    };
+    Some(Color::rgb_u8(rgb[0], rgb[1], rgb[2]))
+}
+
+/// Size of node text, matches the canvas.
+pub const FONT_SIZE: f32 = 18.;
+/// Space between the node border and its text.
+pub const TEXT_PADDING: f32 = 5.;
+/// Empty space around exported diagrams.
+pub const MARGIN: f32 = 20.;
+
+/// Pixels of a node style value, everything but `Val::Px` counts as 0.
+pub fn px(val: Val) -> f32 {
+    match val {
+        Val::Px(x) => x,
+        _ => 0.,
+    }
+}
+
+/// Rectangle of a node in world coordinates, y grows upwards.
+pub fn node_rect(node: &JsonNode) -> Rect {
+    let min = Vec2::new(node.x, node.y);
+    Rect::from_corners(min, min + Vec2::new(px(node.width), px(node.height)))
+}
+
+/// Middle of the rectangle side an arrow is attached to.
+pub fn connect_point(rect: Rect, pos: ArrowConnectPos) -> Vec2 {
+    let center = rect.center();
+    match pos {
+        ArrowConnectPos::Top => Vec2::new(center.x, rect.max.y),
+        ArrowConnectPos::Bottom => Vec2::new(center.x, rect.min.y),
+        ArrowConnectPos::Left => Vec2::new(rect.min.x, center.y),
+        ArrowConnectPos::Right => Vec2::new(rect.max.x, center.y),
+    }
+}
+
+/// Start and end of an arrow, `None` if one of its nodes is missing.
+pub fn arrow_ends(nodes: &[JsonNode], arrow: &ArrowMeta) -> Option<(Vec2, Vec2)> {
+    let point = |connect: ArrowConnect| {
+        let node = nodes.iter().find(|node| node.id == connect.id.0)?;
+        Some(connect_point(node_rect(node), connect.pos))
+    };
+    Some((point(arrow.start)?, point(arrow.end)?))
+}
+
+/// Smallest rectangle around all nodes and arrows, grown by `MARGIN`.
+pub fn diagram_bounds(checkpoint: &Checkpoint) -> Rect {
+    let mut points = vec![];
+    for node in checkpoint.nodes.iter() {
+        let rect = node_rect(node);
+        points.extend([rect.min, rect.max]);
+    }
+    for arrow in checkpoint.arrows.iter() {
+        if let Some((start, end)) = arrow_ends(&checkpoint.nodes, arrow) {
+            points.extend(arrow_lines(start, end, *arrow).into_iter().flatten());
+        }
+    }
+    let bounds = match points.split_first() {
+        Some((first, rest)) => rest
+            .iter()
+            .fold(Rect::from_corners(*first, *first), |rect, point| {
+                rect.union_point(*point)
+            }),
+        None => Rect::default(),
+    };
+    bounds.inset(MARGIN)
+}
+
+/// Top left corner of a block of text placed inside a node by `TextPos`, lines
+/// are left aligned inside the block like on the canvas. `top_left` and the
+/// result are in image coordinates where y grows downwards.
+pub fn text_origin(top_left: Vec2, size: Vec2, block: Vec2, pos: &TextPos) -> Vec2 {
+    let min = top_left + TEXT_PADDING;
+    let max = top_left + size - TEXT_PADDING - block;
+    match pos {
+        TextPos::TopLeft => min,
+        TextPos::TopRight => Vec2::new(max.x, min.y),
+        TextPos::BottomLeft => Vec2::new(min.x, max.y),
+        TextPos::BottomRight => max,
+        TextPos::Center => top_left + (size - block) / 2.,
+    }
+}
+
+/// Breaks text into lines at word boundaries like the canvas does, words
+/// wider than `max_width` are split between characters.
+pub fn wrap_text(text: &str, max_width: f32, measure: impl Fn(&str) -> f32) -> Vec<String> {
+    let mut lines = vec![];
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split_inclusive(' ') {
+            if measure(&format!("{}{}", line, word.trim_end())) <= max_width {
+                line.push_str(word);
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(line.trim_end().to_string());
+                line = String::new();
+            }
+            for c in word.chars() {
+                if !line.is_empty() && c != ' ' && measure(&format!("{}{}", line, c)) > max_width {
+                    lines.push(line);
+                    line = String::new();
+                }
+                line.push(c);
+            }
+        }
+        lines.push(line.trim_end().to_string());
+    }
+    lines
+}
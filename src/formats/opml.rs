@@ -0,0 +1,179 @@
+//! OPML import and export, the outline format shared by outliners.
+//!
+//! Import turns every outline item into a rectangle with an arrow from its
+//! parent and lays the tree out like a mind map. Export nests nodes the way
+//! `tree_entries` infers from arrows. OPML is a tree, so a node with several
+//! parents is only written under the first one.
+use std::fmt::Write;
+
+use bevy::prelude::Rect;
+use roxmltree::{Document, Node};
+
+use super::layout::{connect, new_node, tree_layout, NODE_SIZE};
+use super::tree::tree_entries;
+use super::velo::Checkpoint;
+use super::ImportSummary;
+use crate::canvas::arrow::components::ArrowType;
+use crate::errors::VeloError;
+
+pub const OPML_FILE_EXTENSION: &str = "opml";
+
+pub fn checkpoint_to_opml(checkpoint: &Checkpoint, title: &str) -> String {
+    let mut opml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n  <head>\n    <title>{}</title>\n  </head>\n  <body>\n",
+        escape(title)
+    );
+    let entries: Vec<_> = tree_entries(checkpoint)
+        .into_iter()
+        .filter(|entry| entry.repeat.is_none())
+        .collect();
+    for (i, entry) in entries.iter().enumerate() {
+        let indent = "  ".repeat(entry.depth + 2);
+        let next_depth = entries.get(i + 1).map_or(0, |next| next.depth);
+        let text = escape(&entry.node.text.text);
+        if next_depth > entry.depth {
+            writeln!(opml, "{}<outline text=\"{}\">", indent, text).unwrap();
+            continue;
+        }
+        writeln!(opml, "{}<outline text=\"{}\"/>", indent, text).unwrap();
+        // Close the parents this was the last child of
+        for depth in (next_depth..entry.depth).rev() {
+            writeln!(opml, "{}</outline>", "  ".repeat(depth + 2)).unwrap();
+        }
+    }
+    opml.push_str("  </body>\n</opml>\n");
+    opml
+}
+
+pub fn parse_opml(source: &str) -> Result<(Checkpoint, ImportSummary), VeloError> {
+    let error = |error: String| VeloError::Import(error);
+    let document = Document::parse(source).map_err(|e| error(e.to_string()))?;
+    let root = document.root_element();
+    if !root.has_tag_name("opml") {
+        return Err(error(format!(
+            "expected an OPML file, got <{}>",
+            root.tag_name().name()
+        )));
+    }
+    let body = root
+        .children()
+        .find(|x| x.has_tag_name("body"))
+        .ok_or_else(|| error("file has no <body>".to_string()))?;
+    let mut texts = vec![];
+    let mut parents = vec![];
+    read_outlines(body, None, &mut texts, &mut parents);
+
+    let nodes: Vec<_> = tree_layout(&parents)
+        .into_iter()
+        .zip(texts.iter())
+        .map(|(position, text)| new_node(text, Rect::from_corners(position, position + NODE_SIZE)))
+        .collect();
+    let arrows = parents
+        .iter()
+        .enumerate()
+        .filter_map(|(i, parent)| Some(connect(&nodes[(*parent)?], &nodes[i], ArrowType::Arrow)))
+        .collect();
+    let checkpoint = Checkpoint {
+        nodes,
+        arrows,
+        ..Default::default()
+    };
+    Ok((checkpoint, ImportSummary::default()))
+}
+
+// Depth first, so every item comes after its parent
+fn read_outlines(
+    node: Node,
+    parent: Option<usize>,
+    texts: &mut Vec<String>,
+    parents: &mut Vec<Option<usize>>,
+) {
+    for outline in node.children().filter(|x| x.has_tag_name("outline")) {
+        let mut text = outline
+            .attribute("text")
+            .or_else(|| outline.attribute("title"))
+            .unwrap_or_default()
+            .to_string();
+        if let Some(note) = outline.attribute("_note").filter(|x| !x.is_empty()) {
+            text = format!("{}\n{}", text, note);
+        }
+        texts.push(text);
+        parents.push(parent);
+        read_outlines(outline, Some(texts.len() - 1), texts, parents);
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::node_rect;
+
+    const OPML: &str = r#"<?xml version="1.0"?>
+<opml version="2.0">
+  <head><title>Launch</title></head>
+  <body>
+    <outline text="Launch">
+      <outline text="Docs" _note="API &amp; guides">
+        <outline text="Tutorial"/>
+      </outline>
+      <outline text="Marketing"/>
+    </outline>
+    <outline text="Later"/>
+  </body>
+</opml>"#;
+
+    #[test]
+    fn outline_becomes_tree() {
+        let (checkpoint, _) = parse_opml(OPML).unwrap();
+        let texts: Vec<_> = checkpoint
+            .nodes
+            .iter()
+            .map(|x| x.text.text.as_str())
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Launch",
+                "Docs\nAPI & guides",
+                "Tutorial",
+                "Marketing",
+                "Later"
+            ]
+        );
+        assert_eq!(checkpoint.arrows.len(), 3);
+        let rects: Vec<_> = checkpoint.nodes.iter().map(node_rect).collect();
+        // Children to the right, parents between their first and last child
+        assert!(rects[1].min.x > rects[0].max.x);
+        assert_eq!(rects[1].center().y, rects[2].center().y);
+        assert_eq!(
+            rects[0].center().y,
+            (rects[1].center().y + rects[3].center().y) / 2.
+        );
+        assert!(rects[4].max.y < rects[3].min.y);
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let (checkpoint, _) = parse_opml(OPML).unwrap();
+        let exported = checkpoint_to_opml(&checkpoint, "Launch");
+        assert!(exported.contains(r#"<outline text="Docs&#10;API &amp; guides">"#));
+        let (again, _) = parse_opml(&exported).unwrap();
+        let texts = |checkpoint: &Checkpoint| -> Vec<String> {
+            checkpoint
+                .nodes
+                .iter()
+                .map(|x| x.text.text.clone())
+                .collect()
+        };
+        assert_eq!(texts(&checkpoint), texts(&again));
+        assert_eq!(again.arrows.len(), 3);
+    }
+}
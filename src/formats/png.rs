@@ -0,0 +1,254 @@
+//! PNG export of a tab checkpoint. Rendering happens on the CPU, so it works
+//! headless, e.g. in CI pipelines that regenerate images for docs.
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+use base64::{engine::general_purpose, Engine};
+use bevy::prelude::{Color, Vec2};
+use tiny_skia::{
+    FilterQuality, Paint, PathBuilder, Pixmap, PixmapPaint, PremultipliedColorU8, Stroke, Transform,
+};
+
+use super::velo::Checkpoint;
+use super::{
+    arrow_ends, diagram_bounds, node_rect, text_origin, wrap_text, FONT_SIZE, TEXT_PADDING,
+};
+use crate::canvas::arrow::utils::arrow_lines;
+use crate::errors::VeloError;
+use crate::TextPos;
+
+pub const PNG_FILE_EXTENSION: &str = "png";
+/// Font of node text, relative to the working directory.
+pub const DEFAULT_FONT_PATH: &str = "assets/fonts/iosevka-regular.ttf";
+
+/// Renders nodes and arrows of a checkpoint `scale` times bigger than on the
+/// canvas. Images are looked up by hash with `get_image`.
+pub fn checkpoint_to_png(
+    checkpoint: &Checkpoint,
+    get_image: impl Fn(&str) -> Option<String>,
+    font: &FontArc,
+    scale: f32,
+) -> Result<Vec<u8>, VeloError> {
+    let bounds = diagram_bounds(checkpoint);
+    let size = bounds.size() * scale;
+    let mut pixmap = Pixmap::new(size.x.ceil() as u32, size.y.ceil() as u32)
+        .ok_or_else(|| VeloError::Export(format!("can't render a {}x{} image", size.x, size.y)))?;
+    pixmap.fill(tiny_skia::Color::WHITE);
+    // Canvas y grows upwards, image y downwards
+    let to_image = |point: Vec2| Vec2::new(point.x - bounds.min.x, bounds.max.y - point.y);
+    let black = paint(Color::BLACK);
+    let stroke = Stroke {
+        width: scale,
+        ..Default::default()
+    };
+
+    for arrow in checkpoint.arrows.iter() {
+        let Some((start, end)) = arrow_ends(&checkpoint.nodes, arrow) else {
+            continue;
+        };
+        for line in arrow_lines(start, end, *arrow) {
+            let mut path = PathBuilder::new();
+            for (i, point) in line.iter().enumerate() {
+                let point = to_image(*point) * scale;
+                if i == 0 {
+                    path.move_to(point.x, point.y);
+                } else {
+                    path.line_to(point.x, point.y);
+                }
+            }
+            if let Some(path) = path.finish() {
+                pixmap.stroke_path(&path, &black, &stroke, Transform::identity(), None);
+            }
+        }
+    }
+
+    let mut nodes: Vec<_> = checkpoint.nodes.iter().collect();
+    nodes.sort_by_key(|node| node.z_index);
+    for node in nodes {
+        let rect = node_rect(node);
+        let top_left = to_image(Vec2::new(rect.min.x, rect.max.y));
+        let Some(area) = tiny_skia::Rect::from_xywh(
+            top_left.x * scale,
+            top_left.y * scale,
+            rect.width() * scale,
+            rect.height() * scale,
+        ) else {
+            continue;
+        };
+        pixmap.fill_rect(area, &paint(node.bg_color), Transform::identity(), None);
+        let id = node.id.to_string();
+        let image = match checkpoint.images.get(&id) {
+            Some(hash) => get_image(hash),
+            None => checkpoint.inline_images.get(&id).cloned(),
+        };
+        if let Some(image) = image {
+            draw_image(&mut pixmap, &image, area)?;
+        }
+        let outline = PathBuilder::from_rect(area);
+        pixmap.stroke_path(&outline, &black, &stroke, Transform::identity(), None);
+        let text = &node.text;
+        draw_text(
+            &mut pixmap,
+            font,
+            top_left,
+            rect.size(),
+            &text.text,
+            &text.pos,
+            scale,
+        );
+    }
+
+    pixmap
+        .encode_png()
+        .map_err(|error| VeloError::Export(error.to_string()))
+}
+
+fn paint(color: Color) -> Paint<'static> {
+    let [r, g, b, a] = color
+        .as_rgba_f32()
+        .map(|x| (x.clamp(0., 1.) * 255.).round() as u8);
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(r, g, b, a);
+    paint
+}
+
+// Stretched over the whole node like on the canvas
+fn draw_image(pixmap: &mut Pixmap, image: &str, area: tiny_skia::Rect) -> Result<(), VeloError> {
+    let png = general_purpose::STANDARD
+        .decode(image.as_bytes())
+        .map_err(|error| VeloError::Image(error.to_string()))?;
+    let image = Pixmap::decode_png(&png).map_err(|error| VeloError::Image(error.to_string()))?;
+    let transform = Transform::from_row(
+        area.width() / image.width() as f32,
+        0.,
+        0.,
+        area.height() / image.height() as f32,
+        area.x(),
+        area.y(),
+    );
+    let paint = PixmapPaint {
+        quality: FilterQuality::Bilinear,
+        ..Default::default()
+    };
+    pixmap.draw_pixmap(0, 0, image.as_ref(), &paint, transform, None);
+    Ok(())
+}
+
+// Lays out at canvas size so lines break at the same words at every scale
+fn draw_text(
+    pixmap: &mut Pixmap,
+    font: &FontArc,
+    top_left: Vec2,
+    size: Vec2,
+    text: &str,
+    pos: &TextPos,
+    scale: f32,
+) {
+    let layout_font = font.as_scaled(PxScale::from(FONT_SIZE));
+    let width = |line: &str| line_width(&layout_font, line);
+    let lines = wrap_text(text, size.x - 2. * TEXT_PADDING, width);
+    let line_height = layout_font.height() + layout_font.line_gap();
+    let block = Vec2::new(
+        lines.iter().map(|line| width(line)).fold(0., f32::max),
+        lines.len() as f32 * line_height,
+    );
+    let origin = text_origin(top_left, size, block, pos) * scale;
+
+    let font = font.as_scaled(PxScale::from(FONT_SIZE * scale));
+    for (i, line) in lines.iter().enumerate() {
+        let mut caret = point(
+            origin.x,
+            origin.y + i as f32 * line_height * scale + font.ascent(),
+        );
+        let mut previous = None;
+        for c in line.chars() {
+            let id = font.glyph_id(c);
+            if let Some(previous) = previous {
+                caret.x += font.kern(previous, id);
+            }
+            previous = Some(id);
+            let glyph = id.with_scale_and_position(font.scale(), caret);
+            caret.x += font.h_advance(id);
+            let Some(outline) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|x, y, coverage| {
+                let x = bounds.min.x as i32 + x as i32;
+                let y = bounds.min.y as i32 + y as i32;
+                darken(pixmap, x, y, coverage);
+            });
+        }
+    }
+}
+
+fn line_width<F: Font>(font: &impl ScaleFont<F>, line: &str) -> f32 {
+    let mut width = 0.;
+    let mut previous = None;
+    for c in line.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+// Blends black text into a pixel
+fn darken(pixmap: &mut Pixmap, x: i32, y: i32, coverage: f32) {
+    let (width, height) = (pixmap.width() as i32, pixmap.height() as i32);
+    if x < 0 || y < 0 || x >= width || y >= height {
+        return;
+    }
+    let pixel = &mut pixmap.pixels_mut()[(y * width + x) as usize];
+    let coverage = coverage.clamp(0., 1.);
+    let keep = |value: u8| (value as f32 * (1. - coverage)).round() as u8;
+    let alpha = (pixel.alpha() as f32 * (1. - coverage) + 255. * coverage).round() as u8;
+    if let Some(color) = PremultipliedColorU8::from_rgba(
+        keep(pixel.red()),
+        keep(pixel.green()),
+        keep(pixel.blue()),
+        alpha,
+    ) {
+        *pixel = color;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Rect;
+
+    use super::*;
+    use crate::formats::layout::new_node;
+
+    #[test]
+    fn text_and_images_are_rendered_at_scale() {
+        let font = std::fs::read(DEFAULT_FONT_PATH).unwrap();
+        let font = FontArc::try_from_vec(font).unwrap();
+        let mut image = Pixmap::new(2, 2).unwrap();
+        image.fill(tiny_skia::Color::from_rgba8(0, 0, 255, 255));
+        let image = general_purpose::STANDARD.encode(image.encode_png().unwrap());
+        let text = new_node("Hello", Rect::new(0., 0., 100., 100.));
+        let picture = new_node("", Rect::new(200., 0., 300., 100.));
+        let mut checkpoint = Checkpoint {
+            nodes: vec![text, picture],
+            ..Default::default()
+        };
+        let id = checkpoint.nodes[1].id.to_string();
+        checkpoint.images.insert(id, "hash".to_string());
+
+        let png = checkpoint_to_png(&checkpoint, |_| Some(image.clone()), &font, 2.).unwrap();
+        let pixmap = Pixmap::decode_png(&png).unwrap();
+        // 300x100 of nodes and a margin of 20 around them
+        assert_eq!((pixmap.width(), pixmap.height()), (680, 280));
+        let pixel = |x: u32, y: u32| pixmap.pixel(x, y).unwrap();
+        let picture_center = pixel(500, 140);
+        assert_eq!((picture_center.red(), picture_center.blue()), (0, 255));
+        let text_area = (60..220).flat_map(|x| (100..180).map(move |y| (x, y)));
+        assert!(text_area.into_iter().any(|(x, y)| pixel(x, y).red() < 128));
+
+        let png = checkpoint_to_png(&checkpoint, |_| Some(image.clone()), &font, 0.5).unwrap();
+        let pixmap = Pixmap::decode_png(&png).unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (170, 70));
+    }
+}
@@ -0,0 +1,261 @@
+//! SVG export of a tab checkpoint.
+//!
+//! Canvas coordinates grow upwards, SVG ones downwards, so every y is negated
+//! and the view box is fitted around the diagram.
+use std::fmt::Write;
+use std::ops::Range;
+
+use bevy::prelude::{Color, Handle, Vec2};
+
+use super::velo::Checkpoint;
+use super::{
+    arrow_ends, diagram_bounds, node_rect, text_origin, wrap_text, FONT_SIZE, TEXT_PADDING,
+};
+use crate::canvas::arrow::utils::arrow_lines;
+use crate::{get_sections, TextPos};
+
+pub const SVG_FILE_EXTENSION: &str = "svg";
+
+// Iosevka is monospaced with glyphs half as wide as they are high
+const CHAR_WIDTH: f32 = FONT_SIZE * 0.5;
+const LINE_HEIGHT: f32 = FONT_SIZE * 1.25;
+const ASCENT: f32 = FONT_SIZE * 0.95;
+
+/// Renders nodes and arrows of a checkpoint. Images are looked up by hash
+/// with `get_image` and embedded as base64 PNGs.
+pub fn checkpoint_to_svg(
+    checkpoint: &Checkpoint,
+    get_image: impl Fn(&str) -> Option<String>,
+) -> String {
+    let mut arrows = String::new();
+    for arrow in checkpoint.arrows.iter() {
+        let Some((start, end)) = arrow_ends(&checkpoint.nodes, arrow) else {
+            continue;
+        };
+        for line in arrow_lines(start, end, *arrow) {
+            let points: Vec<_> = line
+                .iter()
+                .map(|point| {
+                    let point = flip(*point);
+                    format!("{},{}", point.x, point.y)
+                })
+                .collect();
+            writeln!(
+                arrows,
+                r#"  <polyline points="{}" fill="none" stroke="black" stroke-width="1"/>"#,
+                points.join(" ")
+            )
+            .unwrap();
+        }
+    }
+
+    let mut nodes: Vec<_> = checkpoint.nodes.iter().collect();
+    nodes.sort_by_key(|node| node.z_index);
+    let mut rects = String::new();
+    for node in nodes {
+        let rect = node_rect(node);
+        let top_left = flip(Vec2::new(rect.min.x, rect.max.y));
+        let size = rect.size();
+        writeln!(
+            rects,
+            r#"  <rect x="{}" y="{}" width="{}" height="{}" fill="{}" fill-opacity="{}" stroke="black" stroke-width="1"/>"#,
+            top_left.x,
+            top_left.y,
+            size.x,
+            size.y,
+            rgb(node.bg_color),
+            node.bg_color.a(),
+        )
+        .unwrap();
+        let id = node.id.to_string();
+        let image = match checkpoint.images.get(&id) {
+            Some(hash) => get_image(hash),
+            None => checkpoint.inline_images.get(&id).cloned(),
+        };
+        if let Some(image) = image {
+            writeln!(
+                rects,
+                r#"  <image x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="none" href="data:image/png;base64,{}"/>"#,
+                top_left.x, top_left.y, size.x, size.y, image
+            )
+            .unwrap();
+        }
+        write_text(&mut rects, top_left, size, &node.text.text, &node.text.pos);
+    }
+
+    let bounds = diagram_bounds(checkpoint);
+    let top_left = flip(Vec2::new(bounds.min.x, bounds.max.y));
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" width="{}" height="{}">
+{}{}</svg>
+"#,
+        top_left.x,
+        top_left.y,
+        bounds.width(),
+        bounds.height(),
+        bounds.width(),
+        bounds.height(),
+        arrows,
+        rects
+    )
+}
+
+fn write_text(svg: &mut String, top_left: Vec2, size: Vec2, text: &str, pos: &TextPos) {
+    let width = |line: &str| line.chars().count() as f32 * CHAR_WIDTH;
+    let lines = wrap_text(text, size.x - 2. * TEXT_PADDING, width);
+    if lines.iter().all(|line| line.is_empty()) {
+        return;
+    }
+    let block = Vec2::new(
+        lines.iter().map(|line| width(line)).fold(0., f32::max),
+        lines.len() as f32 * LINE_HEIGHT,
+    );
+    let origin = text_origin(top_left, size, block, pos);
+    let links = link_ranges(text);
+    // Lines are pieces of the text in order, found to know where links are
+    let mut cursor = 0;
+    writeln!(
+        svg,
+        r#"  <text font-family="Iosevka, monospace" font-size="{}" xml:space="preserve">"#,
+        FONT_SIZE
+    )
+    .unwrap();
+    for (i, line) in lines.iter().enumerate() {
+        let start = text[cursor..].find(line.as_str()).unwrap_or(0) + cursor;
+        cursor = start + line.len();
+        writeln!(
+            svg,
+            r#"    <tspan x="{}" y="{}">{}</tspan>"#,
+            origin.x,
+            origin.y + i as f32 * LINE_HEIGHT + ASCENT,
+            line_with_links(line, start, &links)
+        )
+        .unwrap();
+    }
+    writeln!(svg, "  </text>").unwrap();
+}
+
+// Byte ranges of links in the text, found the way the canvas finds them
+fn link_ranges(text: &str) -> Vec<(Range<usize>, String)> {
+    let (sections, is_link) = get_sections(text.to_string(), Handle::default());
+    let mut start = 0;
+    let mut links = vec![];
+    for (section, is_link) in sections.iter().zip(is_link) {
+        let end = start + section.value.len();
+        if is_link {
+            links.push((start..end, section.value.clone()));
+        }
+        start = end;
+    }
+    links
+}
+
+// A line starting at byte `start` of the text, parts of links are blue like
+// on the canvas and open the link
+fn line_with_links(line: &str, start: usize, links: &[(Range<usize>, String)]) -> String {
+    let end = start + line.len();
+    let mut result = String::new();
+    let mut done = 0;
+    for (range, url) in links.iter() {
+        let (from, to) = (range.start.max(start), range.end.min(end));
+        if from >= to {
+            continue;
+        }
+        let (from, to) = (from - start, to - start);
+        result.push_str(&escape(&line[done..from]));
+        write!(
+            result,
+            r#"<a href="{}" target="_blank"><tspan fill="blue">{}</tspan></a>"#,
+            escape(url),
+            escape(&line[from..to])
+        )
+        .unwrap();
+        done = to;
+    }
+    result.push_str(&escape(&line[done..]));
+    result
+}
+
+fn flip(point: Vec2) -> Vec2 {
+    Vec2::new(point.x, -point.y)
+}
+
+fn rgb(color: Color) -> String {
+    let [r, g, b, _] = color.as_rgba_f32().map(|x| (x * 255.).round() as u8);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Rect;
+
+    use super::*;
+    use crate::canvas::arrow::components::{ArrowConnect, ArrowConnectPos, ArrowMeta, ArrowType};
+    use crate::formats::layout::new_node;
+    use crate::utils::ReflectableUuid;
+
+    #[test]
+    fn nodes_and_arrows_are_rendered() {
+        let mut a = new_node("a < b", Rect::new(0., 0., 100., 100.));
+        let b = new_node("", Rect::new(200., 0., 300., 100.));
+        a.bg_color = Color::rgb(1., 0., 0.);
+        let arrow = ArrowMeta {
+            arrow_type: ArrowType::Arrow,
+            start: ArrowConnect {
+                id: ReflectableUuid(a.id),
+                pos: ArrowConnectPos::Right,
+            },
+            end: ArrowConnect {
+                id: ReflectableUuid(b.id),
+                pos: ArrowConnectPos::Left,
+            },
+        };
+        let checkpoint = Checkpoint {
+            nodes: vec![a, b],
+            arrows: vec![arrow],
+            ..Default::default()
+        };
+        let svg = checkpoint_to_svg(&checkpoint, |_| None);
+        assert_eq!(svg.matches("<rect").count(), 2);
+        assert!(svg.contains(r##"fill="#ff0000""##));
+        assert!(svg.contains("a &lt; b"));
+        // A line and the two strokes of its head
+        assert_eq!(svg.matches("<polyline").count(), 3);
+        assert!(svg.contains(r#"points="100,-50 200,-50""#));
+    }
+
+    #[test]
+    fn text_wraps_at_word_boundaries() {
+        let lines = wrap_text("one two three\nfour", 5., |line| line.len() as f32);
+        assert_eq!(lines, vec!["one", "two", "three", "four"]);
+        let lines = wrap_text("abcdefg", 3., |line| line.len() as f32);
+        assert_eq!(lines, vec!["abc", "def", "g"]);
+    }
+
+    #[test]
+    fn links_are_clickable_across_lines() {
+        let checkpoint = Checkpoint {
+            nodes: vec![new_node(
+                "docs at https://example.com/guide",
+                Rect::new(0., 0., 100., 100.),
+            )],
+            ..Default::default()
+        };
+        let svg = checkpoint_to_svg(&checkpoint, |_| None);
+        let link = r#"<a href="https://example.com/guide" target="_blank">"#;
+        assert!(svg.contains(">docs at</tspan>"));
+        // Ten characters fit on a line, every part of the link opens it
+        assert_eq!(svg.matches(link).count(), 3);
+        assert!(svg.contains(&format!(
+            r#"{}<tspan fill="blue">ample.com/</tspan></a>"#,
+            link
+        )));
+    }
+}
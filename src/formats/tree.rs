@@ -0,0 +1,111 @@
+//! Parent and child structure of a tab, inferred from its arrows, for
+//! exports to outline formats.
+//!
+//! Arrows point from parents to children, nodes nobody points to are the top
+//! level. A node with several parents is visited under the first one and
+//! repeated under the others, an arrow back into the current branch is a
+//! cycle. Siblings are ordered top to bottom, left to right.
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use super::node_rect;
+use super::velo::Checkpoint;
+use crate::JsonNode;
+
+/// Why a node shows up again instead of with its children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    SeeAbove,
+    Cycle,
+}
+
+pub struct TreeEntry<'a> {
+    pub node: &'a JsonNode,
+    pub depth: usize,
+    pub repeat: Option<Repeat>,
+}
+
+/// Nodes in outline order, each followed by its children one level deeper.
+pub fn tree_entries(checkpoint: &Checkpoint) -> Vec<TreeEntry<'_>> {
+    let mut nodes: Vec<_> = checkpoint.nodes.iter().collect();
+    nodes.sort_by(|a, b| {
+        let (a, b) = (node_rect(a), node_rect(b));
+        b.max
+            .y
+            .total_cmp(&a.max.y)
+            .then(a.min.x.total_cmp(&b.min.x))
+    });
+    let order: HashMap<Uuid, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id, i))
+        .collect();
+    let mut children = vec![vec![]; nodes.len()];
+    let mut has_parent = vec![false; nodes.len()];
+    for arrow in checkpoint.arrows.iter() {
+        let (Some(from), Some(to)) = (order.get(&arrow.start.id.0), order.get(&arrow.end.id.0))
+        else {
+            continue;
+        };
+        if from != to && !children[*from].contains(to) {
+            children[*from].push(*to);
+            has_parent[*to] = true;
+        }
+    }
+    for list in children.iter_mut() {
+        list.sort();
+    }
+
+    let mut walk = Walk {
+        children,
+        visited: vec![false; nodes.len()],
+        branch: vec![],
+        entries: vec![],
+    };
+    for root in (0..nodes.len()).filter(|i| !has_parent[*i]) {
+        walk.visit(root, 0);
+    }
+    // Nodes that are only reachable from a cycle
+    while let Some(start) = (0..nodes.len()).find(|i| !walk.visited[*i]) {
+        walk.visit(start, 0);
+    }
+    walk.entries
+        .into_iter()
+        .map(|(node, depth, repeat)| TreeEntry {
+            node: nodes[node],
+            depth,
+            repeat,
+        })
+        .collect()
+}
+
+struct Walk {
+    children: Vec<Vec<usize>>,
+    visited: Vec<bool>,
+    // Nodes from the top level down to the one being visited
+    branch: Vec<usize>,
+    entries: Vec<(usize, usize, Option<Repeat>)>,
+}
+
+impl Walk {
+    fn visit(&mut self, node: usize, depth: usize) {
+        let repeat = if self.branch.contains(&node) {
+            Some(Repeat::Cycle)
+        } else if self.visited[node] {
+            Some(Repeat::SeeAbove)
+        } else {
+            None
+        };
+        self.entries.push((node, depth, repeat));
+        if repeat.is_some() {
+            return;
+        }
+        self.visited[node] = true;
+        self.branch.push(node);
+        for child in self.children[node].clone() {
+            self.visit(child, depth + 1);
+        }
+        self.branch.pop();
+    }
+}
@@ -0,0 +1,331 @@
+//! Versioned `.velo` document files.
+//!
+//! A file is a small header followed by the document and the latest
+//! checkpoint of every tab. The same schema version is stored inside tab
+//! checkpoints, so older checkpoints are migrated forward on load.
+use std::collections::{HashMap, VecDeque};
+
+use serde::{de::Error, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::canvas::arrow::components::ArrowMeta;
+use crate::components::{Doc, Tab};
+use crate::resources::CanvasView;
+use crate::utils::ReflectableUuid;
+use crate::JsonNode;
+
+pub const VELO_FILE_EXTENSION: &str = "velo";
+const VELO_FILE_FORMAT: &str = "velo";
+
+/// Current schema version of checkpoints and `.velo` files.
+/// Bump it together with a new entry in `MIGRATIONS`.
+pub const SCHEMA_VERSION: u64 = 3;
+
+/// `MIGRATIONS[i]` upgrades a checkpoint from version `i` to `i + 1`.
+const MIGRATIONS: [fn(&mut Value); SCHEMA_VERSION as usize] =
+    [migrate_v0_to_v1, migrate_v1_to_v2, migrate_v2_to_v3];
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Checkpoint {
+    pub nodes: Vec<JsonNode>,
+    pub arrows: Vec<ArrowMeta>,
+    /// Node id to the hash of its image in the image store.
+    pub images: HashMap<String, String>,
+    /// Node id to a base64 encoded PNG, used by `.velo` files and old checkpoints.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub inline_images: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VeloFileHeader {
+    pub format: String,
+    pub version: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VeloFileTab {
+    pub id: ReflectableUuid,
+    pub name: String,
+    pub is_active: bool,
+    #[serde(default)]
+    pub view: CanvasView,
+    #[serde(flatten)]
+    pub checkpoint: Checkpoint,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VeloFileDoc {
+    pub id: ReflectableUuid,
+    pub name: String,
+    pub tags: Vec<String>,
+    pub tabs: Vec<VeloFileTab>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VeloFile {
+    pub header: VeloFileHeader,
+    pub doc: VeloFileDoc,
+}
+
+impl VeloFile {
+    /// Builds a file from the latest checkpoint of every tab. Images are
+    /// looked up by hash with `get_image` and embedded into the file.
+    pub fn from_doc(
+        doc: &Doc,
+        get_image: impl Fn(&str) -> Option<String>,
+    ) -> serde_json::Result<Self> {
+        let mut tabs = vec![];
+        for tab in doc.tabs.iter() {
+            let mut checkpoint = latest_checkpoint(tab)?;
+            for (node_id, hash) in checkpoint.images.drain() {
+                if let Some(image) = get_image(&hash) {
+                    checkpoint.inline_images.insert(node_id, image);
+                }
+            }
+            tabs.push(VeloFileTab {
+                id: tab.id,
+                name: tab.name.clone(),
+                is_active: tab.is_active,
+                view: tab.view,
+                checkpoint,
+            });
+        }
+        Ok(VeloFile {
+            header: VeloFileHeader {
+                format: VELO_FILE_FORMAT.to_string(),
+                version: SCHEMA_VERSION,
+            },
+            doc: VeloFileDoc {
+                id: doc.id,
+                name: doc.name.clone(),
+                tags: doc.tags.clone(),
+                tabs,
+            },
+        })
+    }
+
+    pub fn into_doc(self) -> serde_json::Result<Doc> {
+        // The app always shows one of the tabs
+        if self.doc.tabs.is_empty() {
+            return Err(serde_json::Error::custom(
+                "a document needs at least one tab",
+            ));
+        }
+        let mut tabs = vec![];
+        for tab in self.doc.tabs {
+            let mut checkpoints = VecDeque::new();
+            checkpoints.push_back(checkpoint_to_string(&tab.checkpoint)?);
+            tabs.push(Tab {
+                id: tab.id,
+                name: tab.name,
+                is_active: tab.is_active,
+                checkpoints,
+                view: tab.view,
+            });
+        }
+        if !tabs.iter().any(|tab| tab.is_active) {
+            if let Some(tab) = tabs.first_mut() {
+                tab.is_active = true;
+            }
+        }
+        Ok(Doc {
+            id: self.doc.id,
+            name: self.doc.name,
+            tags: self.doc.tags,
+            tabs,
+        })
+    }
+}
+
+pub fn velo_file_to_string(file: &VeloFile) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(file)
+}
+
+/// Parses a `.velo` file and migrates every tab to `SCHEMA_VERSION`.
+pub fn parse_velo_file(json: &str) -> serde_json::Result<VeloFile> {
+    let mut value: Value = serde_json::from_str(json)?;
+    let Some(file) = value.as_object_mut() else {
+        return Err(serde_json::Error::custom("a velo file is a JSON object"));
+    };
+    let header: VeloFileHeader = serde_json::from_value(file.remove("header").unwrap_or_default())?;
+    if header.format != VELO_FILE_FORMAT {
+        return Err(serde_json::Error::custom(format!(
+            "unknown file format: {}",
+            header.format
+        )));
+    }
+    let mut doc = file.remove("doc").unwrap_or_default();
+    if let Some(tabs) = doc.get_mut("tabs").and_then(|tabs| tabs.as_array_mut()) {
+        for tab in tabs.iter_mut() {
+            migrate(tab, header.version)?;
+        }
+    }
+    let doc: VeloFileDoc = serde_json::from_value(doc)?;
+    Ok(VeloFile {
+        header: VeloFileHeader {
+            format: header.format,
+            version: SCHEMA_VERSION,
+        },
+        doc,
+    })
+}
+
+/// Latest checkpoint of a tab, an empty one if the tab was never saved.
+pub fn latest_checkpoint(tab: &Tab) -> serde_json::Result<Checkpoint> {
+    match tab.checkpoints.back() {
+        Some(json) => parse_checkpoint(json),
+        None => Ok(Checkpoint::default()),
+    }
+}
+
+/// Parses a tab checkpoint, migrating it from the version it was saved with.
+pub fn parse_checkpoint(json: &str) -> serde_json::Result<Checkpoint> {
+    let mut value: Value = serde_json::from_str(json)?;
+    migrate_checkpoint(&mut value)?;
+    serde_json::from_value(value)
+}
+
+pub fn checkpoint_to_string(checkpoint: &Checkpoint) -> serde_json::Result<String> {
+    let mut value = serde_json::to_value(checkpoint)?;
+    value["version"] = SCHEMA_VERSION.into();
+    serde_json::to_string(&value)
+}
+
+/// Checkpoints written before versioning was introduced have no `version` key.
+pub fn migrate_checkpoint(value: &mut Value) -> serde_json::Result<()> {
+    let version = value["version"].as_u64().unwrap_or(0);
+    migrate(value, version)
+}
+
+fn migrate(value: &mut Value, version: u64) -> serde_json::Result<()> {
+    // Migrations index into the checkpoint, which panics on other values
+    if !value.is_object() {
+        return Err(serde_json::Error::custom("a checkpoint is a JSON object"));
+    }
+    if version > SCHEMA_VERSION {
+        return Err(serde_json::Error::custom(format!(
+            "schema version {} is newer than supported version {}",
+            version, SCHEMA_VERSION
+        )));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(value);
+    }
+    value["version"] = SCHEMA_VERSION.into();
+    Ok(())
+}
+
+// Checkpoints saved before versioning already have the version 1 layout.
+fn migrate_v0_to_v1(_value: &mut Value) {}
+
+// Images moved out of checkpoints into the content-addressed image store,
+// base64 data saved by older versions is kept inline until the next save.
+fn migrate_v1_to_v2(value: &mut Value) {
+    value["inline_images"] = value["images"].take();
+    value["images"] = serde_json::json!({});
+}
+
+// Nodes were placed with UI offsets inside the canvas panel, which now pans
+// over world coordinates. The panel origin is where the world origin is shown
+// before panning, so the offsets carry over as they are.
+fn migrate_v2_to_v3(value: &mut Value) {
+    if let Some(nodes) = value["nodes"].as_array_mut() {
+        for node in nodes.iter_mut() {
+            for (from, to) in [("left", "x"), ("bottom", "y")] {
+                let offset = node[from]["Px"].as_f64().unwrap_or(0.);
+                if let Some(node) = node.as_object_mut() {
+                    node.remove(from);
+                    node.insert(to.to_string(), offset.into());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Rect;
+
+    use super::*;
+    use crate::formats::layout::new_node;
+
+    #[test]
+    fn panel_offsets_become_world_positions() {
+        let json = r#"{"version": 2, "images": {}, "arrows": [], "nodes": [{
+            "id": "5f2b9a3e-8a5b-4c4e-9a43-2d1f0c8e7b61", "node_type": "Rect",
+            "left": {"Px": 120.0}, "bottom": {"Px": -40.0}, "width": {"Px": 100.0},
+            "height": {"Px": 50.0}, "text": {"text": "a", "pos": "Center"},
+            "bg_color": {"Rgba": {"red": 1.0, "green": 1.0, "blue": 1.0, "alpha": 1.0}},
+            "tags": [], "z_index": 0}]}"#;
+        let checkpoint = parse_checkpoint(json).unwrap();
+        assert_eq!((checkpoint.nodes[0].x, checkpoint.nodes[0].y), (120., -40.));
+        let json = checkpoint_to_string(&checkpoint).unwrap();
+        assert!(!json.contains("left"));
+        assert_eq!(parse_checkpoint(&json).unwrap().nodes[0].x, 120.);
+    }
+
+    #[test]
+    fn unversioned_checkpoints_are_migrated() {
+        // As saved before versioning, with images inline and UI offsets
+        let json = r#"{"images": {"5f2b9a3e-8a5b-4c4e-9a43-2d1f0c8e7b61": "iVBORw0KGgo="},
+            "arrows": [], "nodes": [{
+            "id": "5f2b9a3e-8a5b-4c4e-9a43-2d1f0c8e7b61", "node_type": "Rect",
+            "left": {"Px": 10.0}, "bottom": {"Px": 20.0}, "width": {"Px": 100.0},
+            "height": {"Px": 50.0}, "text": {"text": "a", "pos": "Center"},
+            "bg_color": {"Rgba": {"red": 1.0, "green": 1.0, "blue": 1.0, "alpha": 1.0}},
+            "tags": [], "z_index": 0}]}"#;
+        let checkpoint = parse_checkpoint(json).unwrap();
+        assert!(checkpoint.images.is_empty());
+        assert_eq!(
+            checkpoint.inline_images.values().next().unwrap(),
+            "iVBORw0KGgo="
+        );
+        assert_eq!((checkpoint.nodes[0].x, checkpoint.nodes[0].y), (10., 20.));
+        assert!(parse_checkpoint(r#"{"version": 99}"#).is_err());
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let checkpoint = Checkpoint {
+            nodes: vec![new_node("a", Rect::new(0., 0., 100., 50.))],
+            images: HashMap::from([("node".to_string(), "hash".to_string())]),
+            ..Default::default()
+        };
+        let doc = Doc {
+            id: ReflectableUuid(uuid::Uuid::new_v4()),
+            name: "Plan".to_string(),
+            tags: vec!["work".to_string()],
+            tabs: vec![Tab {
+                id: ReflectableUuid(uuid::Uuid::new_v4()),
+                name: "Tab 1".to_string(),
+                checkpoints: VecDeque::from([checkpoint_to_string(&checkpoint).unwrap()]),
+                is_active: true,
+                view: Default::default(),
+            }],
+        };
+        let file = VeloFile::from_doc(&doc, |_| Some("png".to_string())).unwrap();
+        let json = velo_file_to_string(&file).unwrap();
+        let imported = parse_velo_file(&json).unwrap().into_doc().unwrap();
+        assert_eq!(
+            (imported.id, imported.name, imported.tags),
+            (doc.id, doc.name, doc.tags)
+        );
+        let checkpoint = latest_checkpoint(&imported.tabs[0]).unwrap();
+        assert_eq!(checkpoint.nodes[0].text.text, "a");
+        assert_eq!(checkpoint.inline_images["node"], "png");
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        for json in [
+            "[]",
+            r#"{"header": {"format": "velo", "version": 0}, "doc": {"tabs": [{"nodes": [1]}]}}"#,
+            r#"{"header": {"format": "velo", "version": 3}, "doc": {"tabs": [[]]}}"#,
+        ] {
+            assert!(parse_velo_file(json).is_err(), "{}", json);
+        }
+        let json = r#"{"header": {"format": "velo", "version": 3}, "doc": {
+            "id": "5f2b9a3e-8a5b-4c4e-9a43-2d1f0c8e7b61", "name": "", "tags": [], "tabs": []}}"#;
+        assert!(parse_velo_file(json).unwrap().into_doc().is_err());
+    }
+}
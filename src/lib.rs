@@ -1,17 +1,27 @@
 mod canvas;
 mod chart_plugin;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cli;
 mod components;
+mod errors;
+mod formats;
 mod resources;
+mod storage;
 mod systems;
 mod utils;
 use bevy::{prelude::*, window::PresentMode};
 use bevy_embedded_assets::EmbeddedAssetPlugin;
-use bevy_pkv::PkvStore;
 use bevy_ui_borders::BordersPlugin;
 use canvas::CanvasPlugin;
 use chart_plugin::*;
+pub use storage::StorageKind;
+use storage::Store;
 use systems::*;
-pub struct VeloPlugin;
+
+#[derive(Default)]
+pub struct VeloPlugin {
+    pub storage: StorageKind,
+}
 impl Plugin for VeloPlugin {
     fn build(&self, app: &mut App) {
         app.add_startup_system(setup_camera)
@@ -36,6 +46,6 @@ impl Plugin for VeloPlugin {
             .add_plugin(CanvasPlugin)
             .add_plugin(ChartPlugin)
             .add_plugin(BordersPlugin)
-            .insert_resource(PkvStore::new("", "velo"));
+            .insert_resource(Store::new(&self.storage).expect("the storage can't be opened"));
     }
 }
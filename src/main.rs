@@ -1,7 +1,15 @@
 use bevy::prelude::*;
-use velo::VeloPlugin;
+use velo::{StorageKind, VeloPlugin};
 fn main() {
     #[cfg(target_arch = "wasm32")]
     console_error_panic_hook::set_once();
-    App::new().add_plugin(VeloPlugin).run();
+    #[cfg(target_arch = "wasm32")]
+    let storage = StorageKind::Pkv;
+    // `velo <dir>` keeps documents as JSON files in <dir>
+    #[cfg(not(target_arch = "wasm32"))]
+    let storage = match std::env::args().nth(1) {
+        Some(dir) => StorageKind::Directory(dir.into()),
+        None => StorageKind::Pkv,
+    };
+    App::new().add_plugin(VeloPlugin { storage }).run();
 }
@@ -1,18 +1,63 @@
-use crate::components::Doc;
+use crate::formats::csv::CsvColumns;
+use crate::formats::velo::Checkpoint;
+use crate::storage::backup::RestoreStrategy;
+use crate::storage::cache::DocCache;
 use crate::utils::ReflectableUuid;
 use bevy::prelude::*;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 
 #[derive(Resource, Default)]
 pub struct StaticState {
     pub font: Option<Handle<Font>>,
     pub main_panel: Option<Entity>,
+    pub canvas: Option<Entity>, // Parent of the nodes inside `main_panel`, moved to pan
+}
+
+/// Part of the world shown in the main panel, every tab keeps its own.
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct CanvasView {
+    pub offset: Vec2, // World position at the bottom left corner of the panel
+    pub zoom: f32,    // Screen pixels per world unit
+}
+
+impl Default for CanvasView {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            zoom: 1.,
+        }
+    }
+}
+
+impl CanvasView {
+    pub const MIN_ZOOM: f32 = 0.1;
+    pub const MAX_ZOOM: f32 = 4.;
+
+    /// World position of a point in the panel, relative to its bottom left corner.
+    pub fn to_world(self, point: Vec2) -> Vec2 {
+        self.offset + point / self.zoom
+    }
+
+    /// Changes the zoom and keeps the world position under `point` in place.
+    pub fn zoom_around(&mut self, zoom: f32, point: Vec2) {
+        let anchor = self.to_world(point);
+        self.zoom = zoom.clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        self.offset = anchor - point / self.zoom;
+    }
+
+    /// Shows all of `rect` in a panel of `size`, as large as the zoom allows.
+    pub fn fit(&mut self, rect: Rect, size: Vec2, margin: f32) {
+        let scale = (size - 2. * margin) / rect.size().max(Vec2::ONE);
+        self.zoom = scale.min_element().clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        self.offset = rect.center() - size / 2. / self.zoom;
+    }
 }
 
 #[derive(Resource, Default)]
 pub struct AppState {
     pub current_document: Option<ReflectableUuid>,
-    pub docs: HashMap<ReflectableUuid, Doc>,
+    pub docs: DocCache,
 }
 
 #[derive(Resource, Debug)]
@@ -24,5 +69,112 @@ pub struct SaveRequest {
 #[derive(Resource, Debug)]
 pub struct LoadRequest {
     pub doc_id: Option<ReflectableUuid>, // None means current doc
-    pub drop_last_checkpoint: bool,      // Useful for undo functionality
+}
+
+#[derive(Resource, Debug)]
+pub struct ExportRequest {
+    pub doc_id: Option<ReflectableUuid>, // None means current doc
+    pub format: ExportFormat,
+}
+
+/// Nodes and arrows to add to the active tab through the same events the UI
+/// sends, arrows a few frames later once their nodes are laid out.
+#[derive(Resource)]
+pub struct PopulateRequest {
+    pub checkpoint: Checkpoint,
+    pub frame: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Velo,     // The whole doc
+    Svg,      // The active tab
+    Png,      // The active tab
+    Mermaid,  // The active tab
+    Dot,      // The active tab
+    Canvas,   // The active tab
+    Markdown, // The active tab
+    Opml,     // The active tab
+    Html,     // The whole doc
+    Backup,   // Every doc in the store
+}
+
+/// Background of the canvas.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GridStyle {
+    Off,
+    #[default]
+    Dots,
+    Lines,
+}
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Settings {
+    pub autosave_interval_secs: f32, // Idle time before a dirty doc is saved, 0 disables autosave
+    pub png_scale: f32,              // Size of exported PNGs relative to the canvas
+    pub csv_columns: CsvColumns,     // Headers read by the CSV import
+    pub restore_strategy: RestoreStrategy, // For docs of a dropped backup that are stored already
+    pub grid: GridStyle,             // Drawn behind the nodes
+    pub grid_spacing: f32,           // Canvas pixels between grid lines, also the snap step
+    pub snap_to_grid: bool, // Node edges snap to the grid when dragged or resized, alt skips it
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            autosave_interval_secs: 5.,
+            png_scale: 2.,
+            csv_columns: CsvColumns::default(),
+            restore_strategy: RestoreStrategy::default(),
+            grid: GridStyle::default(),
+            grid_spacing: 20.,
+            snap_to_grid: true,
+        }
+    }
+}
+
+impl Settings {
+    /// Closest grid line to a canvas coordinate, the coordinate itself when
+    /// snapping is off.
+    pub fn snap(&self, value: f32) -> f32 {
+        if !self.snap_to_grid || self.grid_spacing <= 0. {
+            return value;
+        }
+        (value / self.grid_spacing).round() * self.grid_spacing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zoom_keeps_point_and_fit_centers() {
+        let mut view = CanvasView {
+            offset: Vec2::new(100., 50.),
+            zoom: 1.,
+        };
+        let point = Vec2::new(200., 300.);
+        let anchor = view.to_world(point);
+        view.zoom_around(2., point);
+        assert_eq!(view.to_world(point), anchor);
+        view.zoom_around(100., point);
+        assert_eq!(view.zoom, CanvasView::MAX_ZOOM);
+
+        let size = Vec2::new(800., 600.);
+        view.fit(Rect::new(0., 0., 2000., 500.), size, 0.);
+        assert_eq!(view.zoom, 0.4);
+        assert_eq!(view.to_world(size / 2.), Vec2::new(1000., 250.));
+    }
+
+    #[test]
+    fn snap_rounds_to_grid_lines() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.snap(29.), 20.);
+        assert_eq!(settings.snap(-31.), -40.);
+        settings.snap_to_grid = false;
+        assert_eq!(settings.snap(29.), 29.);
+    }
 }
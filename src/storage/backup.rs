@@ -0,0 +1,313 @@
+//! Zip archive of everything in a store, to move it between machines.
+//!
+//! The archive holds `manifest.json`, the `names.json` index of docs, every
+//! doc with all tabs and checkpoints under `docs/<uuid>.json`, images as
+//! PNGs under `images/<sha256>.png` and `settings.json`. Restoring merges an
+//! archive into a store, docs that are stored already are replaced or kept
+//! next to the restored copy depending on the `RestoreStrategy`.
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Cursor, Read, Write};
+
+use base64::{engine::general_purpose, Engine};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use super::images::image_hash;
+use super::{storage_error, StorageBackend};
+use crate::components::Doc;
+use crate::errors::VeloError;
+use crate::resources::Settings;
+use crate::utils::ReflectableUuid;
+
+pub const BACKUP_FILE_EXTENSION: &str = "zip";
+const MANIFEST_FILE: &str = "manifest.json";
+const NAMES_FILE: &str = "names.json";
+const SETTINGS_FILE: &str = "settings.json";
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    app: String,
+    version: u32,
+}
+
+/// What a restore does with a doc whose id is stored already.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RestoreStrategy {
+    /// The restored doc overwrites the stored one, settings are restored too.
+    Replace,
+    /// The restored doc gets a new id next to the stored one, stored settings
+    /// are kept.
+    #[default]
+    KeepBoth,
+}
+
+#[derive(Debug, Default)]
+pub struct RestoreSummary {
+    /// Docs that were not stored before, with the id they are stored under.
+    pub added: Vec<(ReflectableUuid, String)>,
+    pub replaced: Vec<(ReflectableUuid, String)>,
+    pub images: usize,
+}
+
+impl fmt::Display for RestoreSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} docs added, {} replaced, {} images",
+            self.added.len(),
+            self.replaced.len(),
+            self.images
+        )
+    }
+}
+
+/// Writes every stored doc, image and the settings to a zip archive. Docs in
+/// `open_docs` are written instead of their stored version, so changes that
+/// are not saved yet are included.
+pub fn backup_store<'a>(
+    store: &dyn StorageBackend,
+    open_docs: impl Iterator<Item = &'a Doc>,
+) -> Result<Vec<u8>, VeloError> {
+    let names = store.doc_names();
+    let mut docs: HashMap<_, _> = open_docs
+        .filter(|doc| names.contains_key(&doc.id))
+        .map(|doc| (doc.id, doc.clone()))
+        .collect();
+    for id in names.keys() {
+        if !docs.contains_key(id) {
+            docs.insert(*id, store.load_doc(*id).ok_or(VeloError::DocNotFound(*id))?);
+        }
+    }
+
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    let mut write = |name: &str, content: &[u8]| -> Result<(), VeloError> {
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file(name, options).map_err(storage_error)?;
+        zip.write_all(content).map_err(storage_error)
+    };
+    let manifest = Manifest {
+        app: "velo".to_string(),
+        version: VERSION,
+    };
+    write(MANIFEST_FILE, &serde_json::to_vec_pretty(&manifest)?)?;
+    write(NAMES_FILE, &serde_json::to_vec_pretty(&names)?)?;
+    let settings = store.load_settings().unwrap_or_default();
+    write(SETTINGS_FILE, &serde_json::to_vec_pretty(&settings)?)?;
+    for (id, doc) in docs.iter() {
+        write(&doc_file(*id), &serde_json::to_vec(doc)?)?;
+    }
+    for hash in store.image_hashes() {
+        // Blobs that went missing are left out like garbage
+        let Some(image) = store.get_image(&hash) else {
+            continue;
+        };
+        let png = general_purpose::STANDARD
+            .decode(image)
+            .map_err(|error| VeloError::Image(error.to_string()))?;
+        write(&image_file(&hash), &png)?;
+    }
+    Ok(zip.finish().map_err(storage_error)?.into_inner())
+}
+
+/// Merges a backup archive into the store. Nothing is changed if the
+/// archive can't be read.
+pub fn restore_store(
+    store: &mut dyn StorageBackend,
+    archive: &[u8],
+    strategy: RestoreStrategy,
+) -> Result<RestoreSummary, VeloError> {
+    let error = |error: String| VeloError::Import(error);
+    let mut zip = ZipArchive::new(Cursor::new(archive)).map_err(|e| error(e.to_string()))?;
+    let hashes: Vec<_> = zip
+        .file_names()
+        .filter_map(|name| name.strip_prefix("images/")?.strip_suffix(".png"))
+        .map(|hash| hash.to_string())
+        .collect();
+    let mut read = |name: &str| -> Result<Vec<u8>, VeloError> {
+        let mut file = zip
+            .by_name(name)
+            .map_err(|e| error(format!("{}: {}", name, e)))?;
+        let mut content = vec![];
+        file.read_to_end(&mut content)
+            .map_err(|e| error(format!("{}: {}", name, e)))?;
+        Ok(content)
+    };
+    let manifest: Manifest = serde_json::from_slice(&read(MANIFEST_FILE)?)?;
+    if manifest.app != "velo" || manifest.version > VERSION {
+        return Err(error(format!(
+            "unsupported backup version {}",
+            manifest.version
+        )));
+    }
+    let names: HashMap<ReflectableUuid, String> = serde_json::from_slice(&read(NAMES_FILE)?)?;
+    let settings: Settings = serde_json::from_slice(&read(SETTINGS_FILE)?)?;
+    let mut docs = vec![];
+    for id in names.keys() {
+        let doc: Doc = serde_json::from_slice(&read(&doc_file(*id))?)?;
+        docs.push(doc);
+    }
+    let mut images = vec![];
+    for hash in hashes {
+        let png = read(&image_file(&hash))?;
+        // Names are paths in a directory store, only trust the content
+        if image_hash(&png) != hash {
+            return Err(error(format!(
+                "{} does not match its content",
+                image_file(&hash)
+            )));
+        }
+        images.push((png, hash));
+    }
+
+    let mut summary = RestoreSummary::default();
+    // Images first, so no restored checkpoint points to a missing one
+    let stored_images = store.image_hashes();
+    for (png, hash) in images.iter() {
+        if !stored_images.contains(hash) {
+            store.put_image(hash, png)?;
+            summary.images += 1;
+        }
+    }
+    let stored = store.doc_names();
+    for mut doc in docs {
+        if !stored.contains_key(&doc.id) {
+            store.save_doc(&doc)?;
+            summary.added.push((doc.id, doc.name));
+            continue;
+        }
+        match strategy {
+            RestoreStrategy::Replace => {
+                store.save_doc(&doc)?;
+                summary.replaced.push((doc.id, doc.name));
+            }
+            RestoreStrategy::KeepBoth => {
+                doc.id = ReflectableUuid(Uuid::new_v4());
+                doc.name = format!("{} (restored)", doc.name);
+                // Undo history is kept by tab id, the copy must not share it
+                for tab in doc.tabs.iter_mut() {
+                    tab.id = ReflectableUuid(Uuid::new_v4());
+                }
+                store.save_doc(&doc)?;
+                summary.added.push((doc.id, doc.name));
+            }
+        }
+    }
+    if strategy == RestoreStrategy::Replace || store.load_settings().is_none() {
+        store.save_settings(&settings)?;
+    }
+    Ok(summary)
+}
+
+fn doc_file(id: ReflectableUuid) -> String {
+    format!("docs/{}.json", id.0)
+}
+
+fn image_file(hash: &str) -> String {
+    format!("images/{}.png", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::components::Tab;
+    use crate::storage::images::put_image;
+    use crate::storage::memory::MemoryStore;
+
+    #[test]
+    fn backup_restores_into_empty_store() {
+        let mut store = MemoryStore::default();
+        let saved = Doc {
+            id: ReflectableUuid(Uuid::new_v4()),
+            name: "Saved".to_string(),
+            ..Default::default()
+        };
+        let open = Doc {
+            id: ReflectableUuid(Uuid::new_v4()),
+            name: "Open".to_string(),
+            ..Default::default()
+        };
+        store.save_doc(&saved).unwrap();
+        store.save_doc(&open).unwrap();
+        let hash = put_image(&mut store, b"png").unwrap();
+        let mut edited = open.clone();
+        edited.tags = vec!["unsaved".to_string()];
+        let archive = backup_store(&store, [&edited].into_iter()).unwrap();
+
+        let mut other = MemoryStore::default();
+        let summary = restore_store(&mut other, &archive, RestoreStrategy::Replace).unwrap();
+        assert_eq!(summary.added.len(), 2);
+        assert_eq!(summary.images, 1);
+        assert_eq!(other.doc_names(), store.doc_names());
+        assert_eq!(other.load_doc(open.id).unwrap().tags, vec!["unsaved"]);
+        assert_eq!(other.get_image(&hash), store.get_image(&hash));
+        assert!(other.load_settings().is_some());
+    }
+
+    #[test]
+    fn existing_docs_are_replaced_or_kept() {
+        let mut store = MemoryStore::default();
+        let original = Doc {
+            id: ReflectableUuid(Uuid::new_v4()),
+            name: "Plan".to_string(),
+            tabs: vec![Tab {
+                id: ReflectableUuid(Uuid::new_v4()),
+                name: "Tab 1".to_string(),
+                checkpoints: VecDeque::new(),
+                is_active: true,
+                view: Default::default(),
+            }],
+            tags: vec![],
+        };
+        store.save_doc(&original).unwrap();
+        let archive = backup_store(&store, std::iter::empty()).unwrap();
+        let mut renamed = original.clone();
+        renamed.name = "Plan v2".to_string();
+        store.save_doc(&renamed).unwrap();
+
+        let summary = restore_store(&mut store, &archive, RestoreStrategy::KeepBoth).unwrap();
+        assert!(summary.replaced.is_empty());
+        assert_eq!(summary.added[0].1, "Plan (restored)");
+        let copy = store.load_doc(summary.added[0].0).unwrap();
+        assert_ne!(copy.tabs[0].id, original.tabs[0].id);
+        assert_eq!(store.doc_names().len(), 2);
+        assert_eq!(store.load_doc(original.id).unwrap().name, "Plan v2");
+
+        let summary = restore_store(&mut store, &archive, RestoreStrategy::Replace).unwrap();
+        assert_eq!(summary.replaced, vec![(original.id, "Plan".to_string())]);
+        assert_eq!(store.load_doc(original.id).unwrap().name, "Plan");
+        assert_eq!(store.doc_names().len(), 2);
+    }
+
+    #[test]
+    fn image_names_must_be_their_hash() {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        let manifest = Manifest {
+            app: "velo".to_string(),
+            version: VERSION,
+        };
+        let files = [
+            (MANIFEST_FILE, serde_json::to_vec(&manifest).unwrap()),
+            (NAMES_FILE, b"{}".to_vec()),
+            (SETTINGS_FILE, b"{}".to_vec()),
+            ("images/../../escaped.png", b"png".to_vec()),
+        ];
+        for (name, content) in files {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(&content).unwrap();
+        }
+        let archive = zip.finish().unwrap().into_inner();
+
+        let mut store = MemoryStore::default();
+        let result = restore_store(&mut store, &archive, RestoreStrategy::Replace);
+        assert!(matches!(result, Err(VeloError::Import(_))));
+        assert!(store.image_hashes().is_empty());
+        assert!(store.load_settings().is_none());
+    }
+}
@@ -0,0 +1,247 @@
+//! In-memory cache of open documents.
+//!
+//! Keeps at most `capacity` documents, evicting the least recently used
+//! ones. Documents with changes that are not in storage yet are marked
+//! dirty and handed back on eviction so they can be flushed first.
+use std::collections::{hash_map::Entry, HashMap, HashSet, VecDeque};
+
+use super::StorageBackend;
+use crate::components::Doc;
+use crate::errors::VeloError;
+use crate::utils::ReflectableUuid;
+use crate::MAX_SAVED_DOCS_IN_MEMORY;
+
+pub struct DocCache {
+    capacity: usize,
+    docs: HashMap<ReflectableUuid, Doc>,
+    order: VecDeque<ReflectableUuid>, // Least recently used first
+    dirty: HashSet<ReflectableUuid>,
+}
+
+impl Default for DocCache {
+    fn default() -> Self {
+        Self::new(MAX_SAVED_DOCS_IN_MEMORY as usize)
+    }
+}
+
+impl DocCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            docs: HashMap::new(),
+            order: VecDeque::new(),
+            dirty: HashSet::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn contains_key(&self, id: &ReflectableUuid) -> bool {
+        self.docs.contains_key(id)
+    }
+
+    pub fn get(&self, id: &ReflectableUuid) -> Option<&Doc> {
+        self.docs.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &ReflectableUuid) -> Option<&mut Doc> {
+        self.docs.get_mut(id)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Doc> {
+        self.docs.values()
+    }
+
+    /// Inserts a doc as the most recently used one. Call `evict` afterwards
+    /// to bring the cache back to its capacity.
+    pub fn insert(&mut self, id: ReflectableUuid, doc: Doc) -> Option<Doc> {
+        self.touch(id);
+        self.docs.insert(id, doc)
+    }
+
+    /// Makes sure a doc is in memory, loading it from the store if needed,
+    /// and marks it as the most recently used one.
+    pub fn load(
+        &mut self,
+        store: &dyn StorageBackend,
+        id: ReflectableUuid,
+    ) -> Result<(), VeloError> {
+        if let Entry::Vacant(entry) = self.docs.entry(id) {
+            entry.insert(store.load_doc(id).ok_or(VeloError::DocNotFound(id))?);
+        }
+        self.touch(id);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: &ReflectableUuid) -> Option<Doc> {
+        self.order.retain(|x| x != id);
+        self.dirty.remove(id);
+        self.docs.remove(id)
+    }
+
+    /// Marks a doc as the most recently used one.
+    pub fn touch(&mut self, id: ReflectableUuid) {
+        self.order.retain(|x| *x != id);
+        self.order.push_back(id);
+    }
+
+    pub fn mark_dirty(&mut self, id: ReflectableUuid) {
+        if self.docs.contains_key(&id) {
+            self.dirty.insert(id);
+        }
+    }
+
+    pub fn mark_clean(&mut self, id: &ReflectableUuid) {
+        self.dirty.remove(id);
+    }
+
+    pub fn is_dirty(&self, id: &ReflectableUuid) -> bool {
+        self.dirty.contains(id)
+    }
+
+    pub fn dirty_docs(&self) -> impl Iterator<Item = &Doc> {
+        self.dirty.iter().filter_map(|id| self.docs.get(id))
+    }
+
+    /// Drops least recently used docs until the cache fits its capacity,
+    /// never dropping `keep`. Dirty docs are passed to `flush` first and stay
+    /// in the cache if it fails, the errors are returned.
+    pub fn evict(
+        &mut self,
+        keep: Option<ReflectableUuid>,
+        mut flush: impl FnMut(&Doc) -> Result<(), VeloError>,
+    ) -> Vec<VeloError> {
+        let mut errors = vec![];
+        let mut i = 0;
+        while self.docs.len() > self.capacity && i < self.order.len() {
+            let id = self.order[i];
+            if Some(id) == keep {
+                i += 1;
+                continue;
+            }
+            if self.dirty.contains(&id) {
+                if let Err(error) = flush(&self.docs[&id]) {
+                    errors.push(error);
+                    i += 1;
+                    continue;
+                }
+                self.dirty.remove(&id);
+            }
+            self.order.remove(i);
+            self.docs.remove(&id);
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn doc(name: &str) -> Doc {
+        Doc {
+            id: ReflectableUuid(Uuid::new_v4()),
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn fill(cache: &mut DocCache, count: usize) -> Vec<ReflectableUuid> {
+        let mut ids = vec![];
+        for i in 0..count {
+            let doc = doc(&format!("Doc {}", i));
+            ids.push(doc.id);
+            cache.insert(doc.id, doc);
+        }
+        ids
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = DocCache::default();
+        let ids = fill(&mut cache, 10);
+        let errors = cache.evict(None, |_| panic!("nothing to flush"));
+        assert!(errors.is_empty());
+        assert_eq!(cache.len(), MAX_SAVED_DOCS_IN_MEMORY as usize);
+        for id in &ids[..3] {
+            assert!(!cache.contains_key(id));
+        }
+        for id in &ids[3..] {
+            assert!(cache.contains_key(id));
+        }
+    }
+
+    #[test]
+    fn touched_doc_survives_eviction() {
+        let mut cache = DocCache::default();
+        let ids = fill(&mut cache, 7);
+        cache.touch(ids[0]);
+        fill(&mut cache, 2);
+        cache.evict(None, |_| Ok(()));
+        assert!(cache.contains_key(&ids[0]));
+        assert!(!cache.contains_key(&ids[1]));
+        assert!(!cache.contains_key(&ids[2]));
+        assert!(cache.contains_key(&ids[3]));
+    }
+
+    #[test]
+    fn dirty_docs_are_flushed_before_eviction() {
+        let mut cache = DocCache::default();
+        let ids = fill(&mut cache, 9);
+        cache.mark_dirty(ids[1]);
+        cache.mark_dirty(ids[5]);
+        let mut flushed = vec![];
+        cache.evict(None, |doc| {
+            flushed.push(doc.id);
+            Ok(())
+        });
+        assert_eq!(flushed, vec![ids[1]]);
+        assert!(!cache.contains_key(&ids[1]));
+        assert!(cache.is_dirty(&ids[5]));
+        assert!(!cache.is_dirty(&ids[1]));
+        assert_eq!(cache.dirty_docs().count(), 1);
+    }
+
+    #[test]
+    fn kept_doc_is_never_evicted() {
+        let mut cache = DocCache::default();
+        let ids = fill(&mut cache, 12);
+        cache.mark_dirty(ids[0]);
+        cache.evict(Some(ids[0]), |_| panic!("kept doc must not be flushed"));
+        assert!(cache.contains_key(&ids[0]));
+        assert!(cache.is_dirty(&ids[0]));
+        assert_eq!(cache.len(), MAX_SAVED_DOCS_IN_MEMORY as usize);
+        for id in &ids[1..6] {
+            assert!(!cache.contains_key(id));
+        }
+    }
+
+    #[test]
+    fn removed_doc_is_forgotten() {
+        let mut cache = DocCache::default();
+        let ids = fill(&mut cache, 8);
+        cache.mark_dirty(ids[0]);
+        cache.remove(&ids[0]);
+        cache.evict(None, |_| panic!("removed doc must not be flushed"));
+        assert_eq!(cache.len(), MAX_SAVED_DOCS_IN_MEMORY as usize);
+        assert!(cache.contains_key(&ids[1]));
+    }
+
+    #[test]
+    fn failed_flush_keeps_doc() {
+        let mut cache = DocCache::default();
+        let ids = fill(&mut cache, 9);
+        cache.mark_dirty(ids[0]);
+        let errors = cache.evict(None, |_| Err(VeloError::Storage("full".to_string())));
+        assert_eq!(errors.len(), 1);
+        assert!(cache.contains_key(&ids[0]));
+        assert!(cache.is_dirty(&ids[0]));
+        assert!(!cache.contains_key(&ids[1]));
+        assert!(!cache.contains_key(&ids[2]));
+        assert_eq!(cache.len(), MAX_SAVED_DOCS_IN_MEMORY as usize);
+    }
+}
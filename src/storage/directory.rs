@@ -0,0 +1,288 @@
+//! Directory backend, keeps everything as plain files so diagrams can be
+//! versioned next to code:
+//!
+//! ```text
+//! <root>/docs/<uuid>.json     one document with all its tabs
+//! <root>/images/<sha256>.png  images referenced by checkpoints
+//! <root>/settings.json
+//! <root>/last_saved.json      the doc that was open last
+//! ```
+//!
+//! Doc files are polled for changes made outside of the app, e.g. by `git pull`.
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use base64::{engine::general_purpose, Engine};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use uuid::Uuid;
+
+use super::{storage_error, DocChange, StorageBackend};
+use crate::components::Doc;
+use crate::errors::VeloError;
+use crate::resources::Settings;
+use crate::utils::ReflectableUuid;
+
+const DOCS_DIR: &str = "docs";
+const IMAGES_DIR: &str = "images";
+const SETTINGS_FILE: &str = "settings.json";
+const LAST_SAVED_FILE: &str = "last_saved.json";
+
+pub struct DirectoryStore {
+    root: PathBuf,
+    known: HashMap<ReflectableUuid, DocStamp>, // Every doc file we've seen
+}
+
+// Coarse file systems keep the modification time of quick successive
+// writes, the length tells most of those apart
+#[derive(PartialEq, Clone, Copy)]
+struct DocStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl DocStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+        })
+    }
+}
+
+// Reads only what the doc index needs, tabs are skipped
+#[derive(Deserialize)]
+struct DocHeader {
+    id: ReflectableUuid,
+    name: String,
+}
+
+impl DirectoryStore {
+    pub fn new(root: PathBuf) -> Self {
+        let mut store = Self {
+            root,
+            known: HashMap::new(),
+        };
+        store.known = store.doc_stamps();
+        store
+    }
+
+    fn doc_path(&self, id: ReflectableUuid) -> PathBuf {
+        self.root.join(DOCS_DIR).join(format!("{}.json", id.0))
+    }
+
+    fn image_path(&self, hash: &str) -> PathBuf {
+        self.root.join(IMAGES_DIR).join(format!("{}.png", hash))
+    }
+
+    fn read<T: DeserializeOwned>(path: &Path) -> Option<T> {
+        let json = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    fn write<T: Serialize>(path: &Path, value: &T) -> Result<(), VeloError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(storage_error)?;
+        }
+        // Pretty printed so changes show up as readable diffs
+        let json = serde_json::to_string_pretty(value)?;
+        // Write next to the target and rename, a crash never leaves half a file
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(storage_error)?;
+        fs::rename(&tmp, path).map_err(storage_error)
+    }
+
+    fn list(&self, dir: &str, extension: &str) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(self.root.join(dir)) else {
+            return vec![];
+        };
+        entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().and_then(|x| x.to_str()) == Some(extension))
+            .collect()
+    }
+
+    fn doc_stamps(&self) -> HashMap<ReflectableUuid, DocStamp> {
+        self.list(DOCS_DIR, "json")
+            .iter()
+            .filter_map(|path| {
+                let id = Uuid::parse_str(path.file_stem()?.to_str()?).ok()?;
+                Some((ReflectableUuid(id), DocStamp::of(path)?))
+            })
+            .collect()
+    }
+
+    // Our own writes are not changes made by someone else
+    fn remember(&mut self, id: ReflectableUuid) {
+        if let Some(stamp) = DocStamp::of(&self.doc_path(id)) {
+            self.known.insert(id, stamp);
+        }
+    }
+}
+
+impl StorageBackend for DirectoryStore {
+    fn doc_names(&self) -> HashMap<ReflectableUuid, String> {
+        self.list(DOCS_DIR, "json")
+            .iter()
+            .filter_map(|path| Self::read::<DocHeader>(path))
+            .map(|header| (header.id, header.name))
+            .collect()
+    }
+
+    fn load_doc(&self, id: ReflectableUuid) -> Option<Doc> {
+        Self::read(&self.doc_path(id))
+    }
+
+    fn save_doc(&mut self, doc: &Doc) -> Result<(), VeloError> {
+        Self::write(&self.doc_path(doc.id), doc)?;
+        self.remember(doc.id);
+        Ok(())
+    }
+
+    fn remove_doc(&mut self, id: ReflectableUuid) -> Result<(), VeloError> {
+        let path = self.doc_path(id);
+        if path.exists() {
+            fs::remove_file(path).map_err(storage_error)?;
+        }
+        self.known.remove(&id);
+        Ok(())
+    }
+
+    fn image_hashes(&self) -> HashSet<String> {
+        self.list(IMAGES_DIR, "png")
+            .iter()
+            .filter_map(|path| path.file_stem()?.to_str().map(|x| x.to_string()))
+            .collect()
+    }
+
+    fn get_image(&self, hash: &str) -> Option<String> {
+        let png = fs::read(self.image_path(hash)).ok()?;
+        Some(general_purpose::STANDARD.encode(png))
+    }
+
+    fn put_image(&mut self, hash: &str, png: &[u8]) -> Result<(), VeloError> {
+        let path = self.image_path(hash);
+        if path.exists() {
+            return Ok(());
+        }
+        fs::create_dir_all(self.root.join(IMAGES_DIR)).map_err(storage_error)?;
+        fs::write(path, png).map_err(storage_error)
+    }
+
+    fn remove_image(&mut self, hash: &str) -> Result<(), VeloError> {
+        let path = self.image_path(hash);
+        if path.exists() {
+            fs::remove_file(path).map_err(storage_error)?;
+        }
+        Ok(())
+    }
+
+    fn load_settings(&self) -> Option<Settings> {
+        Self::read(&self.root.join(SETTINGS_FILE))
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), VeloError> {
+        Self::write(&self.root.join(SETTINGS_FILE), settings)
+    }
+
+    fn last_saved(&self) -> Option<ReflectableUuid> {
+        Self::read(&self.root.join(LAST_SAVED_FILE))
+    }
+
+    fn set_last_saved(&mut self, id: ReflectableUuid) -> Result<(), VeloError> {
+        Self::write(&self.root.join(LAST_SAVED_FILE), &id)
+    }
+
+    fn poll_changes(&mut self) -> Vec<DocChange> {
+        let mut changes = vec![];
+        for (id, stamp) in self.doc_stamps() {
+            if self.known.get(&id) == Some(&stamp) {
+                continue;
+            }
+            // A file that is still being written is picked up on the next poll
+            let Some(header) = Self::read::<DocHeader>(&self.doc_path(id)) else {
+                continue;
+            };
+            self.known.insert(id, stamp);
+            changes.push(DocChange::Updated {
+                id,
+                name: header.name,
+            });
+        }
+        let removed: Vec<_> = self
+            .known
+            .keys()
+            .filter(|id| !self.doc_path(**id).exists())
+            .copied()
+            .collect();
+        for id in removed {
+            self.known.remove(&id);
+            changes.push(DocChange::Removed(id));
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn docs_round_trip_through_files() {
+        let root = std::env::temp_dir().join(format!("velo-{}", Uuid::new_v4()));
+        let mut store = DirectoryStore::new(root.clone());
+        let doc = Doc {
+            id: ReflectableUuid(Uuid::new_v4()),
+            name: "Diagram".to_string(),
+            ..Default::default()
+        };
+        store.save_doc(&doc).unwrap();
+        store.set_last_saved(doc.id).unwrap();
+        store.put_image("hash", b"png").unwrap();
+
+        let store = DirectoryStore::new(root.clone());
+        assert_eq!(store.doc_names().get(&doc.id), Some(&doc.name));
+        assert_eq!(store.load_doc(doc.id).unwrap().name, doc.name);
+        assert_eq!(store.last_saved(), Some(doc.id));
+        assert!(store.image_hashes().contains("hash"));
+
+        let mut store = store;
+        store.remove_doc(doc.id).unwrap();
+        assert!(store.doc_names().is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn external_edits_are_reported() {
+        let root = std::env::temp_dir().join(format!("velo-{}", Uuid::new_v4()));
+        let mut store = DirectoryStore::new(root.clone());
+        let mut doc = Doc {
+            id: ReflectableUuid(Uuid::new_v4()),
+            name: "Diagram".to_string(),
+            ..Default::default()
+        };
+        store.save_doc(&doc).unwrap();
+        assert!(store.poll_changes().is_empty());
+
+        let mut other = DirectoryStore::new(root.clone());
+        // Written right away, the modification time may not move
+        doc.name = "Renamed diagram".to_string();
+        other.save_doc(&doc).unwrap();
+        assert_eq!(
+            store.poll_changes(),
+            vec![DocChange::Updated {
+                id: doc.id,
+                name: "Renamed diagram".to_string()
+            }]
+        );
+
+        other.remove_doc(doc.id).unwrap();
+        assert_eq!(store.poll_changes(), vec![DocChange::Removed(doc.id)]);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+//! Content-addressed image blobs.
+//!
+//! Every image is stored once under the SHA-256 of its PNG data and
+//! checkpoints only keep the hash, so blobs no checkpoint refers to can be
+//! garbage collected.
+use std::collections::HashSet;
+
+use sha2::{Digest, Sha256};
+
+use super::{stored_docs, StorageBackend};
+use crate::components::Doc;
+use crate::errors::VeloError;
+use crate::formats::velo::parse_checkpoint;
+
+pub fn image_hash(png: &[u8]) -> String {
+    Sha256::digest(png)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Stores the PNG if it is not stored yet and returns its hash.
+pub fn put_image(store: &mut dyn StorageBackend, png: &[u8]) -> Result<String, VeloError> {
+    let hash = image_hash(png);
+    store.put_image(&hash, png)?;
+    Ok(hash)
+}
+
+/// Removes every image that is not referenced by a checkpoint of a stored
+/// document or of one of the `open_docs`.
+pub fn collect_image_garbage<'a>(
+    store: &mut dyn StorageBackend,
+    open_docs: impl Iterator<Item = &'a Doc>,
+) {
+    let index = store.image_hashes();
+    if index.is_empty() {
+        return;
+    }
+    let mut referenced = HashSet::new();
+    for doc in open_docs {
+        if !add_references(doc, &mut referenced) {
+            return;
+        }
+    }
+    for doc in stored_docs(store) {
+        if !add_references(&doc, &mut referenced) {
+            return;
+        }
+    }
+    for hash in index.difference(&referenced) {
+        // A blob that can't be removed now is collected next time
+        let _ = store.remove_image(hash);
+    }
+}
+
+fn add_references(doc: &Doc, referenced: &mut HashSet<String>) -> bool {
+    for tab in doc.tabs.iter() {
+        for json in tab.checkpoints.iter() {
+            // Don't guess what an unreadable checkpoint refers to
+            let Ok(checkpoint) = parse_checkpoint(json) else {
+                return false;
+            };
+            referenced.extend(checkpoint.images.into_values());
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, VecDeque};
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::components::Tab;
+    use crate::formats::velo::{checkpoint_to_string, Checkpoint};
+    use crate::storage::memory::MemoryStore;
+    use crate::utils::ReflectableUuid;
+
+    fn doc_with_image(hash: &str) -> Doc {
+        let checkpoint = Checkpoint {
+            images: HashMap::from([(Uuid::new_v4().to_string(), hash.to_string())]),
+            ..Default::default()
+        };
+        Doc {
+            id: ReflectableUuid(Uuid::new_v4()),
+            tabs: vec![Tab {
+                is_active: true,
+                id: ReflectableUuid(Uuid::new_v4()),
+                name: "Tab 1".to_string(),
+                checkpoints: VecDeque::from([checkpoint_to_string(&checkpoint).unwrap()]),
+                view: Default::default(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unreferenced_images_are_collected() {
+        let mut store = MemoryStore::default();
+        let kept = put_image(&mut store, b"kept").unwrap();
+        let open = put_image(&mut store, b"open").unwrap();
+        let garbage = put_image(&mut store, b"garbage").unwrap();
+        store.save_doc(&doc_with_image(&kept)).unwrap();
+        let open_doc = doc_with_image(&open);
+        collect_image_garbage(&mut store, [&open_doc].into_iter());
+        assert!(store.get_image(&kept).is_some());
+        assert!(store.get_image(&open).is_some());
+        assert!(store.get_image(&garbage).is_none());
+    }
+
+    #[test]
+    fn same_image_is_stored_once() {
+        let mut store = MemoryStore::default();
+        let first = put_image(&mut store, b"png").unwrap();
+        let second = put_image(&mut store, b"png").unwrap();
+        assert_eq!(first, second);
+        assert_eq!(store.image_hashes().len(), 1);
+    }
+}
@@ -0,0 +1,79 @@
+//! In-memory backend, nothing outlives the app. Used by tests.
+use std::collections::{HashMap, HashSet};
+
+use base64::{engine::general_purpose, Engine};
+
+use super::StorageBackend;
+use crate::components::Doc;
+use crate::errors::VeloError;
+use crate::resources::Settings;
+use crate::utils::ReflectableUuid;
+
+#[derive(Default)]
+pub struct MemoryStore {
+    docs: HashMap<ReflectableUuid, Doc>,
+    images: HashMap<String, String>, // Hash to base64 encoded PNG
+    settings: Option<Settings>,
+    last_saved: Option<ReflectableUuid>,
+}
+
+impl StorageBackend for MemoryStore {
+    fn doc_names(&self) -> HashMap<ReflectableUuid, String> {
+        self.docs
+            .values()
+            .map(|doc| (doc.id, doc.name.clone()))
+            .collect()
+    }
+
+    fn load_doc(&self, id: ReflectableUuid) -> Option<Doc> {
+        self.docs.get(&id).cloned()
+    }
+
+    fn save_doc(&mut self, doc: &Doc) -> Result<(), VeloError> {
+        self.docs.insert(doc.id, doc.clone());
+        Ok(())
+    }
+
+    fn remove_doc(&mut self, id: ReflectableUuid) -> Result<(), VeloError> {
+        self.docs.remove(&id);
+        Ok(())
+    }
+
+    fn image_hashes(&self) -> HashSet<String> {
+        self.images.keys().cloned().collect()
+    }
+
+    fn get_image(&self, hash: &str) -> Option<String> {
+        self.images.get(hash).cloned()
+    }
+
+    fn put_image(&mut self, hash: &str, png: &[u8]) -> Result<(), VeloError> {
+        self.images
+            .entry(hash.to_string())
+            .or_insert_with(|| general_purpose::STANDARD.encode(png));
+        Ok(())
+    }
+
+    fn remove_image(&mut self, hash: &str) -> Result<(), VeloError> {
+        self.images.remove(hash);
+        Ok(())
+    }
+
+    fn load_settings(&self) -> Option<Settings> {
+        self.settings.clone()
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), VeloError> {
+        self.settings = Some(settings.clone());
+        Ok(())
+    }
+
+    fn last_saved(&self) -> Option<ReflectableUuid> {
+        self.last_saved
+    }
+
+    fn set_last_saved(&mut self, id: ReflectableUuid) -> Result<(), VeloError> {
+        self.last_saved = Some(id);
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+//! Persistence of documents, images and settings.
+//!
+//! Systems only talk to the `Store` resource, the backend behind it is picked
+//! when `VeloPlugin` is built.
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::components::Doc;
+use crate::errors::VeloError;
+use crate::resources::Settings;
+use crate::utils::ReflectableUuid;
+
+pub mod backup;
+pub mod cache;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod directory;
+pub mod images;
+pub mod memory;
+pub mod pkv;
+
+pub trait StorageBackend: Send + Sync {
+    /// Doc id to name of every stored doc.
+    fn doc_names(&self) -> HashMap<ReflectableUuid, String>;
+    fn load_doc(&self, id: ReflectableUuid) -> Option<Doc>;
+    fn save_doc(&mut self, doc: &Doc) -> Result<(), VeloError>;
+    fn remove_doc(&mut self, id: ReflectableUuid) -> Result<(), VeloError>;
+    /// Hashes of every stored image.
+    fn image_hashes(&self) -> HashSet<String>;
+    /// Returns the base64 encoded PNG stored under `hash`.
+    fn get_image(&self, hash: &str) -> Option<String>;
+    /// Stores the PNG under `hash` unless it is stored already.
+    fn put_image(&mut self, hash: &str, png: &[u8]) -> Result<(), VeloError>;
+    fn remove_image(&mut self, hash: &str) -> Result<(), VeloError>;
+    fn load_settings(&self) -> Option<Settings>;
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), VeloError>;
+    /// The doc that was open when the app was closed.
+    fn last_saved(&self) -> Option<ReflectableUuid>;
+    fn set_last_saved(&mut self, id: ReflectableUuid) -> Result<(), VeloError>;
+    /// Docs changed by someone else since the last call. Only backends that
+    /// can be edited outside of the app report anything.
+    fn poll_changes(&mut self) -> Vec<DocChange> {
+        vec![]
+    }
+    /// Upgrades data saved by older versions, called once after opening.
+    fn migrate(&mut self) -> Result<(), VeloError> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DocChange {
+    /// A doc was added or its content changed.
+    Updated {
+        id: ReflectableUuid,
+        name: String,
+    },
+    Removed(ReflectableUuid),
+}
+
+#[derive(Default, Clone, Debug)]
+pub enum StorageKind {
+    /// Key-value store of the platform, rocksdb on desktop and local storage on the web.
+    #[default]
+    Pkv,
+    /// Plain JSON files in a directory, see `DirectoryStore`.
+    #[cfg(not(target_arch = "wasm32"))]
+    Directory(PathBuf),
+    /// Nothing is persisted, for tests.
+    Memory,
+}
+
+#[derive(Resource)]
+pub struct Store(pub Box<dyn StorageBackend>);
+
+impl Deref for Store {
+    type Target = dyn StorageBackend;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+impl DerefMut for Store {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut *self.0
+    }
+}
+
+impl Store {
+    pub fn new(kind: &StorageKind) -> Result<Self, VeloError> {
+        let store = match kind {
+            StorageKind::Pkv => Store(Box::new(pkv::open_pkv_store()?)),
+            #[cfg(not(target_arch = "wasm32"))]
+            StorageKind::Directory(root) => {
+                Store(Box::new(directory::DirectoryStore::new(root.clone())))
+            }
+            StorageKind::Memory => Store(Box::<memory::MemoryStore>::default()),
+        };
+        Ok(store)
+    }
+}
+
+/// Startup system that runs `StorageBackend::migrate` before anything is
+/// loaded.
+pub fn migrate_store(mut store: ResMut<Store>) -> Result<(), VeloError> {
+    store.migrate()
+}
+
+/// Iterates over every stored document, loading them one at a time.
+pub fn stored_docs(store: &dyn StorageBackend) -> impl Iterator<Item = Doc> + '_ {
+    store
+        .doc_names()
+        .into_keys()
+        .filter_map(|id| store.load_doc(id))
+}
+
+pub fn storage_error(error: impl ToString) -> VeloError {
+    VeloError::Storage(error.to_string())
+}
@@ -0,0 +1,155 @@
+//! `PkvStore` backend.
+//!
+//! Every document lives under its own `doc:<uuid>` key, `"names"` is the
+//! index of stored documents and `"tags"` keeps their tags, so saving one
+//! document never rewrites the others. Images are stored as base64 under
+//! `image:<sha256>` and listed in `"image_index"`.
+use std::collections::{HashMap, HashSet};
+
+use base64::{engine::general_purpose, Engine};
+use bevy_pkv::PkvStore;
+
+use super::{storage_error, StorageBackend};
+use crate::components::Doc;
+use crate::errors::VeloError;
+use crate::resources::Settings;
+use crate::utils::ReflectableUuid;
+
+const LEGACY_DOCS_KEY: &str = "docs";
+const NAMES_KEY: &str = "names";
+const TAGS_KEY: &str = "tags";
+const IMAGE_INDEX_KEY: &str = "image_index";
+const SETTINGS_KEY: &str = "settings";
+const LAST_SAVED_KEY: &str = "last_saved";
+
+fn doc_key(id: ReflectableUuid) -> String {
+    format!("doc:{}", id.0)
+}
+
+fn image_key(hash: &str) -> String {
+    format!("image:{}", hash)
+}
+
+/// Opens the app database. Only one process can open it at a time and
+/// `PkvStore::new` panics when it's locked, e.g. by the running app.
+pub fn open_pkv_store() -> Result<PkvStore, VeloError> {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+    let pkv = std::panic::catch_unwind(|| PkvStore::new("", "velo"));
+    std::panic::set_hook(hook);
+    pkv.map_err(|_| VeloError::Storage("the database is in use, close Velo first".to_string()))
+}
+
+impl StorageBackend for PkvStore {
+    fn doc_names(&self) -> HashMap<ReflectableUuid, String> {
+        self.get::<HashMap<ReflectableUuid, String>>(NAMES_KEY)
+            .unwrap_or_default()
+    }
+
+    fn load_doc(&self, id: ReflectableUuid) -> Option<Doc> {
+        self.get::<Doc>(&doc_key(id)).ok()
+    }
+
+    fn save_doc(&mut self, doc: &Doc) -> Result<(), VeloError> {
+        self.set(&doc_key(doc.id), doc).map_err(storage_error)?;
+        let mut names = self.doc_names();
+        if names.get(&doc.id) != Some(&doc.name) {
+            names.insert(doc.id, doc.name.clone());
+            self.set(NAMES_KEY, &names).map_err(storage_error)?;
+        }
+        let mut tags = self
+            .get::<HashMap<ReflectableUuid, Vec<String>>>(TAGS_KEY)
+            .unwrap_or_default();
+        if tags.get(&doc.id) != Some(&doc.tags) {
+            tags.insert(doc.id, doc.tags.clone());
+            self.set(TAGS_KEY, &tags).map_err(storage_error)?;
+        }
+        Ok(())
+    }
+
+    fn remove_doc(&mut self, id: ReflectableUuid) -> Result<(), VeloError> {
+        let mut names = self.doc_names();
+        if names.remove(&id).is_some() {
+            self.set(NAMES_KEY, &names).map_err(storage_error)?;
+        }
+        if let Ok(mut tags) = self.get::<HashMap<ReflectableUuid, Vec<String>>>(TAGS_KEY) {
+            if tags.remove(&id).is_some() {
+                self.set(TAGS_KEY, &tags).map_err(storage_error)?;
+            }
+        }
+        // PkvStore can't delete keys, the document is dropped from the index
+        // and its body is replaced with an empty document
+        self.set(&doc_key(id), &Doc::default())
+            .map_err(storage_error)
+    }
+
+    fn image_hashes(&self) -> HashSet<String> {
+        self.get::<HashSet<String>>(IMAGE_INDEX_KEY)
+            .unwrap_or_default()
+    }
+
+    fn get_image(&self, hash: &str) -> Option<String> {
+        self.get::<String>(&image_key(hash))
+            .ok()
+            .filter(|image| !image.is_empty())
+    }
+
+    fn put_image(&mut self, hash: &str, png: &[u8]) -> Result<(), VeloError> {
+        let mut index = self.image_hashes();
+        if !index.contains(hash) {
+            self.set_string(&image_key(hash), &general_purpose::STANDARD.encode(png))
+                .map_err(storage_error)?;
+            index.insert(hash.to_string());
+            self.set(IMAGE_INDEX_KEY, &index).map_err(storage_error)?;
+        }
+        Ok(())
+    }
+
+    fn remove_image(&mut self, hash: &str) -> Result<(), VeloError> {
+        // PkvStore can't delete keys, an empty blob is treated as missing
+        self.set_string(&image_key(hash), "")
+            .map_err(storage_error)?;
+        let mut index = self.image_hashes();
+        if index.remove(hash) {
+            self.set(IMAGE_INDEX_KEY, &index).map_err(storage_error)?;
+        }
+        Ok(())
+    }
+
+    fn load_settings(&self) -> Option<Settings> {
+        self.get::<Settings>(SETTINGS_KEY).ok()
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), VeloError> {
+        self.set(SETTINGS_KEY, settings).map_err(storage_error)
+    }
+
+    fn last_saved(&self) -> Option<ReflectableUuid> {
+        self.get::<ReflectableUuid>(LAST_SAVED_KEY).ok()
+    }
+
+    fn set_last_saved(&mut self, id: ReflectableUuid) -> Result<(), VeloError> {
+        self.set(LAST_SAVED_KEY, &id).map_err(storage_error)
+    }
+
+    fn migrate(&mut self) -> Result<(), VeloError> {
+        migrate_docs_blob(self)
+    }
+}
+
+/// Moves documents saved by older versions in the single `"docs"` map to
+/// their own keys. Runs once, the emptied map marks the migration as done.
+fn migrate_docs_blob(pkv: &mut PkvStore) -> Result<(), VeloError> {
+    let Ok(docs) = pkv.get::<HashMap<ReflectableUuid, Doc>>(LEGACY_DOCS_KEY) else {
+        return Ok(());
+    };
+    if docs.is_empty() {
+        return Ok(());
+    }
+    // Keep the legacy map around if anything fails, the next start retries
+    for doc in docs.values() {
+        pkv.save_doc(doc)?;
+    }
+    pkv.set(LEGACY_DOCS_KEY, &HashMap::<ReflectableUuid, Doc>::new())
+        .map_err(storage_error)
+}
@@ -1,11 +1,28 @@
-use crate::components::MainCamera;
+use crate::components::{Grid, MainCamera};
 use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::{Fill, ShapeBundle, Stroke};
+
+// The 2D camera sees down to -0.1, arrows are drawn at 0
+const BACKGROUND_Z: f32 = -0.09;
+pub const GRID_Z: f32 = -0.05;
+
 pub fn setup_background(mut commands: Commands, asset_server: Res<AssetServer>) {
     let background_image = asset_server.load("bg.png");
     commands.spawn(SpriteBundle {
         texture: background_image,
+        transform: Transform::from_xyz(0., 0., BACKGROUND_Z),
         ..Default::default()
     });
+    // The path is built by `draw_grid` once the panel has a size
+    commands.spawn((
+        ShapeBundle {
+            transform: Transform::from_xyz(0., 0., GRID_Z),
+            ..default()
+        },
+        Fill::color(Color::NONE),
+        Stroke::new(Color::NONE, 1.),
+        Grid,
+    ));
 }
 pub fn setup_camera(mut commands: Commands) {
     commands.spawn((Camera2dBundle::default(), MainCamera));